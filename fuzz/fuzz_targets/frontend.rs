@@ -0,0 +1,28 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! 用 cargo fuzz run frontend 运行。语料可以从 tests/fuzz.rs 中的 CORPUS 开始。
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Err(message) = xenon::check_no_panic(&String::from_utf8_lossy(data)) {
+        panic!("{}", message);
+    }
+});
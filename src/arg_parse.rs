@@ -17,23 +17,383 @@
 
 use std::env::Args;
 
-pub enum Mode {
-    Koopa,
-    RiscV,
-    Optimization,
-}
-
-pub type ParsedArgs = (Mode, String, String);
-
-pub fn parse(mut args: Args) -> Result<ParsedArgs, String> {
-    let args = &mut args;
-    let mode = match args.skip(1).next().unwrap().as_str() {
-        "-koopa" => Ok(Mode::Koopa),
-        "-riscv" => Ok(Mode::RiscV),
-        "-perf" => Ok(Mode::Optimization),
-        s => Err(format!("未知的模式: {}", s)),
-    }?;
-    let input = args.next().unwrap();
-    let output = args.skip(1).next().unwrap();
-    Ok((mode, input, output))
+pub const USAGE: &str = "用法: xenon [选项] <输入文件>... [-o <输出文件>]
+       xenon symbols <输入文件>...
+       xenon fmt <输入文件>...
+       xenon doc <输入文件>... [-o <输出文件>]
+       xenon --emit-runtime[=c|asm] [--target=<目标>] [-o <输出文件>]
+       xenon --explain <编号>
+       xenon test <用例目录> [--runner <命令>] [--timeout <秒>] [-j <线程数>]
+       xenon cov-report <计数文件> <输入文件>...
+
+输入文件为 - 时从标准输入读取源代码。多个输入文件共享同一个全局作用域，输出合并为一个文件。
+
+xenon doc 输出 HTML 文档，列出每个函数的签名和紧挨在定义之前的 /// 注释。
+
+选项:
+    -o <文件>          输出到指定文件，缺省时输出到标准输出
+    --emit-tokens, --dump-tokens
+                       只运行词法分析，逐行输出词法单元的位置、种类和文本
+    --emit-ast         以 JSON 格式输出检查后的语法树
+    --emit-ir          输出 Koopa IR（默认）
+    --stats            输出每个函数的度量（参数、结点、语句、嵌套层数、圈复杂度、调用、局部数组字节、IR 指令数）
+                       与全局定义的数量，--stats-json 以 JSON 格式输出
+    --report-dead      报告从 main 不可达的函数与全局定义（注明使用它们的不可达代码）以及条件为常量而
+                       永远不会执行的语句，不修改程序，--report-dead-json 以 JSON 格式输出
+    --run              解释执行程序，运行时库函数读写标准输入输出，退出码为 main 的返回值
+    --format           输出格式化后的源代码，注释放在最近的语句或定义旁
+    --verify-incremental
+                       调试增量检查: 逐行输入源代码，每次编辑后与完整检查比较，输出最终的诊断信息
+    --coverage         在每条语句前插入计数器，与 --emit-ir 或 --run 一起使用；
+                       同时在第一个输入文件旁生成映射文件 名字.covmap，程序结束时把计数输出到标准错误
+    --profile-generate[=blocks]
+                       在每个函数的入口和每个循环体的开头插入计数器（blocks 时还有每个基本块的开头），
+                       用法同 --coverage，映射文件为 名字.profmap，程序结束时每个计数器输出一行
+                       xenon-profile: <编号> <计数>
+    --check-bounds     在每次取数组下标之前检查下标是否越界，越界时输出行号、下标与长度后以退出码 1 结束；
+                       指针形参的第一维与不越界的常量下标不检查。与 --emit-ir 或 --run 一起使用
+    --sanitize=overflow
+                       在 + - * /（包括复合赋值与自增自减）有符号整数溢出时输出行号、运算与操作数后以退出码
+                       1 结束；常量运算仍在编译期求值。与 --emit-ir 或 --run 一起使用
+    --emit-runtime[=c] 不读取输入文件，输出运行时库 libsysy 的可移植 C 实现
+    --target=<目标>    目标平台，目前仅支持 riscv32
+    --explain <编号>   显示错误或警告编号（如 W001）的详细说明
+    --trace=<类别>[,<类别>]
+                       向标准错误输出检查过程的跟踪: const-eval 为常量求值的每个子表达式及其结果，
+                       checker 为标识符解析到的作用域和产生每条诊断信息的规则
+    -h, --help         显示本帮助
+    -V, --version      显示版本号
+
+xenon cov-report 读取 --coverage 程序输出到标准错误的计数（多次运行的计数相加）与输入文件旁的映射文件，
+逐行输出执行次数，从未执行的行标记为 #####，最后输出语句与行的覆盖率。
+
+xenon test 运行目录中的全部评测用例（名字.sy、名字.in、名字.out），将标准输出和退出码与 .out 比较:
+    --runner <命令>    编译为 Koopa IR 后调用该命令执行，IR 文件的路径作为最后一个参数，缺省时解释执行
+    --timeout <秒>     每个用例的时间限制，默认 10 秒
+    -j <线程数>        并行运行的用例数，默认为处理器数
+
+退出码: 0 成功，1 源代码有错误，2 用法或输入输出错误，101 编译器内部错误；--run 时为 main 的返回值的低 8 位。
+
+汇编后端尚未实现，--emit-asm、--emit-runtime=asm、-O1、-O2 与 -g 报错；-O0 被接受，不起作用。
+
+兼容旧的调用方式: xenon -koopa <输入文件> -o <输出文件>
+";
+
+#[derive(Clone, Copy)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    Ir,
+    Symbols,
+    Doc,
+    Stats,
+    StatsJson,
+    ReportDead,
+    ReportDeadJson,
+    Format,
+    Run,
+    VerifyIncremental,
+    Runtime(RuntimeFormat),
+}
+
+/// --target=<目标> 指定的目标平台。
+#[derive(Clone, Copy, Default)]
+pub enum Target {
+    #[default]
+    Riscv32,
+}
+
+/// --emit-runtime 输出的运行时库形式。
+#[derive(Clone, Copy)]
+pub enum RuntimeFormat {
+    C,
+    Asm,
+}
+
+/// 插桩方式。插桩的程序在 main 返回之前把计数输出到标准错误，计数器与源代码的对应关系写到映射文件。
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Instrument {
+    #[default]
+    None,
+    /// --coverage: 每条语句一个计数器
+    Coverage,
+    /// --profile-generate: 每个函数的入口与每个循环体一个计数器
+    Profile,
+    /// --profile-generate=blocks: 另外在每个基本块的开头
+    ProfileBlocks,
+}
+
+impl Instrument {
+    /// 映射文件的扩展名。
+    pub fn map_extension(&self) -> Option<&'static str> {
+        match self {
+            Instrument::None => None,
+            Instrument::Coverage => Some("covmap"),
+            Instrument::Profile | Instrument::ProfileBlocks => Some("profmap"),
+        }
+    }
+}
+
+/// --trace=<类别> 打开的跟踪输出。
+#[derive(Clone, Copy, Default)]
+pub struct Trace {
+    pub const_eval: bool,
+    pub checker: bool,
+}
+
+impl Trace {
+    pub fn is_enabled(&self) -> bool {
+        self.const_eval || self.checker
+    }
+}
+
+pub struct Options {
+    pub emit: Emit,
+    pub inputs: Vec<String>,
+    pub output: Option<String>,
+    pub target: Target,
+    pub trace: Trace,
+    pub instrument: Instrument,
+    /// --check-bounds: 运行时检查数组下标
+    pub check_bounds: bool,
+    /// --sanitize=overflow: 运行时检查有符号整数溢出
+    pub sanitize_overflow: bool,
+}
+
+/// 不带任何选项时的编译选项：输出 Koopa IR，目标平台为 riscv32。
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            emit: Emit::Ir,
+            inputs: Vec::new(),
+            output: None,
+            target: Target::default(),
+            trace: Trace::default(),
+            instrument: Instrument::None,
+            check_bounds: false,
+            sanitize_overflow: false,
+        }
+    }
+}
+
+pub struct TestOptions {
+    pub dir: String,
+    pub runner: Option<Vec<String>>,
+    pub timeout: u64,
+    pub jobs: usize,
+}
+
+pub enum Command {
+    Compile(Options),
+    Test(TestOptions),
+    /// xenon cov-report: 计数文件与编译时的输入文件
+    CoverageReport {
+        counts: String,
+        inputs: Vec<String>,
+    },
+    Explain(String),
+    /// 隐藏选项 --force-internal-error，用于测试驱动程序对内部错误的处理
+    ForceInternalError,
+    Help,
+    Version,
+}
+
+fn set_emit(emit: &mut Option<(String, Emit)>, flag: &str, value: Emit) -> Result<(), String> {
+    match emit {
+        Some((previous, _)) if previous != flag => Err(format!("选项 {} 与 {} 冲突", flag, previous)),
+        _ => {
+            *emit = Some((flag.to_string(), value));
+            Ok(())
+        }
+    }
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("选项 {} 缺少参数", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("选项 {} 的参数 {} 不是合法的数字", flag, value))
+}
+
+fn parse_test(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.peekable();
+    let mut dir = None;
+    let mut runner = None;
+    let mut timeout = 10;
+    let mut jobs = std::thread::available_parallelism().map_or(1, usize::from);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--runner" => match args
+                .next()
+                .map(|command| command.split_whitespace().map(String::from).collect::<Vec<_>>())
+            {
+                Some(command) if !command.is_empty() => runner = Some(command),
+                _ => return Err("选项 --runner 缺少命令".to_string()),
+            },
+            "--timeout" => timeout = number(&arg, args.next())?,
+            "-j" => jobs = number(&arg, args.next())?,
+            _ if arg.starts_with('-') => return Err(format!("未知的选项: {}", arg)),
+            _ if dir.is_some() => return Err("只能指定一个用例目录".to_string()),
+            _ => dir = Some(arg),
+        }
+    }
+    let dir = dir.ok_or("缺少用例目录")?;
+    Ok(Command::Test(TestOptions {
+        dir,
+        runner,
+        timeout,
+        jobs,
+    }))
+}
+
+fn parse_coverage_report(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut files = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("未知的选项: {}", arg)),
+            _ => files.push(arg),
+        }
+    }
+    if files.len() < 2 {
+        return Err("xenon cov-report 需要计数文件和至少一个输入文件".to_string());
+    }
+    let counts = files.remove(0);
+    Ok(Command::CoverageReport { counts, inputs: files })
+}
+
+/// 插桩的映射文件的路径: 第一个输入文件换成 `extension` 扩展名，从标准输入读取时为 stdin.<extension>。
+pub fn map_path(input: &str, extension: &str) -> std::path::PathBuf {
+    match input {
+        "-" => std::path::Path::new("stdin").with_extension(extension),
+        _ => std::path::Path::new(input).with_extension(extension),
+    }
+}
+
+pub fn parse(args: Args) -> Result<Command, String> {
+    let mut args = args.skip(1).peekable();
+    match args.peek().map(String::as_str) {
+        Some("test") => {
+            args.next();
+            return parse_test(args);
+        }
+        Some("cov-report") => {
+            args.next();
+            return parse_coverage_report(args);
+        }
+        _ => (),
+    }
+    let mut emit = None;
+    let mut inputs = Vec::new();
+    let mut output = None;
+    let mut target = Target::default();
+    let mut trace = Trace::default();
+    let mut instrument: Option<(String, Instrument)> = None;
+    let mut check_bounds = false;
+    let mut sanitize_overflow = false;
+    match args.peek().map(String::as_str) {
+        Some("symbols") => set_emit(&mut emit, "symbols", Emit::Symbols)?,
+        Some("fmt") => set_emit(&mut emit, "fmt", Emit::Format)?,
+        Some("doc") => set_emit(&mut emit, "doc", Emit::Doc)?,
+        _ => (),
+    }
+    if emit.is_some() {
+        args.next();
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--force-internal-error" => return Ok(Command::ForceInternalError),
+            _ if arg.starts_with("--trace=") => {
+                for category in arg["--trace=".len()..].split(',') {
+                    match category {
+                        "const-eval" => trace.const_eval = true,
+                        "checker" => trace.checker = true,
+                        _ => return Err(format!("未知的跟踪类别 {}，可用的类别: const-eval、checker", category)),
+                    }
+                }
+            }
+            "--explain" => return args.next().map(Command::Explain).ok_or("选项 --explain 缺少编号".to_string()),
+            "-o" => match (args.next(), &output) {
+                (None, _) => return Err("选项 -o 缺少输出文件".to_string()),
+                (Some(_), Some(_)) => return Err("只能指定一个输出文件".to_string()),
+                (Some(file), None) => output = Some(file),
+            },
+            "--emit-tokens" | "--dump-tokens" => set_emit(&mut emit, &arg, Emit::Tokens)?,
+            "--emit-ast" => set_emit(&mut emit, &arg, Emit::Ast)?,
+            "--run" => set_emit(&mut emit, &arg, Emit::Run)?,
+            "--format" => set_emit(&mut emit, &arg, Emit::Format)?,
+            "--verify-incremental" => set_emit(&mut emit, &arg, Emit::VerifyIncremental)?,
+            "--stats" => set_emit(&mut emit, &arg, Emit::Stats)?,
+            "--stats-json" => set_emit(&mut emit, &arg, Emit::StatsJson)?,
+            "--report-dead" => set_emit(&mut emit, &arg, Emit::ReportDead)?,
+            "--report-dead-json" => set_emit(&mut emit, &arg, Emit::ReportDeadJson)?,
+            "--emit-ir" | "-koopa" => set_emit(&mut emit, &arg, Emit::Ir)?,
+            "--emit-runtime" | "--emit-runtime=c" => set_emit(&mut emit, &arg, Emit::Runtime(RuntimeFormat::C))?,
+            // 汇编、优化与调试信息都需要后端，在此之前明确报错，而不是默默输出 Koopa IR；
+            // 汇编版本的运行时库只供生成的汇编链接，还没有与 C 版本对照的行为测试
+            "--emit-asm" | "--emit-runtime=asm" | "-riscv" | "-perf" | "-O1" | "-O2" | "-g" => {
+                return Err(format!("选项 {} 需要汇编后端，后端尚未实现", arg))
+            }
+            "-O0" => (),
+            "--check-bounds" => check_bounds = true,
+            _ if arg.starts_with("--sanitize=") => {
+                for sanitizer in arg["--sanitize=".len()..].split(',') {
+                    match sanitizer {
+                        "overflow" => sanitize_overflow = true,
+                        _ => return Err(format!("未知的检查 {}，可用的检查: overflow", sanitizer)),
+                    }
+                }
+            }
+            "--coverage" | "--profile-generate" | "--profile-generate=blocks" => {
+                let value = match arg.as_str() {
+                    "--coverage" => Instrument::Coverage,
+                    "--profile-generate" => Instrument::Profile,
+                    _ => Instrument::ProfileBlocks,
+                };
+                match &instrument {
+                    Some((previous, _)) if previous != &arg => return Err(format!("选项 {} 与 {} 冲突", arg, previous)),
+                    _ => instrument = Some((arg, value)),
+                }
+            }
+            _ if arg.starts_with("--target=") => {
+                target = match &arg["--target=".len()..] {
+                    "riscv32" => Target::Riscv32,
+                    name => return Err(format!("不支持的目标平台: {}", name)),
+                };
+            }
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("未知的选项: {}", arg)),
+            "-" if inputs.iter().any(|input| input == "-") => return Err("标准输入只能作为一个输入文件".to_string()),
+            _ => inputs.push(arg),
+        }
+    }
+    match (&emit, inputs.is_empty()) {
+        (Some((flag, Emit::Runtime(_))), false) => return Err(format!("选项 {} 不接受输入文件", flag)),
+        (Some((_, Emit::Runtime(_))), true) => (),
+        (_, true) => return Err("缺少输入文件".to_string()),
+        (_, false) => (),
+    }
+    let instrument_flag = instrument.as_ref().map(|(flag, _)| flag.as_str());
+    if let (Some(instrument), Some((flag, emit))) = (
+        instrument_flag
+            .or(check_bounds.then_some("--check-bounds"))
+            .or(sanitize_overflow.then_some("--sanitize=overflow")),
+        &emit,
+    ) {
+        if !matches!(emit, Emit::Ir | Emit::Run) {
+            return Err(format!("选项 {} 与 {} 冲突", instrument, flag));
+        }
+    }
+    Ok(Command::Compile(Options {
+        emit: emit.map_or(Emit::Ir, |(_, emit)| emit),
+        inputs,
+        output,
+        target,
+        trace,
+        instrument: instrument.map_or(Instrument::None, |(_, instrument)| instrument),
+        check_bounds,
+        sanitize_overflow,
+    }))
 }
@@ -0,0 +1,20 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! 诊断信息的编号与详细说明，供 `xenon --explain <编号>` 使用。
+
+pub mod codes;
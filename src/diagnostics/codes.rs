@@ -0,0 +1,370 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+/// 一个错误或警告编号。`explanation` 包含触发该诊断的示例代码以及修改方法。
+pub struct Code {
+    pub code: &'static str,
+    pub summary: &'static str,
+    pub explanation: &'static str,
+}
+
+pub static CODES: &[Code] = &[
+    Code {
+        code: "E001",
+        summary: "使用了不存在的标识符",
+        explanation: "\
+表达式中的变量、常量、数组或函数在当前位置不可见。SysY 要求标识符先定义后使用，
+局部定义只在所在的语句块中可见。
+
+错误示例:
+
+    int main() {
+        { int x = 1; }
+        return x;
+    }
+
+x 定义在内层语句块中，离开该块后就不可见了。把定义移到使用处所在的作用域即可:
+
+    int main() {
+        int x = 1;
+        return x;
+    }
+",
+    },
+    Code {
+        code: "E002",
+        summary: "标识符在当前作用域中重复定义",
+        explanation: "\
+同一个作用域中不能有两个同名的定义，函数的参数与函数体最外层的定义也不能同名。
+关键字和运行时库函数（如 getint、putint）的名字同样不能重新定义。
+
+错误示例:
+
+    int f() { return 0; }
+    int f() { return 1; }
+
+删除多余的定义，或者给其中一个换一个名字。在内层语句块中定义同名标识符是允许的，
+但会产生警告 W002。
+",
+    },
+    Code {
+        code: "E003",
+        summary: "需要常量表达式的地方使用了变量",
+        explanation: "\
+数组的长度、常量的初始值和全局变量的初始值必须能在编译期求值，
+只能由字面量、已定义的常量以及常量数组的元素组成。
+
+错误示例:
+
+    int n = 10;
+    int a[n];
+
+把 n 定义为常量即可:
+
+    const int n = 10;
+    int a[n];
+",
+    },
+    Code {
+        code: "E004",
+        summary: "表达式的类型不符合要求",
+        explanation: "\
+SysY 中只有整型可以参与运算、作为条件或者被返回，数组名只能作为函数的数组参数传递，
+void 函数的返回值不能使用。
+
+错误示例:
+
+    void f() {}
+    int main() {
+        int a[2];
+        return a + f();
+    }
+
+对数组取下标得到整型元素，并且只在表达式中调用返回 int 的函数:
+
+    int f() { return 1; }
+    int main() {
+        int a[2] = {};
+        return a[0] + f();
+    }
+",
+    },
+    Code {
+        code: "E005",
+        summary: "break 或 continue 不在循环内",
+        explanation: "\
+break 和 continue 只能出现在 while 循环的循环体中。
+
+错误示例:
+
+    int main() {
+        if (1) { break; }
+        return 0;
+    }
+
+如果想提前结束函数，应该使用 return；如果想跳出循环，应把语句放进对应的 while 中。
+",
+    },
+    Code {
+        code: "E006",
+        summary: "goto 向前跳转或目标标签不存在",
+        explanation: "\
+Xenon 的 goto 扩展只允许跳转到同一个函数中位于 goto 之后的标签，标签在函数中不能重名。
+
+错误示例:
+
+    int main() {
+    again:
+        putint(1);
+        goto again;
+        return 0;
+    }
+
+向前跳转构成的循环应改写为 while:
+
+    int main() {
+        while (1) { putint(1); }
+        return 0;
+    }
+",
+    },
+    Code {
+        code: "E007",
+        summary: "常量表达式溢出或下标越界",
+        explanation: "\
+编译期求值的表达式必须在 int 的表示范围内，不能除以 0，移位的位数不能越界；
+常量数组的下标是常量时，必须在数组的范围内。
+
+错误示例:
+
+    const int big = 2147483647;
+    int main() {
+        return big + 1;
+    }
+
+检查表达式是否写错；确实需要更大的值时，SysY 没有更宽的整型，只能换一种算法。
+",
+    },
+    Code {
+        code: "E008",
+        summary: "数组的维度或初始化列表不合法",
+        explanation: "\
+数组的每一维长度必须是正的常量，整个数组不能超出 32 位地址空间；初始化列表中的元素
+个数不能超过数组的大小，嵌套的初始化列表必须对齐到某一维的边界。
+
+错误示例:
+
+    int a[2] = {1, 2, 3};
+    int b[0];
+
+去掉多余的元素，或者把数组定义得足够大:
+
+    int a[3] = {1, 2, 3};
+    int b[1];
+",
+    },
+    Code {
+        code: "E009",
+        summary: "函数调用的实参个数与形参不符",
+        explanation: "\
+调用函数时传入的实参个数必须与函数定义中的形参个数相同，SysY 没有默认参数与可变参数
+（运行时库函数 putf 除外）。
+
+错误示例:
+
+    int add(int a, int b) { return a + b; }
+    int main() {
+        return add(1);
+    }
+
+按函数定义补全实参:
+
+    int main() {
+        return add(1, 2);
+    }
+",
+    },
+    Code {
+        code: "E010",
+        summary: "main 函数缺失、不符合要求或被递归调用",
+        explanation: "\
+程序必须有一个没有参数、返回 int 的 main 函数，并且任何函数都不能调用 main。
+
+错误示例:
+
+    int main() {
+        if (getint()) { return main(); }
+        return 0;
+    }
+
+需要重复执行的逻辑应放进单独的函数或 while 循环中:
+
+    int main() {
+        while (getint()) {}
+        return 0;
+    }
+",
+    },
+    Code {
+        code: "W001",
+        summary: "局部变量定义后从未使用",
+        explanation: "\
+局部变量或数组定义之后，所在语句块中再也没有读取或赋值它，这个定义是多余的，
+往往意味着本想使用它的地方用错了变量。全局变量与函数参数不会触发该警告。
+
+触发示例:
+
+    int main() {
+        int sum = 0;
+        int i = getint();
+        return i * 2;
+    }
+
+删除多余的定义，或者检查本应使用 sum 的地方是否写成了别的变量。
+",
+    },
+    Code {
+        code: "W002",
+        summary: "内层定义遮蔽了外层的同名标识符",
+        explanation: "\
+在内层语句块中定义了与外层同名的标识符。这是合法的，但内层定义之后在该块中
+就无法再访问外层的同名变量，往往是笔误。
+
+触发示例:
+
+    int main() {
+        int sum = 0;
+        {
+            int sum = 1;
+            putint(sum);
+        }
+        return sum;
+    }
+
+如果本意是修改外层变量，去掉内层的类型名，写作 sum = 1;；否则给内层变量换一个名字。
+",
+    },
+    Code {
+        code: "W003",
+        summary: "if 的条件是常量",
+        explanation: "\
+if 的条件在编译期就能求值，因此其中一个分支永远不会执行。
+
+触发示例:
+
+    const int DEBUG = 0;
+    int main() {
+        if (DEBUG) { putint(1); }
+        return 0;
+    }
+
+如果是有意用常量开关代码，可以忽略该警告；否则检查条件中是否误用了常量而不是变量。
+",
+    },
+    Code {
+        code: "W004",
+        summary: "while 的条件是常量 0",
+        explanation: "\
+while 的条件在编译期求值为 0，循环体永远不会执行。
+
+触发示例:
+
+    int main() {
+        while (0) { putint(1); }
+        return 0;
+    }
+
+删除这个循环，或者检查条件是否写错。
+",
+    },
+    Code {
+        code: "W005",
+        summary: "while 的条件是非 0 常量，循环不会结束",
+        explanation: "\
+while 的条件是非 0 常量，而循环体中没有 break、return 或跳到循环之外的 goto，循环永远不会结束。
+
+触发示例:
+
+    int main() {
+        while (1) { putint(getint()); }
+        return 0;
+    }
+
+在满足结束条件时用 break 跳出循环:
+
+    int main() {
+        while (1) {
+            int x = getint();
+            if (x == 0) { break; }
+            putint(x);
+        }
+        return 0;
+    }
+",
+    },
+    Code {
+        code: "W006",
+        summary: "变量在赋值之前被读取",
+        explanation: "\
+局部变量定义时没有初始值，读取时并非在每条执行路径上都已被赋值，读到的值可能是不确定的。
+if 语句的两个分支都赋值才算已赋值；循环体可能一次也不执行，其中的赋值不计入循环之后；
+return、break、continue 与 goto 之后的赋值也不计入。每个变量只警告一次。
+
+触发示例:
+
+    int main() {
+        int sum;
+        int i = 0;
+        while (i < 10) {
+            sum = sum + i;
+            i = i + 1;
+        }
+        return sum;
+    }
+
+在定义时给出初始值，写作 int sum = 0;。全局变量没有初始值时为 0，不会触发该警告。
+",
+    },
+];
+
+/// 查找编号，不区分大小写。
+pub fn lookup(code: &str) -> Option<&'static Code> {
+    CODES.iter().find(|entry| entry.code.eq_ignore_ascii_case(code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn codes_are_unique_and_documented() {
+        let mut seen = HashSet::new();
+        for entry in CODES {
+            assert!(seen.insert(entry.code), "{} 重复", entry.code);
+            assert!(entry.code.starts_with(['E', 'W']) && entry.code[1..].parse::<u32>().is_ok());
+            assert!(entry.explanation.contains("示例"), "{} 缺少示例", entry.code);
+        }
+    }
+
+    #[test]
+    fn lookup_ignores_case() {
+        assert_eq!(lookup("w001").unwrap().code, "W001");
+        assert_eq!(lookup("w001").unwrap().summary, "局部变量定义后从未使用");
+        assert!(lookup("E999").is_none());
+    }
+}
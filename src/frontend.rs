@@ -15,16 +15,303 @@
 // You should have received a copy of the GNU General Public License
 // along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
 
+use crate::arg_parse::{Instrument, Options};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::sync::atomic::AtomicBool;
+
 mod ast;
+mod bounds;
 mod checker;
+pub mod coverage;
+mod dead;
+mod doc;
 mod dump;
 mod expr;
+mod format;
+pub mod incremental;
+mod interpreter;
+mod json;
+mod lexer;
+mod overflow;
 mod parser;
+mod profile;
+mod stats;
+mod symbols;
+mod walk;
+
+pub use checker::{ChineseFormatter, ErrorFormatter, ItemKind};
+
+pub struct Source {
+    pub name: String,
+    pub code: String,
+}
+
+/// 一条错误或警告，以及它所在的源文件在输入中的下标。
+pub type Diagnostic = (usize, String);
+
+pub type Output = Result<(String, Vec<Diagnostic>), Diagnostic>;
+
+fn parse(sources: &[Source]) -> Result<Vec<ast::TranslationUnit>, Diagnostic> {
+    sources
+        .iter()
+        .enumerate()
+        .map(|(file, source)| parser::build_ast(&source.code).map_err(|e| (file, e)))
+        .collect()
+}
+
+fn generate_ast(sources: &[Source]) -> Result<(ast::TranslationUnit, Vec<Diagnostic>), Diagnostic> {
+    let names: Vec<&str> = sources.iter().map(|source| source.name.as_str()).collect();
+    checker::check(parse(sources)?, &names)
+}
+
+/// 完整的词法单元序列。无法识别的词法单元也在其中，同时作为错误返回。
+pub fn generate_tokens(sources: &[Source]) -> (String, Vec<Diagnostic>) {
+    let mut text = String::new();
+    let mut errors = Vec::new();
+    for (file, source) in sources.iter().enumerate() {
+        for token in lexer::tokenize(&source.code) {
+            if token.kind == lexer::TokenKind::Error {
+                let (line, column) = token.start;
+                errors.push((file, format!("{}:{}: 无法识别的词法单元 {}", line, column, token.text)));
+            }
+            text.push_str(&format!("{}\n", token));
+        }
+    }
+    (text, errors)
+}
+
+pub fn generate_format(sources: &[Source]) -> Output {
+    let text = sources
+        .iter()
+        .enumerate()
+        .map(|(file, source)| format::format_code(&source.code).map_err(|e| (file, e)))
+        .collect::<Result<String, _>>()?;
+    Ok((text, Vec::new()))
+}
+
+/// 模拟编辑器逐行输入每个文件，再逐行删除并恢复，每次编辑都与完整的重新检查比较。输出最终的诊断信息。
+pub fn generate_incremental(sources: &[Source]) -> Output {
+    let mut text = String::new();
+    for source in sources {
+        let mut document = incremental::Document::new(String::new());
+        document.set_verify(true);
+        let lines: Vec<&str> = source.code.split_inclusive('\n').collect();
+        for line in &lines {
+            let end = document.text().len();
+            document.edit(end..end, line).unwrap();
+        }
+        let mut offset = 0;
+        for line in &lines {
+            document.edit(offset..offset + line.len(), "").unwrap();
+            document.edit(offset..offset, line).unwrap();
+            offset += line.len();
+        }
+        for diagnostic in document.diagnostics() {
+            let severity = match diagnostic.severity {
+                incremental::Severity::Error => "错误",
+                incremental::Severity::Warning => "警告",
+            };
+            let span = &diagnostic.span;
+            text.push_str(&format!(
+                "{}: {}..{} {}: {}\n",
+                source.name, span.start, span.end, severity, diagnostic.message
+            ));
+        }
+    }
+    Ok((text, Vec::new()))
+}
+
+/// 函数名到定义它的源文件的下标。
+fn defined_in(units: &[ast::TranslationUnit]) -> HashMap<String, usize> {
+    units
+        .iter()
+        .enumerate()
+        .flat_map(|(file, unit)| {
+            unit.iter().filter_map(move |item| match item.as_ref() {
+                ast::GlobalItem::FuncDef { id, .. } => Some((id.clone(), file)),
+                ast::GlobalItem::Def(_) => None,
+            })
+        })
+        .collect()
+}
+
+/// 插桩后的语法树、映射文件的内容（不插桩时为 None）与插入的调用所用的运行时库函数。
+type Instrumented = (Vec<ast::TranslationUnit>, Option<String>, Vec<&'static str>);
+
+/// 语法分析后按 `instrument` 插桩，`check_bounds` 时包装下标，`check_overflow` 时改写算术运算
+/// （检查之后还需要 [`resolve`]）。
+fn instrument(
+    sources: &[Source],
+    instrument: Instrument,
+    check_bounds: bool,
+    check_overflow: bool,
+) -> Result<Instrumented, Diagnostic> {
+    let mut units = parse(sources)?;
+    if instrument == Instrument::None && !check_bounds && !check_overflow {
+        return Ok((units, None, Vec::new()));
+    }
+    let lines = sources
+        .iter()
+        .enumerate()
+        .map(|(file, source)| parser::statement_lines(&source.code).map_err(|e| (file, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut hooks = Vec::new();
+    // 先包装下标，计数器的下标不检查
+    if check_bounds {
+        bounds::guard(&mut units, &lines);
+        hooks.push(bounds::CHECK_BOUND);
+    }
+    if check_overflow {
+        overflow::guard(&mut units, &lines);
+        hooks.push(overflow::CHECK_OVERFLOW);
+    }
+    let map = match instrument {
+        Instrument::None => return Ok((units, None, hooks)),
+        Instrument::Coverage => {
+            let counters = coverage::instrument(&mut units, &lines);
+            let names: Vec<&str> = sources.iter().map(|source| source.name.as_str()).collect();
+            hooks.push(coverage::WRITE_COUNTERS);
+            coverage::map_text(&names, &counters)
+        }
+        _ => {
+            hooks.push(profile::WRITE_PROFILE);
+            profile::map_text(&profile::instrument(&mut units, &lines, instrument == Instrument::ProfileBlocks))
+        }
+    };
+    Ok((units, Some(map), hooks))
+}
+
+/// 检查之后填入下标检查的长度，折叠常量运算的溢出检查。错误归到出错的函数所在的源文件。
+fn resolve(
+    ast: &mut ast::TranslationUnit,
+    defined_in: &HashMap<String, usize>,
+    check_bounds: bool,
+    check_overflow: bool,
+) -> Result<(), Diagnostic> {
+    // 先折叠，常量下标才能去掉检查
+    if check_overflow {
+        overflow::resolve(ast).map_err(|(function, e)| (defined_in.get(&function).copied().unwrap_or(0), e))?;
+    }
+    if check_bounds {
+        bounds::resolve(ast).map_err(|(function, e)| (defined_in.get(&function).copied().unwrap_or(0), e))?;
+    }
+    Ok(())
+}
+
+/// 同 generate_ir，但按 `options` 先插桩，同时返回映射文件的内容（只做运行时检查时为 None）。
+pub fn generate_instrumented_ir(
+    sources: &[Source],
+    options: &Options,
+) -> Result<(String, Option<String>, Vec<Diagnostic>), Diagnostic> {
+    let (check_bounds, check_overflow) = (options.check_bounds, options.sanitize_overflow);
+    let (units, map, hooks) = self::instrument(sources, options.instrument, check_bounds, check_overflow)?;
+    let defined_in = defined_in(&units);
+    let names: Vec<&str> = sources.iter().map(|source| source.name.as_str()).collect();
+    let (mut ast, warnings) = checker::check_instrumented(units, &names, &hooks)?;
+    resolve(&mut ast, &defined_in, check_bounds, check_overflow)?;
+    Ok((dump::dump_ir(&ast), map, warnings))
+}
+
+pub fn generate_ast_text(sources: &[Source]) -> Output {
+    let (ast, warnings) = generate_ast(sources)?;
+    Ok((json::dump_ast(&ast), warnings))
+}
+
+pub fn generate_ir(sources: &[Source]) -> Output {
+    let (ast, warnings) = generate_ast(sources)?;
+    Ok((dump::dump_ir(&ast), warnings))
+}
+
+pub fn generate_stats(sources: &[Source], json: bool) -> Output {
+    let (ast, warnings) = generate_ast(sources)?;
+    let stats = stats::collect(&ast, &dump::dump_ir(&ast));
+    match json {
+        true => Ok((stats::stats_json(&stats), warnings)),
+        false => Ok((stats::stats_table(&stats), warnings)),
+    }
+}
+
+/// 未使用与不可达代码的报告，`json` 为 true 时以 JSON 格式输出。
+pub fn generate_dead_code(sources: &[Source], json: bool) -> Output {
+    let units = parse(sources)?;
+    let names: Vec<&str> = sources.iter().map(|source| source.name.as_str()).collect();
+    let (ast, warnings) = checker::check(units.clone(), &names)?;
+    let codes: Vec<&str> = sources.iter().map(|source| source.code.as_str()).collect();
+    let report = dead::collect(&units, &ast, &codes);
+    match json {
+        true => Ok((dead::report_json(&report, &names), warnings)),
+        false => Ok((dead::report_text(&report, &names), warnings)),
+    }
+}
+
+/// 只做检查，诊断信息由 `formatter` 生成。
+pub fn generate_checked(sources: &[Source], formatter: &dyn ErrorFormatter) -> Output {
+    let names: Vec<&str> = sources.iter().map(|source| source.name.as_str()).collect();
+    let (_, warnings) = checker::check_with_formatter(parse(sources)?, &names, formatter)?;
+    Ok((String::new(), warnings))
+}
+
+/// 解释执行，返回 main 函数的返回值，插桩时同时返回映射文件的内容。插桩与运行时检查由 `options` 决定。
+/// 运行时错误归到出错的函数所在的源文件。`cancel` 置位后执行以运行时错误结束。
+pub fn run(
+    sources: &[Source],
+    options: &Options,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    errors: &mut dyn Write,
+    cancel: &AtomicBool,
+) -> Result<(i32, Option<String>, Vec<Diagnostic>), Diagnostic> {
+    let (check_bounds, check_overflow) = (options.check_bounds, options.sanitize_overflow);
+    let (units, map, hooks) = self::instrument(sources, options.instrument, check_bounds, check_overflow)?;
+    let defined_in = defined_in(&units);
+    let names: Vec<&str> = sources.iter().map(|source| source.name.as_str()).collect();
+    let (mut ast, warnings) = checker::check_instrumented(units, &names, &hooks)?;
+    resolve(&mut ast, &defined_in, check_bounds, check_overflow)?;
+    match interpreter::run(&ast, input, output, errors, cancel) {
+        Ok(value) => Ok((value, map, warnings)),
+        Err((function, call_site, e)) => {
+            let file = defined_in.get(function).copied().unwrap_or(0);
+            match call_site {
+                Some((caller, line)) => {
+                    let caller_file = names[defined_in.get(caller).copied().unwrap_or(0)];
+                    Err((file, format!("函数 {}（由 {} 第 {} 行的 {} 调用）运行时出错: {}", function, caller_file, line, caller, e)))
+                }
+                None => Err((file, format!("函数 {} 运行时出错: {}", function, e))),
+            }
+        }
+    }
+}
+
+pub fn generate_symbols(sources: &[Source]) -> Output {
+    let (ast, warnings) = generate_ast(sources)?;
+    Ok((symbols::dump_symbols(&ast), warnings))
+}
 
-fn generate_ast(code: &str) -> Result<ast::TranslationUnit, String> {
-    checker::check(parser::build_ast(code))
+/// 检查一组源文件并返回跟踪记录，源代码有错误时返回出错之前的记录。
+pub fn generate_trace(sources: &[Source], formatter: &dyn ErrorFormatter, const_eval: bool, checker: bool) -> String {
+    let Ok(units) = parse(sources) else {
+        return String::new();
+    };
+    let names: Vec<&str> = sources.iter().map(|source| source.name.as_str()).collect();
+    let (_, tracer) = checker::check_with_tracer(units, &names, formatter, Some(checker::Tracer::new(const_eval, checker)));
+    tracer.unwrap().into_lines().into_iter().map(|line| line + "\n").collect()
 }
 
-pub fn generate_ir(code: &str) -> Result<String, String> {
-    Ok(dump::dump_ir(&generate_ast(code)?))
+/// `sources` 未经预处理，文档注释从原始代码中提取。
+pub fn generate_doc(sources: &[Source]) -> Output {
+    let preprocessed: Vec<Source> = sources
+        .iter()
+        .map(|source| Source {
+            name: source.name.clone(),
+            code: crate::preprocessor::preprocess(&source.code),
+        })
+        .collect();
+    let (ast, warnings) = generate_ast(&preprocessed)?;
+    let mut comments = HashMap::new();
+    for source in sources {
+        comments.extend(doc::doc_comments(&source.code));
+    }
+    let names: Vec<&str> = sources.iter().map(|source| source.name.as_str()).collect();
+    Ok((doc::generate_doc(&names.join(", "), &ast, &comments), warnings))
 }
@@ -17,7 +17,7 @@
 
 pub type TranslationUnit = Vec<Box<GlobalItem>>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum GlobalItem {
     Def(Definition),
     FuncDef {
@@ -28,14 +28,14 @@ pub enum GlobalItem {
     },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Parameter {
     Int(String),
     PointerTmp(String, Vec<Expr>),
     Pointer(String, Vec<usize>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Definition {
     ConstVariableDefTmp(String, Expr),
     ConstVariableDef(String, i32),
@@ -47,7 +47,7 @@ pub enum Definition {
     ConstArrayDef {
         id: String,
         lengths: Vec<usize>,
-        init_list: ConstInitList,
+        values: Vec<i32>,
     },
     VariableDef(String, Option<Expr>),
     ArrayDefTmp {
@@ -64,7 +64,7 @@ pub enum Definition {
 
 pub type InitList = Vec<InitListItem>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum InitListItem {
     InitList(Box<InitList>),
     Expr(Expr),
@@ -72,13 +72,13 @@ pub enum InitListItem {
 
 pub type ConstInitList = Vec<ConstInitListItem>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ConstInitListItem {
     InitList(Box<ConstInitList>),
     Num(i32),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Statement {
     Expr(Expr),
     If {
@@ -93,23 +93,88 @@ pub enum Statement {
     Return(Option<Expr>),
     Break,
     Continue,
+    Goto(String),
+    Label(String),
 }
 
-pub type Block = Vec<BlockItem>;
+/// 语句块中的各项。只提供遍历与追加，不暴露 `Vec` 的其他修改方法。
+#[derive(Debug, Clone, Default)]
+pub struct Block(Vec<BlockItem>);
 
-#[derive(Debug)]
+impl Block {
+    pub fn iter(&self) -> std::slice::Iter<'_, BlockItem> {
+        self.0.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, BlockItem> {
+        self.0.iter_mut()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn push(&mut self, item: BlockItem) {
+        self.0.push(item);
+    }
+
+    pub fn as_slice(&self) -> &[BlockItem] {
+        &self.0
+    }
+}
+
+impl FromIterator<BlockItem> for Block {
+    fn from_iter<T: IntoIterator<Item = BlockItem>>(iter: T) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for Block {
+    type Item = BlockItem;
+    type IntoIter = std::vec::IntoIter<BlockItem>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Block {
+    type Item = &'a BlockItem;
+    type IntoIter = std::slice::Iter<'a, BlockItem>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Block {
+    type Item = &'a mut BlockItem;
+    type IntoIter = std::slice::IterMut<'a, BlockItem>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter_mut()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum BlockItem {
     Def(Box<Definition>),
     Block(Box<Block>),
     Statement(Box<Statement>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum AssignOp {
     Assignment,
     AddAssign,
     SubtractAssign,
     MultiplyAssign,
+    DivideAssign,
+    ModulusAssign,
     BitAndAssign,
     BitOrAssign,
     BitXorAssign,
@@ -117,7 +182,7 @@ pub enum AssignOp {
     BitRightShiftAssign,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ArithmeticOp {
     Multiply,
     Divide,
@@ -139,27 +204,27 @@ pub enum ArithmeticOp {
     LessOrEqual,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum LogicOp {
     LogicalAnd,
     LogicalOr,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum InfixOp {
     Assign(AssignOp),
     Arith(ArithmeticOp),
     Logic(LogicOp),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ArithmeticUnaryOp {
     LogicalNot,
     Negative,
     BitNot,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum OtherUnaryOp {
     PostfixSelfIncrease,
     PostfixSelfDecrease,
@@ -167,21 +232,24 @@ pub enum OtherUnaryOp {
     PrefixSelfDecrease,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum UnaryOp {
     ArithUnary(ArithmeticUnaryOp),
     Others(OtherUnaryOp),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ExprInner {
     InfixExpr(Box<Expr>, InfixOp, Box<Expr>),
     UnaryExpr(UnaryOp, Box<Expr>),
 
-    Num(i32),
+    Num(i64),
     Identifier(String),
-    FunctionCall(String, Vec<Expr>),
+    FunctionCall(String, Vec<Expr>, usize),
     ArrayElement(String, Vec<Expr>, bool),
+    Comma(Box<Expr>, Box<Expr>),
+    /// 一维数组字面量，如 `f({1, 2, 3})`，求值为指向临时数组首元素的指针
+    ArrayInit(Vec<Expr>),
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -191,10 +259,11 @@ pub enum SimpleType {
     Void,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Expr {
     pub inner: ExprInner,
     pub type_: SimpleType,
+    pub is_left_value: Option<bool>,
 }
 
 impl From<ExprInner> for Expr {
@@ -202,6 +271,7 @@ impl From<ExprInner> for Expr {
         Self {
             inner,
             type_: SimpleType::Void,
+            is_left_value: None,
         }
     }
 }
@@ -211,6 +281,30 @@ impl Default for Expr {
         Self {
             inner: ExprInner::Num(0),
             type_: SimpleType::Void,
+            is_left_value: None,
+        }
+    }
+}
+
+/// 逐层拆开释放，深层嵌套的表达式不会在递归释放时栈溢出。
+impl Drop for Expr {
+    fn drop(&mut self) {
+        if matches!(self.inner, ExprInner::Num(_) | ExprInner::Identifier(_)) {
+            return;
+        }
+        let mut stack = vec![std::mem::replace(&mut self.inner, ExprInner::Num(0))];
+        while let Some(inner) = stack.pop() {
+            let children: Vec<Expr> = match inner {
+                ExprInner::InfixExpr(lhs, _, rhs) | ExprInner::Comma(lhs, rhs) => vec![*lhs, *rhs],
+                ExprInner::UnaryExpr(_, expr) => vec![*expr],
+                ExprInner::FunctionCall(_, exprs, _) | ExprInner::ArrayElement(_, exprs, _) | ExprInner::ArrayInit(exprs) => {
+                    exprs
+                }
+                ExprInner::Num(_) | ExprInner::Identifier(_) => continue,
+            };
+            for mut child in children {
+                stack.push(std::mem::replace(&mut child.inner, ExprInner::Num(0)));
+            }
         }
     }
 }
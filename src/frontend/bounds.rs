@@ -0,0 +1,344 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! 下标检查: --check-bounds 把函数体中的每个下标 `i` 改写为 `_sysy_check_bound(i, 长度, 行号)`，
+//! 运行时库在下标越界时输出行号、下标与长度，然后以非零退出码结束程序。
+//!
+//! 检查之前用 [`guard`] 包装下标，长度暂时为 0；检查之后由 [`resolve`] 按作用域找到数组的维度填入长度。
+//! 指针形参的第一维长度未知，不检查；折叠为常量且不越界的下标也不再检查。
+//! 数组长度与常量定义中的下标不包装，它们仍在编译期求值。
+
+use super::ast::*;
+use super::expr::const_eval::flat_index;
+use super::walk;
+use std::collections::HashMap;
+use std::mem::take;
+
+pub const CHECK_BOUND: &str = "_sysy_check_bound";
+
+fn wrap(expr: &mut Expr, line: usize) {
+    match &mut expr.inner {
+        ExprInner::InfixExpr(lhs, _, rhs) | ExprInner::Comma(lhs, rhs) => {
+            wrap(lhs, line);
+            wrap(rhs, line);
+        }
+        ExprInner::UnaryExpr(_, operand) => wrap(operand, line),
+        ExprInner::FunctionCall(_, exprs, _) | ExprInner::ArrayInit(exprs) => exprs.iter_mut().for_each(|expr| wrap(expr, line)),
+        ExprInner::ArrayElement(_, subscripts, _) => {
+            for subscript in subscripts.iter_mut() {
+                wrap(subscript, line);
+                let arguments = vec![take(subscript), ExprInner::Num(0).into(), ExprInner::Num(line as i64).into()];
+                *subscript = ExprInner::FunctionCall(CHECK_BOUND.to_string(), arguments, line).into();
+            }
+        }
+        ExprInner::Num(_) | ExprInner::Identifier(_) => (),
+    }
+}
+
+/// 包装检查之前的语法树中函数体里的下标。`lines[i]` 是第 i 个文件中各语句与定义的行号，见 `parser::statement_lines`。
+pub fn guard(units: &mut [TranslationUnit], lines: &[Vec<usize>]) {
+    walk::function_exprs(units, lines, wrap);
+}
+
+/// 标识符的维度。`Pointer` 中是除第一维之外的长度。
+#[derive(Clone, Copy)]
+enum Shape<'a> {
+    Scalar,
+    Array(&'a [usize]),
+    ConstArray(&'a [usize], &'a [i32]),
+    Pointer(&'a [usize]),
+}
+
+struct Resolver<'a> {
+    scopes: Vec<HashMap<&'a str, Shape<'a>>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn define(&mut self, definition: &'a Definition) {
+        let (id, shape) = match definition {
+            Definition::ConstVariableDef(id, _) | Definition::VariableDef(id, _) => (id, Shape::Scalar),
+            Definition::ArrayDef { id, lengths, .. } => (id, Shape::Array(lengths)),
+            Definition::ConstArrayDef { id, lengths, values } => (id, Shape::ConstArray(lengths, values)),
+            _ => unreachable!(),
+        };
+        self.scopes.last_mut().unwrap().insert(id, shape);
+    }
+
+    fn shape(&self, id: &str) -> Shape<'a> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(id).copied())
+            .unwrap_or(Shape::Scalar)
+    }
+
+    fn expr(&self, expr: &mut Expr) -> Result<(), String> {
+        match &mut expr.inner {
+            ExprInner::InfixExpr(lhs, _, rhs) | ExprInner::Comma(lhs, rhs) => {
+                self.expr(lhs)?;
+                self.expr(rhs)?;
+            }
+            ExprInner::UnaryExpr(_, operand) => self.expr(operand)?,
+            ExprInner::FunctionCall(_, exprs, _) | ExprInner::ArrayInit(exprs) => {
+                exprs.iter_mut().try_for_each(|expr| self.expr(expr))?
+            }
+            ExprInner::ArrayElement(id, subscripts, _) => {
+                let shape = self.shape(id);
+                for (dimension, subscript) in subscripts.iter_mut().enumerate() {
+                    let ExprInner::FunctionCall(callee, arguments, _) = &mut subscript.inner else {
+                        continue;
+                    };
+                    if callee != CHECK_BOUND {
+                        continue;
+                    }
+                    self.expr(&mut arguments[0])?;
+                    let length = match shape {
+                        Shape::Array(lengths) | Shape::ConstArray(lengths, _) => lengths.get(dimension).copied(),
+                        Shape::Pointer(lengths) => dimension.checked_sub(1).and_then(|i| lengths.get(i).copied()),
+                        Shape::Scalar => None,
+                    };
+                    match (length, &arguments[0].inner) {
+                        (Some(length), ExprInner::Num(index)) if usize::try_from(*index).is_ok_and(|index| index < length) => {
+                            *subscript = take(&mut arguments[0])
+                        }
+                        (Some(length), _) => arguments[1].inner = ExprInner::Num(length as i64),
+                        (None, _) => *subscript = take(&mut arguments[0]),
+                    }
+                }
+                // 常量数组的下标都是常量时，与不检查时一样在编译期求值，越界是编译错误
+                if let Shape::ConstArray(lengths, values) = shape {
+                    let indices: Option<Vec<i64>> = subscripts
+                        .iter()
+                        .map(|subscript| match &subscript.inner {
+                            ExprInner::Num(index) => Some(*index),
+                            ExprInner::FunctionCall(callee, arguments, _) if callee == CHECK_BOUND => match arguments[0].inner {
+                                ExprInner::Num(index) => Some(index),
+                                _ => None,
+                            },
+                            _ => None,
+                        })
+                        .collect();
+                    if let Some(indices) = indices {
+                        let index = flat_index(&indices, lengths).ok_or("下标超出范围")?;
+                        expr.inner = ExprInner::Num(values[index].into());
+                    }
+                }
+            }
+            ExprInner::Num(_) | ExprInner::Identifier(_) => (),
+        }
+        Ok(())
+    }
+
+    fn init_list(&self, init_list: &mut InitList) -> Result<(), String> {
+        for item in init_list {
+            match item {
+                InitListItem::InitList(init_list) => self.init_list(init_list)?,
+                InitListItem::Expr(expr) => self.expr(expr)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, block: &'a mut Block) -> Result<(), String> {
+        self.scopes.push(HashMap::new());
+        for item in block {
+            match item {
+                BlockItem::Def(definition) => {
+                    match definition.as_mut() {
+                        Definition::VariableDef(_, Some(init)) => self.expr(init)?,
+                        Definition::ArrayDef {
+                            init_list: Some(init_list),
+                            ..
+                        } => self.init_list(init_list)?,
+                        _ => (),
+                    }
+                    self.define(definition);
+                }
+                BlockItem::Block(block) => self.block(block)?,
+                BlockItem::Statement(statement) => match statement.as_mut() {
+                    Statement::Expr(expr) | Statement::Return(Some(expr)) => self.expr(expr)?,
+                    Statement::If {
+                        condition,
+                        then_block,
+                        else_block,
+                    } => {
+                        self.expr(condition)?;
+                        self.block(then_block)?;
+                        self.block(else_block)?;
+                    }
+                    Statement::While { condition, block } => {
+                        self.expr(condition)?;
+                        self.block(block)?;
+                    }
+                    _ => (),
+                },
+            }
+        }
+        self.scopes.pop();
+        Ok(())
+    }
+}
+
+/// 为检查过的语法树中由 [`guard`] 包装的下标填入对应维度的长度，去掉不需要的检查。
+/// 常量数组的常量下标越界时返回出错的函数与错误信息，与不检查时一样。
+pub fn resolve(ast: &mut TranslationUnit) -> Result<(), (String, String)> {
+    let mut resolver = Resolver {
+        scopes: vec![HashMap::new()],
+    };
+    for item in ast.iter_mut() {
+        match item.as_mut() {
+            GlobalItem::Def(definition) => resolver.define(definition),
+            GlobalItem::FuncDef {
+                id,
+                parameter_list,
+                block,
+                ..
+            } => {
+                let mut parameters = HashMap::new();
+                for parameter in parameter_list.iter() {
+                    match parameter {
+                        Parameter::Int(id) => parameters.insert(id.as_str(), Shape::Scalar),
+                        Parameter::Pointer(id, lengths) => parameters.insert(id.as_str(), Shape::Pointer(lengths)),
+                        Parameter::PointerTmp(..) => unreachable!(),
+                    };
+                }
+                resolver.scopes.push(parameters);
+                resolver.block(block).map_err(|e| (id.clone(), e))?;
+                resolver.scopes.pop();
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{checker::check_instrumented, dump::dump_ir, interpreter, parser};
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    fn guarded(code: &str) -> TranslationUnit {
+        let mut units = vec![parser::build_ast(code).unwrap()];
+        guard(&mut units, &[parser::statement_lines(code).unwrap()]);
+        let (mut ast, _) = check_instrumented(units, &["test.sy"], &[CHECK_BOUND]).unwrap();
+        resolve(&mut ast).unwrap();
+        ast
+    }
+
+    fn run(code: &str) -> Result<i32, String> {
+        let ast = guarded(code);
+        let mut output = Vec::new();
+        interpreter::run(&ast, &mut "".as_bytes(), &mut output, &mut std::io::sink(), &AtomicBool::new(false)).map_err(|(_, _, e)| e)
+    }
+
+    #[test]
+    fn out_of_bounds_read_traps() {
+        let code = "int main() {
+  int a[2][3], i = 0;
+  while (i < 3) {
+    a[1][i] = i;
+    i = i + 1;
+  }
+  return a[i - 2][i];
+}
+";
+        assert_eq!(run(code).unwrap_err(), "第 7 行: 下标 3 越界，长度为 3");
+        assert_eq!(run(&code.replace("[i - 2][i]", "[i - 2][i - 1]")), Ok(2));
+    }
+
+    #[test]
+    fn definitions_report_their_own_line() {
+        let code = "int main() {
+  int a[2] = {1, 2}, x = 2;
+  int y = a[x];
+  return y;
+}
+";
+        assert_eq!(run(code).unwrap_err(), "第 3 行: 下标 2 越界，长度为 2");
+        let code = "int main() {\n  int a[2] = {}, x = 1;\n  if (x)\n    int b[2] = {a[x],\n      a[x + 1]};\n  return 0;\n}\n";
+        assert_eq!(run(code).unwrap_err(), "第 4 行: 下标 2 越界，长度为 2");
+    }
+
+    #[test]
+    fn pointer_first_dimension_is_unchecked() {
+        let code = "int f(int p[][2], int i) { return p[i][i]; }
+int main() {
+  int a[4][2] = {};
+  return f(a, 3);
+}
+";
+        assert_eq!(run(code).unwrap_err(), "第 1 行: 下标 3 越界，长度为 2");
+        let ir = dump_ir(&guarded(code));
+        assert_eq!(ir.matches("call @_sysy_check_bound(").count(), 1, "{}", ir);
+        // 只声明被调用的运行时库函数
+        assert!(ir.contains("decl @_sysy_check_bound(i32, i32, i32): i32\n"), "{}", ir);
+        assert!(!ir.contains("_sysy_check_overflow"), "{}", ir);
+    }
+
+    #[test]
+    fn constant_subscripts_are_not_checked() {
+        let code = "const int T[3] = {4, 5, 6};
+int main() {
+  int a[3], i = getint();
+  a[2] = T[1];
+  return a[i] + T[i] + a[5];
+}
+";
+        let ir = dump_ir(&guarded(code));
+        // a[i]、T[i] 与越界的 a[5] 各一次
+        assert_eq!(ir.matches("call @_sysy_check_bound(").count(), 3, "{}", ir);
+        assert!(ir.contains("store 5, "), "{}", ir);
+    }
+
+    #[test]
+    fn constant_out_of_range_is_compile_error() {
+        let resolved = |code: &str| {
+            let mut units = vec![parser::build_ast(code).unwrap()];
+            guard(&mut units, &[parser::statement_lines(code).unwrap()]);
+            let (mut ast, _) = check_instrumented(units, &["test.sy"], &[CHECK_BOUND]).unwrap();
+            resolve(&mut ast).map(|_| ())
+        };
+        assert_eq!(
+            resolved("const int a[2] = {1, 2}; int main() { return a[5]; }"),
+            Err(("main".to_string(), "下标超出范围".to_string()))
+        );
+        assert_eq!(
+            resolved("const int a[2][2] = {}; int f() { return a[1][2]; } int main() { return 0; }"),
+            Err(("f".to_string(), "下标超出范围".to_string()))
+        );
+        // 下标不全是常量时在运行时检查
+        assert_eq!(
+            resolved("const int a[2][2] = {}; int main() { return a[getint()][5]; }"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn canonical_loop_has_one_check() {
+        let code = "int main() {
+  int a[10], i = 0, n = getint();
+  while (i < n) {
+    a[i] = i;
+    i = i + 1;
+  }
+  return 0;
+}
+";
+        let ir = dump_ir(&guarded(code));
+        assert_eq!(ir.matches("call @_sysy_check_bound(").count(), 1, "{}", ir);
+    }
+}
@@ -16,19 +16,49 @@
 // along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
 
 use super::ast::{Definition::*, *};
+use super::{bounds, coverage, overflow, profile};
+use super::expr::types::lengths_str;
 use super::expr::types::Type::{self, *};
-use std::{collections::HashMap, mem::take, vec};
+use super::format::{expr_str, init_list_str};
+use super::Diagnostic;
+use std::cell::RefCell;
+use std::collections::{hash_map, HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::{iter::Rev, mem::take, slice, vec};
 
+#[derive(Debug, Clone)]
 pub enum SymbolTableItem<'a> {
     ConstVariable(i32),
     Variable,
-    ConstArray(&'a Vec<usize>, &'a ConstInitList),
+    ConstArray(&'a Vec<usize>, &'a Vec<i32>),
     Array(&'a Vec<usize>),
-    Function(Type<'a>, Vec<Type<'a>>),
+    Function {
+        ret: Type<'a>,
+        params: Vec<Type<'a>>,
+        // 运行时库函数，不能重定义
+        is_builtin: bool,
+    },
     Pointer(&'a Vec<usize>),
     Keyword,
 }
 
+impl Display for SymbolTableItem<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SymbolTableItem::ConstVariable(i) => write!(f, "const int {}", i),
+            SymbolTableItem::Variable => write!(f, "int"),
+            SymbolTableItem::ConstArray(lengths, _) => write!(f, "const int{}", lengths_str(lengths)),
+            SymbolTableItem::Array(lengths) => write!(f, "int{}", lengths_str(lengths)),
+            SymbolTableItem::Function { ret, params, .. } => {
+                let para_str: Vec<String> = params.iter().map(Type::to_string).collect();
+                write!(f, "{} ({})", ret, para_str.join(", "))
+            }
+            SymbolTableItem::Pointer(lengths) => write!(f, "{}", Pointer(lengths)),
+            SymbolTableItem::Keyword => write!(f, "keyword"),
+        }
+    }
+}
+
 #[macro_export]
 macro_rules! risk {
     ($expression:expr, $pattern:pat => $extracted_expression:expr) => {
@@ -41,41 +71,560 @@ macro_rules! risk {
 
 use SymbolTableItem::{Array, ConstArray, ConstVariable, Function, Keyword, Variable};
 
-pub type SymbolTable<'a> = Vec<HashMap<&'a str, SymbolTableItem<'a>>>;
+impl<'a> SymbolTableItem<'a> {
+    /// `values` 由 flatten_const_init_list 补齐，元素个数总与维度一致。
+    fn new_const_array(lengths: &'a Vec<usize>, values: &'a Vec<i32>) -> Self {
+        debug_assert_eq!(values.len(), lengths.iter().product::<usize>());
+        ConstArray(lengths, values)
+    }
+}
+
+/// 检查过程的跟踪记录，每条一行。插桩处只判断 `SymbolTable::tracer` 是否为 None，关闭时几乎没有开销。
+pub struct Tracer {
+    pub const_eval: bool,
+    pub checker: bool,
+    lines: RefCell<Vec<String>>,
+}
+
+impl Tracer {
+    pub fn new(const_eval: bool, checker: bool) -> Self {
+        Self {
+            const_eval,
+            checker,
+            lines: RefCell::new(Vec::new()),
+        }
+    }
 
-pub trait Scope<'a> {
-    fn search(&self, identifier: &str) -> Option<&SymbolTableItem>;
+    pub fn log(&self, line: String) {
+        self.lines.borrow_mut().push(line);
+    }
 
-    fn insert_definition(&mut self, identifier: &'a str, symbol: SymbolTableItem<'a>) -> Result<(), String>;
+    /// 预留一行，结果得出后用 fill 填入，使嵌套的求值按先序排列。
+    pub fn reserve(&self) -> usize {
+        self.log(String::new());
+        self.lines.borrow().len() - 1
+    }
 
-    fn enter_scope(&mut self);
-    fn exit_scope(&mut self);
+    pub fn fill(&self, index: usize, line: String) {
+        self.lines.borrow_mut()[index] = line;
+    }
+
+    pub fn into_lines(self) -> Vec<String> {
+        self.lines.into_inner()
+    }
 }
 
-impl<'a> Scope<'a> for SymbolTable<'a> {
-    fn search(&self, identifier: &str) -> Option<&SymbolTableItem> {
-        for map in self.iter().rev() {
-            if let Some(info) = map.get(identifier) {
-                return Some(info);
+/// 标识符在符号表中的种类，供 ErrorFormatter 选择措辞。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    Variable,
+    Constant,
+    Array,
+    Pointer,
+    Keyword,
+    Function,
+    BuiltinFunction,
+}
+
+impl SymbolTableItem<'_> {
+    pub fn kind(&self) -> ItemKind {
+        match self {
+            Variable => ItemKind::Variable,
+            ConstVariable(_) => ItemKind::Constant,
+            Array(_) | ConstArray(_, _) => ItemKind::Array,
+            SymbolTableItem::Pointer(_) => ItemKind::Pointer,
+            Keyword => ItemKind::Keyword,
+            Function { is_builtin: true, .. } => ItemKind::BuiltinFunction,
+            Function { is_builtin: false, .. } => ItemKind::Function,
+        }
+    }
+}
+
+/// 检查器诊断信息（错误与警告）的文本，可替换为其他语言。表达式以源代码的形式、类型以 SysY 的写法传入。
+pub trait ErrorFormatter {
+    fn undefined_identifier(&self, id: &str) -> String;
+    fn undefined_function(&self, id: &str) -> String;
+    fn not_callable(&self, id: &str, kind: ItemKind) -> String;
+    /// `declared` 为先前定义的类型
+    fn redefinition(&self, id: &str, previous: ItemKind, declared: &str) -> String;
+    fn argument_mismatch(&self, arg: &str, expected: &str) -> String;
+    fn argument_count(&self, id: &str, expected: usize, got: usize) -> String;
+    fn recursive_main(&self) -> String;
+    fn not_integer(&self, expr: &str) -> String;
+    fn non_constant_global(&self, id: &str) -> String;
+    /// `statement` 为 "if" 或 "while"
+    fn condition_type(&self, statement: &str, got: &str) -> String;
+    fn missing_return_value(&self) -> String;
+    fn unexpected_return_value(&self, expr: &str) -> String;
+    fn return_type(&self, expr: &str) -> String;
+    /// `statement` 为 "break" 或 "continue"
+    fn outside_loop(&self, statement: &str) -> String;
+    fn backward_goto(&self, label: &str) -> String;
+    fn duplicate_label(&self, label: &str) -> String;
+    fn undefined_label(&self, label: &str) -> String;
+    fn missing_main(&self) -> String;
+    /// 全局标识符 `id` 在文件 `previous` 与 `file` 中都有定义
+    fn duplicate_global(&self, id: &str, previous: &str, file: &str) -> String;
+    fn type_mismatch(&self, expected: &str, got: &str) -> String;
+    fn not_left_value(&self, expr: &str) -> String;
+    fn not_constant(&self, expr: &str) -> String;
+    fn out_of_range(&self, value: i64) -> String;
+    /// `expr` 为折叠了操作数的常量表达式，如 `2147483647 + 1`
+    fn constant_overflow(&self, expr: &str) -> String;
+    fn array_literal_element(&self, expr: &str) -> String;
+    fn subscript_count(&self, id: &str) -> String;
+    fn subscript_out_of_range(&self) -> String;
+    fn not_subscriptable(&self, id: &str) -> String;
+    fn const_array_as_pointer(&self, id: &str) -> String;
+    fn zero_length(&self) -> String;
+    fn negative_length(&self, expr: &str) -> String;
+    /// `type_` 为数组的类型，如 `int[65536][65536]`
+    fn array_too_large(&self, type_: &str) -> String;
+    fn nested_init_list(&self, init_list: &str) -> String;
+    fn init_list_too_long(&self) -> String;
+    /// 以下为警告
+    fn unused_variable(&self, id: &str) -> String;
+    fn shadowing(&self, id: &str) -> String;
+    fn read_before_assignment(&self, id: &str) -> String;
+    fn constant_if(&self, value: i64) -> String;
+    /// `value` 非 0 时只在循环不会结束时调用
+    fn constant_while(&self, value: i64) -> String;
+}
+
+/// 默认的诊断信息，以编号开头，编号的详细说明见 `xenon --explain`。
+pub struct ChineseFormatter;
+
+fn coded(code: &str, message: impl Display) -> String {
+    format!("[{}] {}", code, message)
+}
+
+impl ErrorFormatter for ChineseFormatter {
+    fn undefined_identifier(&self, id: &str) -> String {
+        coded("E001", format!("{} 不存在，或不是整型、数组或指针变量", id))
+    }
+
+    fn undefined_function(&self, id: &str) -> String {
+        coded("E001", format!("函数 '{}' 不存在", id))
+    }
+
+    fn not_callable(&self, id: &str, kind: ItemKind) -> String {
+        let kind = match kind {
+            ItemKind::Variable => "变量",
+            ItemKind::Constant => "常量",
+            ItemKind::Array => "数组",
+            ItemKind::Pointer => "指针",
+            ItemKind::Keyword => "关键字",
+            ItemKind::Function | ItemKind::BuiltinFunction => "函数",
+        };
+        coded("E004", format!("'{}' 是{}，不能作为函数调用", id, kind))
+    }
+
+    fn redefinition(&self, id: &str, previous: ItemKind, declared: &str) -> String {
+        coded(
+            "E002",
+            match previous {
+                ItemKind::Keyword => format!("标识符 {} 是关键字，不能重定义", id),
+                ItemKind::BuiltinFunction => format!("函数 {} 是运行时库函数，不能重定义", id),
+                _ => format!("标识符 {} 在当前作用域中已定义为 {}", id, declared),
+            },
+        )
+    }
+
+    fn argument_mismatch(&self, arg: &str, expected: &str) -> String {
+        coded("E004", format!("{} 无法转换到类型 {}", arg, expected))
+    }
+
+    fn argument_count(&self, id: &str, expected: usize, got: usize) -> String {
+        coded("E009", format!("函数 {} 需要 {} 个实参，实际传入 {} 个", id, expected, got))
+    }
+
+    fn recursive_main(&self) -> String {
+        coded("E010", "不允许递归调用 main 函数")
+    }
+
+    fn not_integer(&self, expr: &str) -> String {
+        coded("E004", format!("{} 不是整型表达式", expr))
+    }
+
+    fn non_constant_global(&self, id: &str) -> String {
+        coded("E003", format!("全局变量 {} 的初始化表达式不是常量表达式", id))
+    }
+
+    fn condition_type(&self, statement: &str, got: &str) -> String {
+        coded("E004", format!("{} 条件应为整型，实际类型为 {}", statement, got))
+    }
+
+    fn missing_return_value(&self) -> String {
+        coded("E004", "int 函数中的 return 语句未返回表达式")
+    }
+
+    fn unexpected_return_value(&self, expr: &str) -> String {
+        coded("E004", format!("在 void 函数中返回了表达式 {}", expr))
+    }
+
+    fn return_type(&self, expr: &str) -> String {
+        coded("E004", format!("return 语句返回的 {} 类型与函数定义不匹配", expr))
+    }
+
+    fn outside_loop(&self, statement: &str) -> String {
+        coded("E005", format!("{} 只能用在循环内", statement))
+    }
+
+    fn backward_goto(&self, label: &str) -> String {
+        coded("E006", format!("goto 只能向后跳转，标签 {} 已在之前定义", label))
+    }
+
+    fn duplicate_label(&self, label: &str) -> String {
+        coded("E006", format!("标签 {} 在当前函数中已存在", label))
+    }
+
+    fn undefined_label(&self, label: &str) -> String {
+        coded("E006", format!("goto 的目标标签 {} 不存在", label))
+    }
+
+    fn missing_main(&self) -> String {
+        coded("E010", "没有 main 函数，或 main 函数不符合要求")
+    }
+
+    fn duplicate_global(&self, id: &str, previous: &str, file: &str) -> String {
+        coded("E002", format!("全局标识符 {} 在 {} 与 {} 中重复定义", id, previous, file))
+    }
+
+    fn type_mismatch(&self, expected: &str, got: &str) -> String {
+        coded("E004", format!("类型 {} 无法转换到类型 {}", got, expected))
+    }
+
+    fn not_left_value(&self, expr: &str) -> String {
+        coded("E004", format!("{} 不是左值表达式", expr))
+    }
+
+    fn not_constant(&self, expr: &str) -> String {
+        coded("E003", format!("{} 不是常量表达式", expr))
+    }
+
+    fn out_of_range(&self, value: i64) -> String {
+        coded("E007", format!("{} 超出了 int 的表示范围", value))
+    }
+
+    fn constant_overflow(&self, expr: &str) -> String {
+        coded("E007", format!("常量表达式 {} 溢出、除以 0 或移位越界", expr))
+    }
+
+    fn array_literal_element(&self, expr: &str) -> String {
+        coded("E004", format!("数组字面量的元素 {} 不是整型表达式", expr))
+    }
+
+    fn subscript_count(&self, id: &str) -> String {
+        coded("E004", format!("{} 的下标个数与维度不符", id))
+    }
+
+    fn subscript_out_of_range(&self) -> String {
+        coded("E007", "下标超出范围")
+    }
+
+    fn not_subscriptable(&self, id: &str) -> String {
+        coded("E004", format!("{} 不能使用下标运算符", id))
+    }
+
+    fn const_array_as_pointer(&self, id: &str) -> String {
+        coded("E004", format!("常量数组 {} 不能转为指针", id))
+    }
+
+    fn zero_length(&self) -> String {
+        coded("E008", "数组维度不能为零")
+    }
+
+    fn negative_length(&self, expr: &str) -> String {
+        coded("E008", format!("{} 的值小于等于 0", expr))
+    }
+
+    fn array_too_large(&self, type_: &str) -> String {
+        coded("E008", format!("数组 {} 过大，超出了 32 位地址空间", type_))
+    }
+
+    fn nested_init_list(&self, init_list: &str) -> String {
+        coded("E008", format!("{} 不能是初始化列表", init_list))
+    }
+
+    fn init_list_too_long(&self) -> String {
+        coded("E008", "初始化列表过长")
+    }
+
+    fn unused_variable(&self, id: &str) -> String {
+        coded("W001", format!("局部变量 {} 定义后从未使用", id))
+    }
+
+    fn shadowing(&self, id: &str) -> String {
+        coded("W002", format!("标识符 {} 遮蔽了外层作用域中的同名定义", id))
+    }
+
+    fn read_before_assignment(&self, id: &str) -> String {
+        coded("W006", format!("变量 {} 在赋值之前被读取", id))
+    }
+
+    fn constant_if(&self, value: i64) -> String {
+        match value {
+            0 => coded("W003", "if 的条件是常量 0，then 分支永远不会执行"),
+            _ => coded("W003", format!("if 的条件是常量 {}，else 分支永远不会执行", value)),
+        }
+    }
+
+    fn constant_while(&self, value: i64) -> String {
+        match value {
+            0 => coded("W004", "while 的条件是编译期常量，值为 0，循环体永远不会执行"),
+            _ => coded("W005", format!("while({}) 是无限循环", value)),
+        }
+    }
+}
+
+pub struct SymbolTable<'a> {
+    scopes: Vec<HashMap<&'a str, SymbolTableItem<'a>>>,
+    // 已弹出并清空的作用域，留待 enter_scope 复用，避免反复分配
+    pool: Vec<HashMap<&'a str, SymbolTableItem<'a>>>,
+    // 有事务进行时，记录每次定义所在的作用域和标识符，供回滚使用
+    journal: Vec<(usize, &'a str)>,
+    transactions: usize,
+    tracer: Option<Tracer>,
+    formatter: &'a dyn ErrorFormatter,
+}
+
+/// 事务开始时符号表的状态。必须交给 commit 或 rollback 结束，可以嵌套。
+#[must_use]
+pub struct Transaction {
+    depth: usize,
+    journal_len: usize,
+}
+
+/// 符号表所有作用域的完整副本，交给 restore 恢复。
+#[cfg(test)]
+struct SymbolTableSnapshot<'a> {
+    scopes: Vec<HashMap<&'a str, SymbolTableItem<'a>>>,
+    journal_len: usize,
+    transactions: usize,
+}
+
+impl<'a> SymbolTable<'a> {
+    fn new(global: HashMap<&'a str, SymbolTableItem<'a>>) -> Self {
+        Self {
+            scopes: vec![global],
+            pool: Vec::new(),
+            journal: Vec::new(),
+            transactions: 0,
+            tracer: None,
+            formatter: &ChineseFormatter,
+        }
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    pub fn formatter(&self) -> &dyn ErrorFormatter {
+        self.formatter
+    }
+
+    /// 打开了 checker 跟踪时记录一行，`line` 只在需要时求值。
+    fn trace_checker(&self, line: impl FnOnce() -> String) {
+        if let Some(tracer) = self.tracer.as_ref().filter(|tracer| tracer.checker) {
+            tracer.log(format!("checker: {}", line()));
+        }
+    }
+
+    /// 打开了 checker 跟踪时记录产生诊断信息的规则。
+    fn trace_result<T>(&self, rule: &str, result: Result<T, String>, warnings: &[String]) -> Result<T, String> {
+        for warning in warnings {
+            self.trace_checker(|| format!("{}产生警告: {}", rule, warning));
+        }
+        if let Err(e) = &result {
+            self.trace_checker(|| format!("{}产生错误: {}", rule, e));
+        }
+        result
+    }
+
+    fn begin_transaction(&mut self) -> Transaction {
+        self.transactions += 1;
+        Transaction {
+            depth: self.scope_depth(),
+            journal_len: self.journal.len(),
+        }
+    }
+
+    /// 保留事务中的所有修改。外层事务回滚时，这些修改仍会被撤销。
+    fn commit(&mut self, transaction: Transaction) {
+        debug_assert!(self.journal.len() >= transaction.journal_len, "事务没有按嵌套顺序结束");
+        self.transactions -= 1;
+        if self.transactions == 0 {
+            self.journal.clear();
+        }
+    }
+
+    /// 撤销事务中的所有修改：退出事务中进入的作用域，删除事务中加入已有作用域的定义。
+    fn rollback(&mut self, transaction: Transaction) {
+        while self.scope_depth() > transaction.depth {
+            self.exit_scope();
+        }
+        for (scope, id) in self.journal.drain(transaction.journal_len..).rev() {
+            if scope < transaction.depth {
+                self.scopes[scope].remove(id);
+            }
+        }
+        self.transactions -= 1;
+    }
+}
+
+/// 推测性的检查（先尝试插入定义或检查，不成功再恢复）使用的快照。与事务不同，快照复制整个符号表，
+/// 恢复时连同快照之后退出的作用域一起还原。检查器本身用事务撤销修改，还没有推测性的检查，只在测试中编译。
+#[cfg(test)]
+impl<'a> SymbolTable<'a> {
+    fn snapshot(&self) -> SymbolTableSnapshot<'a> {
+        SymbolTableSnapshot {
+            scopes: self.scopes.clone(),
+            journal_len: self.journal.len(),
+            transactions: self.transactions,
+        }
+    }
+
+    /// 恢复到 snapshot 时的状态。快照之后开始的事务必须已经结束，之后记录的定义随之丢弃。
+    fn restore(&mut self, snapshot: SymbolTableSnapshot<'a>) {
+        debug_assert_eq!(self.transactions, snapshot.transactions, "快照与恢复之间的事务没有结束");
+        while self.scope_depth() > 1 {
+            self.exit_scope();
+        }
+        self.scopes = snapshot.scopes;
+        self.journal.truncate(snapshot.journal_len);
+    }
+}
+
+// 仅供测试断言使用，作用域的层数见 Scope::scope_depth
+#[cfg(test)]
+impl<'a> SymbolTable<'a> {
+    /// 所有作用域中的定义总数，包括被遮蔽的定义。
+    fn symbol_count(&self) -> usize {
+        self.scopes.iter().map(HashMap::len).sum()
+    }
+
+    fn contains_in_current_scope(&self, id: &str) -> bool {
+        self.scopes.last().unwrap().contains_key(id)
+    }
+
+    /// 所有定义及其所在作用域的层次（全局作用域为 0），按层次和名字排序。
+    fn all_symbols_flat(&self) -> Vec<(usize, &'a str)> {
+        let mut symbols: Vec<_> = self
+            .scopes
+            .iter()
+            .enumerate()
+            .flat_map(|(depth, scope)| scope.keys().map(move |&id| (depth, id)))
+            .collect();
+        symbols.sort();
+        symbols
+    }
+}
+
+impl<'a> AsRef<[HashMap<&'a str, SymbolTableItem<'a>>]> for SymbolTable<'a> {
+    fn as_ref(&self) -> &[HashMap<&'a str, SymbolTableItem<'a>>] {
+        &self.scopes
+    }
+}
+
+/// 按从内到外的顺序遍历所有可见的符号，被遮蔽的外层定义会被跳过。
+pub struct Symbols<'t, 'a> {
+    scopes: Rev<slice::Iter<'t, HashMap<&'a str, SymbolTableItem<'a>>>>,
+    current: Option<hash_map::Iter<'t, &'a str, SymbolTableItem<'a>>>,
+    seen: HashSet<&'a str>,
+}
+
+impl<'t, 'a> Iterator for Symbols<'t, 'a> {
+    type Item = (&'a str, &'t SymbolTableItem<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(current) = &mut self.current {
+                for (identifier, symbol) in current.by_ref() {
+                    if self.seen.insert(identifier) {
+                        return Some((identifier, symbol));
+                    }
+                }
             }
+            self.current = Some(self.scopes.next()?.iter());
         }
-        None
+    }
+}
+
+impl<'t, 'a> IntoIterator for &'t SymbolTable<'a> {
+    type Item = (&'a str, &'t SymbolTableItem<'a>);
+    type IntoIter = Symbols<'t, 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Symbols {
+            scopes: self.scopes.iter().rev(),
+            current: None,
+            seen: HashSet::new(),
+        }
+    }
+}
+
+pub trait Scope<'a>: AsRef<[HashMap<&'a str, SymbolTableItem<'a>>]> {
+    fn search(&self, identifier: &str) -> Option<&SymbolTableItem<'_>>;
+
+    /// 标识符已在当前作用域中定义时，保留原先的定义并返回它。
+    fn insert_definition(&mut self, identifier: &'a str, symbol: SymbolTableItem<'a>) -> Result<(), SymbolTableItem<'a>>;
+
+    fn enter_scope(&mut self);
+    fn take_current_scope(&mut self) -> HashMap<&'a str, SymbolTableItem<'a>>;
+
+    fn exit_scope(&mut self) {
+        self.take_current_scope();
+    }
+
+    fn scope_depth(&self) -> usize {
+        self.as_ref().len()
+    }
+}
+
+impl<'a> Scope<'a> for SymbolTable<'a> {
+    fn search(&self, identifier: &str) -> Option<&SymbolTableItem<'_>> {
+        let found = self
+            .scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(depth, map)| Some((depth, map.get(identifier)?)));
+        self.trace_checker(|| match found {
+            Some((0, item)) => format!("{} 解析为全局作用域中的 {}", identifier, item),
+            Some((depth, item)) => format!("{} 解析为第 {} 层作用域中的 {}", identifier, depth, item),
+            None => format!("{} 未定义", identifier),
+        });
+        found.map(|(_, item)| item)
     }
 
-    fn insert_definition(&mut self, id: &'a str, symbol: SymbolTableItem<'a>) -> Result<(), String> {
-        match self.last_mut().unwrap().insert(id, symbol) {
-            Some(Keyword) => Err(format!("标识符 {} 是关键字，不能重定义", id)),
-            Some(_) => Err(format!("标识符 {} 在当前作用域中已存在", id)),
-            None => Ok(()),
+    fn insert_definition(&mut self, id: &'a str, symbol: SymbolTableItem<'a>) -> Result<(), SymbolTableItem<'a>> {
+        let scope = self.scopes.len() - 1;
+        match self.scopes[scope].entry(id) {
+            hash_map::Entry::Occupied(previous) => Err(previous.get().clone()),
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(symbol);
+                if self.transactions > 0 {
+                    self.journal.push((scope, id));
+                }
+                Ok(())
+            }
         }
     }
 
     fn enter_scope(&mut self) {
-        self.push(HashMap::new());
+        let scope = self.pool.pop().unwrap_or_default();
+        self.scopes.push(scope);
+    }
+
+    fn take_current_scope(&mut self) -> HashMap<&'a str, SymbolTableItem<'a>> {
+        debug_assert!(self.scope_depth() > 1, "全局作用域不能被弹出");
+        self.scopes.pop().unwrap()
     }
 
     fn exit_scope(&mut self) {
-        self.pop();
+        let mut scope = self.take_current_scope();
+        scope.clear();
+        self.pool.push(scope);
     }
 }
 
@@ -110,7 +659,7 @@ impl InitListTrait for InitListItem {
     fn new_item(expr: &mut Expr, symbol_table: &SymbolTable) -> Result<Self, String> {
         match expr.expr_type(symbol_table)? {
             Int => Ok(Self::Expr(take(expr))),
-            _ => Err(format!("{:?} 不是整型表达式", expr)),
+            _ => Err(symbol_table.formatter().not_integer(&expr_str(expr))),
         }
     }
     fn get_last(v: &mut Vec<Self>) -> &mut Vec<Self> {
@@ -130,10 +679,13 @@ where
     for ele in init_list {
         match ele {
             InitListItem::InitList(l) => {
-                if len_prod.len() == 1 || sum % len_prod[0] != 0 {
-                    return Err(format!("{:?} 不能是初始化列表", l));
+                if len_prod.len() == 1 || !sum.is_multiple_of(len_prod[0]) {
+                    return Err(context.formatter().nested_init_list(&init_list_str(l)));
                 }
-                let rev_depth = len_prod.iter().position(|prod| sum % prod != 0).unwrap_or(len_prod.len() - 1);
+                let rev_depth = len_prod
+                    .iter()
+                    .position(|prod| !sum.is_multiple_of(*prod))
+                    .unwrap_or(len_prod.len() - 1);
                 let depth = len_prod.len() - rev_depth - 1;
                 let (l, s) = __impl(context, l, &len_prod[0..rev_depth])?;
                 let v_ref = (0..depth).fold(&mut v, |state, _| {
@@ -147,7 +699,7 @@ where
             }
             InitListItem::Expr(expr) => {
                 let v_ref = len_prod.iter().rev().skip(1).fold(&mut v, |state, i| {
-                    if state.is_empty() || sum % i == 0 {
+                    if state.is_empty() || sum.is_multiple_of(*i) {
                         state.push(T::new_list(Vec::new()));
                     }
                     T::get_last(state)
@@ -157,7 +709,7 @@ where
             }
         }
         if sum > *len_prod.last().unwrap() {
-            return Err("初始化列表过长".to_string());
+            return Err(context.formatter().init_list_too_long());
         }
     }
     Ok((v, *len_prod.last().unwrap()))
@@ -178,50 +730,81 @@ where
     Ok(__impl::<T>(context, init_list, &len_prod)?.0)
 }
 
-fn process_definition<'a>(context: &mut SymbolTable<'a>, def: &'a mut Definition) -> Result<(), String> {
+fn flatten_const_init_list(init_list: &ConstInitList, lengths: &[usize]) -> Vec<i32> {
+    let mut values = Vec::new();
+    for item in init_list {
+        match item {
+            ConstInitListItem::InitList(l) => values.extend(flatten_const_init_list(l, &lengths[1..])),
+            ConstInitListItem::Num(i) => values.push(*i),
+        }
+    }
+    values.resize(lengths.iter().product(), 0);
+    values
+}
+
+fn insert<'a>(context: &mut SymbolTable<'a>, id: &'a str, symbol: SymbolTableItem<'a>) -> Result<(), String> {
+    context
+        .insert_definition(id, symbol)
+        .map_err(|previous| context.formatter().redefinition(id, previous.kind(), &previous.to_string()))
+}
+
+fn define<'a>(
+    context: &mut SymbolTable<'a>,
+    id: &'a str,
+    symbol: SymbolTableItem<'a>,
+    warnings: &mut Vec<String>,
+) -> Result<(), String> {
+    if context.scope_depth() > 1
+        && !context.scopes.last().unwrap().contains_key(id)
+        && !matches!(context.search(id), None | Some(Keyword))
+    {
+        warnings.push(context.formatter().shadowing(id));
+    }
+    insert(context, id, symbol)
+}
+
+/// 检查定义并登记到当前作用域，返回定义的标识符。
+fn process_definition<'a>(
+    context: &mut SymbolTable<'a>,
+    def: &'a mut Definition,
+    warnings: &mut Vec<String>,
+) -> Result<&'a str, String> {
     match def {
         ConstVariableDefTmp(id, init) => {
             *def = ConstVariableDef(take(id), init.const_eval(context)?);
             let (identifier, init) = risk!(def, ConstVariableDef(id, i) => (id, *i));
-            context.insert_definition(identifier, ConstVariable(init))
+            define(context, identifier, ConstVariable(init), warnings)?;
+            Ok(identifier)
         }
         ConstArrayDefTmp { id, lengths, init_list } => {
-            for expr in lengths.iter_mut() {
-                if expr.const_eval(context)? <= 0 {
-                    return Err(format!("{:?} 的值小于等于 0", expr));
-                }
-            }
-            let lengths: Vec<usize> = lengths
-                .iter_mut()
-                .map(|p| risk!(p.inner, ExprInner::Num(i) => i as usize))
-                .collect();
+            let lengths = array_lengths(context, lengths)?;
             let init_list = process_init_list(context, init_list, &lengths)?;
+            let values = flatten_const_init_list(&init_list, &lengths);
             *def = ConstArrayDef {
                 id: take(id),
                 lengths,
-                init_list,
+                values,
             };
-            let (identifier, lengths, init_list) = risk!(def, ConstArrayDef { id, lengths, init_list } => (id, lengths, init_list));
-            context.insert_definition(identifier, ConstArray(lengths, init_list))
+            let (identifier, lengths, values) = risk!(def, ConstArrayDef { id, lengths, values } => (id, lengths, values));
+            define(
+                context,
+                identifier,
+                SymbolTableItem::new_const_array(lengths, values),
+                warnings,
+            )?;
+            Ok(identifier)
         }
         VariableDef(identifier, init) => {
             if let Some(expr) = init {
                 if !matches!(expr.expr_type(context)?, Int) {
-                    return Err(format!("{:?} 不是整型表达式", expr));
+                    return Err(context.formatter().not_integer(&expr_str(expr)));
                 }
             }
-            context.insert_definition(identifier, Variable)
+            define(context, identifier, Variable, warnings)?;
+            Ok(identifier)
         }
         ArrayDefTmp { id, lengths, init_list } => {
-            for expr in lengths.iter_mut() {
-                if expr.const_eval(context)? <= 0 {
-                    return Err(format!("{:?} 的值小于等于 0", expr));
-                }
-            }
-            let lengths: Vec<usize> = lengths
-                .iter_mut()
-                .map(|p| risk!(p.inner, ExprInner::Num(i) => i as usize))
-                .collect();
+            let lengths = array_lengths(context, lengths)?;
             let init_list = match init_list {
                 Some(init_list) => Some(process_init_list(context, init_list, &lengths)?),
                 None => None,
@@ -232,126 +815,1814 @@ fn process_definition<'a>(context: &mut SymbolTable<'a>, def: &'a mut Definition
                 init_list,
             };
             let (identifier, lengths) = risk!(def, ArrayDef { id, lengths, init_list: _ } => (id, lengths));
-            context.insert_definition(identifier, Array(lengths))
+            define(context, identifier, Array(lengths), warnings)?;
+            Ok(identifier)
+        }
+        // 已经检查过的定义，再次检查时直接登记
+        ConstVariableDef(identifier, init) => {
+            define(context, identifier, ConstVariable(*init), warnings)?;
+            Ok(identifier)
+        }
+        ConstArrayDef { id, lengths, values } => {
+            define(context, id, SymbolTableItem::new_const_array(lengths, values), warnings)?;
+            Ok(id)
+        }
+        ArrayDef { id, lengths, .. } => {
+            define(context, id, Array(lengths), warnings)?;
+            Ok(id)
         }
-        _ => unreachable!(),
     }
 }
 
-fn process_block<'a>(context: &mut SymbolTable<'a>, block: &'a mut Block, return_void: bool, in_while: bool) -> Result<(), String> {
-    context.enter_scope();
-    for block_item in block.iter_mut() {
+fn check_global_init(context: &SymbolTable, id: &str, init: &mut Expr) -> Result<(), String> {
+    init.expr_type(context)?;
+    match init.inner {
+        ExprInner::Num(_) => Ok(()),
+        _ => Err(context.formatter().non_constant_global(id)),
+    }
+}
+
+fn check_global_init_list(context: &SymbolTable, id: &str, init_list: &mut InitList) -> Result<(), String> {
+    for item in init_list.iter_mut() {
+        match item {
+            InitListItem::InitList(init_list) => check_global_init_list(context, id, init_list)?,
+            InitListItem::Expr(expr) => check_global_init(context, id, expr)?,
+        }
+    }
+    Ok(())
+}
+
+/// `block` 中是否有能离开循环体 `body` 的语句: 不在内层循环中的 break、return，以及目标标签不在 `body` 中的 goto。
+fn exits_loop(block: &Block, body: &Block, nested: bool) -> bool {
+    block.iter().any(|block_item| match block_item {
+        BlockItem::Def(_) => false,
+        BlockItem::Block(block) => exits_loop(block, body, nested),
+        BlockItem::Statement(statement) => match statement.as_ref() {
+            Statement::Break => !nested,
+            Statement::Return(_) => true,
+            Statement::Goto(label) => !defines_label(body, label),
+            Statement::If {
+                condition: _,
+                then_block,
+                else_block,
+            } => exits_loop(then_block, body, nested) || exits_loop(else_block, body, nested),
+            Statement::While { condition: _, block } => exits_loop(block, body, true),
+            _ => false,
+        },
+    })
+}
+
+fn defines_label(block: &Block, label: &str) -> bool {
+    block.iter().any(|block_item| match block_item {
+        BlockItem::Def(_) => false,
+        BlockItem::Block(block) => defines_label(block, label),
+        BlockItem::Statement(statement) => match statement.as_ref() {
+            Statement::Label(l) => l == label,
+            Statement::If {
+                condition: _,
+                then_block,
+                else_block,
+            } => defines_label(then_block, label) || defines_label(else_block, label),
+            Statement::While { condition: _, block } => defines_label(block, label),
+            _ => false,
+        },
+    })
+}
+
+fn contains_label(block: &Block) -> bool {
+    block.iter().any(|block_item| match block_item {
+        BlockItem::Def(_) => false,
+        BlockItem::Block(block) => contains_label(block),
+        BlockItem::Statement(statement) => match statement.as_ref() {
+            Statement::Label(_) => true,
+            Statement::If {
+                condition: _,
+                then_block,
+                else_block,
+            } => contains_label(then_block) || contains_label(else_block),
+            Statement::While { condition: _, block } => contains_label(block),
+            _ => false,
+        },
+    })
+}
+
+/// 检查之前的 `items` 中是否使用了标识符 `id`。重新定义 `id` 之后的部分使用的是新的定义，不计入，
+/// 新定义的初始值与数组长度中的 `id` 仍指原来的定义。
+fn uses(items: &[BlockItem], id: &str) -> bool {
+    let mut identifiers = Vec::new();
+    for block_item in items {
         match block_item {
-            BlockItem::Def(definition) => process_definition(context, definition)?,
-            BlockItem::Block(block) => process_block(context, block, return_void, in_while)?,
-            BlockItem::Statement(statement) => match statement.as_mut() {
-                Statement::Expr(expr) => expr.check_expr(context)?,
+            BlockItem::Def(definition) => {
+                let defined = match definition.as_ref() {
+                    ConstVariableDefTmp(defined, init) | VariableDef(defined, Some(init)) => {
+                        collect_identifiers(init, &mut identifiers);
+                        defined
+                    }
+                    ConstArrayDefTmp { id, lengths, init_list } => {
+                        lengths.iter().for_each(|expr| collect_identifiers(expr, &mut identifiers));
+                        collect_init_list_identifiers(init_list, &mut identifiers);
+                        id
+                    }
+                    ArrayDefTmp { id, lengths, init_list } => {
+                        lengths.iter().for_each(|expr| collect_identifiers(expr, &mut identifiers));
+                        if let Some(init_list) = init_list {
+                            collect_init_list_identifiers(init_list, &mut identifiers);
+                        }
+                        id
+                    }
+                    VariableDef(defined, None)
+                    | ConstVariableDef(defined, _)
+                    | ConstArrayDef { id: defined, .. }
+                    | ArrayDef { id: defined, .. } => defined,
+                };
+                if identifiers.contains(&id) {
+                    return true;
+                }
+                if defined == id {
+                    return false;
+                }
+            }
+            BlockItem::Block(block) => {
+                if uses(block.as_slice(), id) {
+                    return true;
+                }
+            }
+            BlockItem::Statement(statement) => match statement.as_ref() {
+                Statement::Expr(expr) | Statement::Return(Some(expr)) => collect_identifiers(expr, &mut identifiers),
                 Statement::If {
                     condition,
                     then_block,
                     else_block,
-                } => match condition.expr_type(context)? {
-                    Int => {
-                        process_block(context, then_block, return_void, in_while)?;
-                        process_block(context, else_block, return_void, in_while)?;
-                    }
-                    _ => return Err(format!("{:?} 不能作为 if 的条件", condition)),
-                },
-                Statement::While { condition, block } => match condition.expr_type(context)? {
-                    Int => process_block(context, block, return_void, true)?,
-                    _ => return Err(format!("{:?} 不能作为 if 的条件", condition)),
-                },
-                Statement::Return(expr) => match (expr, return_void) {
-                    (None, true) => (),
-                    (None, false) => return Err("int 函数中的 return 语句未返回表达式".to_string()),
-                    (Some(expr), true) => return Err(format!("在 void 函数中返回了表达式 {:?}", expr)),
-                    (Some(expr), false) => {
-                        if !matches!(expr.expr_type(context)?, Int) {
-                            return Err(format!("return 语句返回的 {:?} 类型与函数定义不匹配", expr));
-                        }
+                } => {
+                    collect_identifiers(condition, &mut identifiers);
+                    if uses(then_block.as_slice(), id) || uses(else_block.as_slice(), id) {
+                        return true;
                     }
-                },
-                Statement::Break | Statement::Continue => {
-                    if !in_while {
-                        return Err("在 while 语句外使用了 break 或 continue".to_string());
+                }
+                Statement::While { condition, block } => {
+                    collect_identifiers(condition, &mut identifiers);
+                    if uses(block.as_slice(), id) {
+                        return true;
                     }
                 }
+                _ => (),
             },
         }
+        if identifiers.contains(&id) {
+            return true;
+        }
     }
-    context.exit_scope();
-    Ok(())
+    false
 }
 
-pub fn check(mut ast: TranslationUnit) -> Result<TranslationUnit, String> {
-    let mut context = vec![HashMap::from([
-        ("getint", Function(Int, Vec::new())),
-        ("getch", Function(Int, Vec::new())),
-        ("getarray", Function(Int, vec![Pointer(&[])])),
-        ("putint", Function(Void, vec![Int])),
-        ("putch", Function(Void, vec![Int])),
-        ("putarray", Function(Int, vec![Int, Pointer(&[])])),
-        ("starttime", Function(Void, Vec::new())),
-        ("stoptime", Function(Void, Vec::new())),
-        ("if", Keyword),
-        ("while", Keyword),
-        ("break", Keyword),
-        ("continue", Keyword),
-        ("return", Keyword),
-        ("int", Keyword),
-        ("const", Keyword),
-        ("void", Keyword),
-    ])];
-    for i in ast.iter_mut() {
+/// 在所有出口路径上都已赋值的变量。
+pub type AssignedVars<'a> = HashSet<&'a str>;
+
+fn assigned_variable<'a>(context: &SymbolTable, expr: &'a Expr, assigned: &mut AssignedVars<'a>) {
+    if let ExprInner::Identifier(id) = &expr.inner {
+        if matches!(context.search(id), Some(Variable)) {
+            assigned.insert(id);
+        }
+    }
+}
+
+/// 收集表达式求值后一定被赋值的变量。`&&` 与 `||` 的右操作数不一定求值，不计入。
+/// 与下面的遍历一样用显式的栈代替递归，深层嵌套的表达式不会栈溢出。
+fn collect_assigned<'a>(context: &SymbolTable, expr: &'a Expr, assigned: &mut AssignedVars<'a>) {
+    let mut stack = vec![expr];
+    while let Some(expr) = stack.pop() {
+        match &expr.inner {
+            ExprInner::InfixExpr(lhs, InfixOp::Logic(_), _) => stack.push(lhs),
+            ExprInner::InfixExpr(lhs, op, rhs) => {
+                stack.extend([rhs.as_ref(), lhs]);
+                if let InfixOp::Assign(_) = op {
+                    assigned_variable(context, lhs, assigned);
+                }
+            }
+            ExprInner::Comma(lhs, rhs) => stack.extend([rhs.as_ref(), lhs]),
+            ExprInner::UnaryExpr(op, operand) => {
+                stack.push(operand);
+                if let UnaryOp::Others(_) = op {
+                    assigned_variable(context, operand, assigned);
+                }
+            }
+            ExprInner::FunctionCall(_, exprs, _) | ExprInner::ArrayElement(_, exprs, _) | ExprInner::ArrayInit(exprs) => {
+                stack.extend(exprs.iter().rev())
+            }
+            ExprInner::Num(_) | ExprInner::Identifier(_) => (),
+        }
+    }
+}
+
+/// 可能尚未赋值的局部变量: 定义时没有初始值，且不在所有路径上都已赋值（见 [`AssignedVars`]）。
+type Unassigned<'a> = HashSet<&'a str>;
+
+/// 按求值顺序检查表达式读取的变量: 读取 `unassigned` 中的变量时产生警告，并把它移出 `unassigned`，
+/// 每个变量只警告一次。表达式中先赋值后读取的变量不警告。
+fn check_reads(formatter: &dyn ErrorFormatter, expr: &Expr, unassigned: &mut Unassigned, warnings: &mut Vec<String>) {
+    if unassigned.is_empty() {
+        return;
+    }
+    // 对变量的赋值在右侧求值之后进行，先压入栈中
+    enum Work<'e> {
+        Expr(&'e Expr),
+        Assign(&'e str, &'e AssignOp),
+    }
+    let mut assigned = HashSet::new();
+    let mut read = |id: &str, assigned: &HashSet<&str>| {
+        if !assigned.contains(id) && unassigned.remove(id) {
+            warnings.push(formatter.read_before_assignment(id));
+        }
+    };
+    let mut stack = vec![Work::Expr(expr)];
+    while let Some(work) = stack.pop() {
+        let expr = match work {
+            Work::Expr(expr) => expr,
+            Work::Assign(id, op) => {
+                if !matches!(op, AssignOp::Assignment) {
+                    read(id, &assigned);
+                }
+                assigned.insert(id);
+                continue;
+            }
+        };
+        match &expr.inner {
+            ExprInner::InfixExpr(lhs, InfixOp::Assign(op), rhs) => {
+                match &lhs.inner {
+                    ExprInner::Identifier(id) => stack.push(Work::Assign(id, op)),
+                    _ => stack.push(Work::Expr(lhs)),
+                }
+                stack.push(Work::Expr(rhs));
+            }
+            ExprInner::InfixExpr(lhs, _, rhs) | ExprInner::Comma(lhs, rhs) => stack.extend([Work::Expr(rhs), Work::Expr(lhs)]),
+            ExprInner::UnaryExpr(_, operand) => stack.push(Work::Expr(operand)),
+            ExprInner::Identifier(id) => read(id, &assigned),
+            ExprInner::FunctionCall(_, exprs, _) | ExprInner::ArrayElement(_, exprs, _) | ExprInner::ArrayInit(exprs) => {
+                stack.extend(exprs.iter().rev().map(Work::Expr))
+            }
+            ExprInner::Num(_) => (),
+        }
+    }
+}
+
+fn check_init_list_reads(
+    formatter: &dyn ErrorFormatter,
+    init_list: &InitList,
+    unassigned: &mut Unassigned,
+    warnings: &mut Vec<String>,
+) {
+    for item in init_list {
+        match item {
+            InitListItem::InitList(init_list) => check_init_list_reads(formatter, init_list, unassigned, warnings),
+            InitListItem::Expr(expr) => check_reads(formatter, expr, unassigned, warnings),
+        }
+    }
+}
+
+/// 检查语句块，返回块执行结束时已赋值的外层变量。
+///
+/// `return`、`break`、`continue` 与 `goto` 之后的赋值不再计入；if 语句取两个分支的交集；
+/// while 语句只计入条件中的赋值。标签处可能由 goto 跳入，此前的赋值都不再计入。
+/// 块内定义的变量以及被块内定义遮蔽的同名变量都不出现在结果中。
+///
+/// `unassigned` 是进入块时可能尚未赋值的变量。块中按顺序减去每条语句一定赋值的变量，读取剩下的变量时产生
+/// 警告；标签处不恢复被减去的变量，由 goto 跳过的赋值不会产生警告。
+fn process_block<'a>(
+    context: &mut SymbolTable<'a>,
+    block: &'a mut Block,
+    return_void: bool,
+    in_while: bool,
+    labels: &mut HashMap<&'a str, bool>,
+    unassigned: &Unassigned<'a>,
+    warnings: &mut Vec<String>,
+) -> Result<AssignedVars<'a>, String> {
+    // 定义之后的语句与定义都没有使用的局部变量与数组，在检查（折叠常量）之前确定
+    let items = block.as_slice();
+    let unused: Vec<bool> = (0..items.len())
+        .map(|index| match &items[index] {
+            BlockItem::Def(definition) => match definition.as_ref() {
+                VariableDef(id, _) | ArrayDefTmp { id, .. } | ArrayDef { id, .. } => !uses(&items[index + 1..], id),
+                _ => false,
+            },
+            _ => false,
+        })
+        .collect();
+    context.enter_scope();
+    let mut unassigned = unassigned.clone();
+    let mut assigned = AssignedVars::new();
+    let mut exited = false;
+    for (block_item, unused) in block.iter_mut().zip(unused) {
+        let mut exits = false;
+        let nested = match block_item {
+            BlockItem::Def(definition) => {
+                // 初始值中的同名标识符指外层的定义，先检查读取，再登记新的定义
+                let uninitialized = match definition.as_ref() {
+                    VariableDef(_, Some(init)) => {
+                        check_reads(context.formatter(), init, &mut unassigned, warnings);
+                        false
+                    }
+                    ArrayDefTmp {
+                        init_list: Some(init_list),
+                        ..
+                    }
+                    | ArrayDef {
+                        init_list: Some(init_list),
+                        ..
+                    } => {
+                        check_init_list_reads(context.formatter(), init_list, &mut unassigned, warnings);
+                        false
+                    }
+                    definition => matches!(definition, VariableDef(_, None)),
+                };
+                let id = process_definition(context, definition, warnings)?;
+                if unused {
+                    warnings.push(context.formatter().unused_variable(id));
+                }
+                match uninitialized {
+                    true => unassigned.insert(id),
+                    false => unassigned.remove(id),
+                };
+                AssignedVars::new()
+            }
+            BlockItem::Block(block) => {
+                if contains_label(block) {
+                    assigned.clear();
+                }
+                process_block(context, block, return_void, in_while, labels, &unassigned, warnings)?
+            }
+            BlockItem::Statement(statement) => match statement.as_mut() {
+                Statement::Expr(expr) => {
+                    expr.check_expr(context)?;
+                    check_reads(context.formatter(), expr, &mut unassigned, warnings);
+                    let mut nested = AssignedVars::new();
+                    collect_assigned(context, expr, &mut nested);
+                    nested
+                }
+                Statement::If {
+                    condition,
+                    then_block,
+                    else_block,
+                } => match condition.expr_type(context)? {
+                    Int => {
+                        check_reads(context.formatter(), condition, &mut unassigned, warnings);
+                        match condition.inner {
+                            ExprInner::Num(i) if i == 0 || !else_block.is_empty() => {
+                                warnings.push(context.formatter().constant_if(i))
+                            }
+                            _ => (),
+                        }
+                        if contains_label(then_block) || contains_label(else_block) {
+                            assigned.clear();
+                        }
+                        let mut nested = AssignedVars::new();
+                        collect_assigned(context, condition, &mut nested);
+                        unassigned.retain(|id| !nested.contains(id));
+                        let then_assigned =
+                            process_block(context, then_block, return_void, in_while, labels, &unassigned, warnings)?;
+                        let else_assigned =
+                            process_block(context, else_block, return_void, in_while, labels, &unassigned, warnings)?;
+                        nested.extend(then_assigned.intersection(&else_assigned));
+                        nested
+                    }
+                    type_ => return Err(context.formatter().condition_type("if", &type_.to_string())),
+                },
+                Statement::While { condition, block } => match condition.expr_type(context)? {
+                    Int => {
+                        check_reads(context.formatter(), condition, &mut unassigned, warnings);
+                        match condition.inner {
+                            ExprInner::Num(i) if i == 0 || !exits_loop(block, block, false) => {
+                                warnings.push(context.formatter().constant_while(i))
+                            }
+                            _ => (),
+                        }
+                        if contains_label(block) {
+                            assigned.clear();
+                        }
+                        let mut nested = AssignedVars::new();
+                        collect_assigned(context, condition, &mut nested);
+                        unassigned.retain(|id| !nested.contains(id));
+                        process_block(context, block, return_void, true, labels, &unassigned, warnings)?;
+                        nested
+                    }
+                    type_ => return Err(context.formatter().condition_type("while", &type_.to_string())),
+                },
+                Statement::Return(expr) => {
+                    let mut nested = AssignedVars::new();
+                    match (expr, return_void) {
+                        (None, true) => (),
+                        (None, false) => return Err(context.formatter().missing_return_value()),
+                        (Some(expr), true) => return Err(context.formatter().unexpected_return_value(&expr_str(expr))),
+                        (Some(expr), false) => {
+                            if !matches!(expr.expr_type(context)?, Int) {
+                                return Err(context.formatter().return_type(&expr_str(expr)));
+                            }
+                            check_reads(context.formatter(), expr, &mut unassigned, warnings);
+                            collect_assigned(context, expr, &mut nested);
+                        }
+                    }
+                    exits = true;
+                    nested
+                }
+                Statement::Break => {
+                    if !in_while {
+                        return Err(context.formatter().outside_loop("break"));
+                    }
+                    exits = true;
+                    AssignedVars::new()
+                }
+                Statement::Continue => {
+                    if !in_while {
+                        return Err(context.formatter().outside_loop("continue"));
+                    }
+                    exits = true;
+                    AssignedVars::new()
+                }
+                Statement::Goto(label) => {
+                    if labels.get(label.as_str()) == Some(&true) {
+                        return Err(context.formatter().backward_goto(label));
+                    }
+                    labels.insert(label, false);
+                    exits = true;
+                    AssignedVars::new()
+                }
+                Statement::Label(label) => {
+                    if labels.insert(label, true) == Some(true) {
+                        return Err(context.formatter().duplicate_label(label));
+                    }
+                    assigned.clear();
+                    AssignedVars::new()
+                }
+            },
+        };
+        unassigned.retain(|id| !nested.contains(id));
+        if !exited {
+            assigned.extend(nested);
+        }
+        exited |= exits;
+    }
+    let locals = context.scopes.last().unwrap();
+    assigned.retain(|id| !locals.contains_key(id));
+    context.exit_scope();
+    Ok(assigned)
+}
+
+fn collect_identifiers<'b>(expr: &'b Expr, identifiers: &mut Vec<&'b str>) {
+    let mut stack = vec![expr];
+    while let Some(expr) = stack.pop() {
+        match &expr.inner {
+            ExprInner::InfixExpr(lhs, _, rhs) | ExprInner::Comma(lhs, rhs) => stack.extend([rhs.as_ref(), lhs]),
+            ExprInner::UnaryExpr(_, expr) => stack.push(expr),
+            ExprInner::Num(_) => (),
+            ExprInner::Identifier(id) => identifiers.push(id),
+            ExprInner::FunctionCall(_, exprs, _) | ExprInner::ArrayInit(exprs) => stack.extend(exprs.iter().rev()),
+            ExprInner::ArrayElement(id, exprs, _) => {
+                identifiers.push(id);
+                stack.extend(exprs.iter().rev());
+            }
+        }
+    }
+}
+
+fn collect_init_list_identifiers<'b>(init_list: &'b InitList, identifiers: &mut Vec<&'b str>) {
+    for item in init_list {
+        match item {
+            InitListItem::InitList(init_list) => collect_init_list_identifiers(init_list, identifiers),
+            InitListItem::Expr(expr) => collect_identifiers(expr, identifiers),
+        }
+    }
+}
+
+fn sort_global_consts<T>(definitions: Vec<(T, &mut Definition)>) -> Vec<(T, &mut Definition)> {
+    let dependencies: Vec<Vec<usize>> = {
+        let names: Vec<&str> = definitions
+            .iter()
+            .map(|(_, definition)| risk!(&**definition, ConstVariableDefTmp(id, _) | ConstArrayDefTmp { id, .. } | ConstVariableDef(id, _) | ConstArrayDef { id, .. } => id.as_str()))
+            .collect();
+        definitions
+            .iter()
+            .enumerate()
+            .map(|(i, (_, definition))| {
+                let mut identifiers = Vec::new();
+                match &**definition {
+                    ConstVariableDefTmp(_, init) => collect_identifiers(init, &mut identifiers),
+                    ConstArrayDefTmp { lengths, init_list, .. } => {
+                        lengths.iter().for_each(|expr| collect_identifiers(expr, &mut identifiers));
+                        collect_init_list_identifiers(init_list, &mut identifiers);
+                    }
+                    ConstVariableDef(..) | ConstArrayDef { .. } => (),
+                    _ => unreachable!(),
+                }
+                (0..names.len())
+                    .filter(|&j| j != i && identifiers.contains(&names[j]))
+                    .collect()
+            })
+            .collect()
+    };
+    let mut definitions: Vec<_> = definitions.into_iter().map(Some).collect();
+    let mut sorted = Vec::new();
+    while sorted.len() < definitions.len() {
+        // 存在循环依赖时按原顺序处理，由 const_eval 报告错误
+        let ready = (0..definitions.len())
+            .find(|&i| definitions[i].is_some() && dependencies[i].iter().all(|&j| definitions[j].is_none()))
+            .or_else(|| definitions.iter().position(Option::is_some))
+            .unwrap();
+        sorted.push(definitions[ready].take().unwrap());
+    }
+    sorted
+}
+
+/// 已求值的全局常量。常量按依赖关系先于其他全局定义求值，但只有全局数组的定义可以使用之后才定义的常量，
+/// 其他全局定义、函数签名与函数体只能使用之前定义的常量；不可见的常量暂时从全局作用域中取出。
+/// `T` 标明常量所在的位置，用于报告错误。
+struct GlobalConsts<'a, T> {
+    /// 按在源代码中的顺序排列: 顺序、位置、标识符、符号表项与当前是否在全局作用域中
+    consts: Vec<(usize, T, &'a str, SymbolTableItem<'a>, bool)>,
+}
+
+impl<'a, T: Copy> GlobalConsts<'a, T> {
+    /// 从全局作用域中取出已登记的常量，`consts` 中是它们的顺序、位置与标识符。
+    fn take(context: &mut SymbolTable<'a>, mut consts: Vec<(usize, T, &'a str)>) -> Self {
+        consts.sort_by_key(|(order, _, _)| *order);
+        let consts = consts
+            .into_iter()
+            .map(|(order, at, id)| (order, at, id, context.scopes[0].remove(id).unwrap(), false))
+            .collect();
+        Self { consts }
+    }
+
+    /// 只让顺序在 `order` 之前的常量可见，`all` 时所有常量都可见。常量与之后的全局定义重名时报错。
+    fn show(&mut self, context: &mut SymbolTable<'a>, order: usize, all: bool) -> Result<(), (T, String)> {
+        for (position, at, id, item, shown) in self.consts.iter_mut() {
+            let visible = all || *position < order;
+            match (visible, *shown) {
+                (true, false) => insert(context, id, item.clone()).map_err(|e| (*at, e))?,
+                (false, true) => {
+                    context.scopes[0].remove(*id);
+                }
+                _ => (),
+            }
+            *shown = visible;
+        }
+        Ok(())
+    }
+}
+
+fn is_global_array(task: &GlobalTask) -> bool {
+    matches!(task, GlobalTask::Def(ArrayDefTmp { .. } | ArrayDef { .. }))
+}
+
+fn global_item_id(item: &GlobalItem) -> &str {
+    match item {
+        GlobalItem::Def(
+            ConstVariableDefTmp(id, _)
+            | ConstVariableDef(id, _)
+            | ConstArrayDefTmp { id, .. }
+            | ConstArrayDef { id, .. }
+            | VariableDef(id, _)
+            | ArrayDefTmp { id, .. }
+            | ArrayDef { id, .. },
+        ) => id,
+        GlobalItem::FuncDef { id, .. } => id,
+    }
+}
+
+/// 数组各维的长度，必须是正的常量。长度取自 const_eval 返回的 i32，不依赖表达式被折叠为 Num，
+/// 先判断符号再转换为 usize，负数不会变成很大的长度。
+fn array_lengths(context: &SymbolTable, exprs: &mut [Expr]) -> Result<Vec<usize>, String> {
+    let lengths: Vec<usize> = exprs
+        .iter_mut()
+        .map(|expr| match expr.const_eval(context)? {
+            0 => Err(context.formatter().zero_length()),
+            len if len < 0 => Err(context.formatter().negative_length(&expr_str(expr))),
+            len => Ok(len as usize),
+        })
+        .collect::<Result<_, _>>()?;
+    // 每个元素 4 字节，整个数组必须能放进 32 位的地址空间；此后计算元素个数时不会溢出
+    let size = lengths.iter().try_fold(4usize, |size, &len| size.checked_mul(len));
+    match size.filter(|&size| size <= u32::MAX as usize) {
+        Some(_) => Ok(lengths),
+        None => Err(context.formatter().array_too_large(&format!("int{}", lengths_str(&lengths)))),
+    }
+}
+
+fn process_parameters(context: &SymbolTable, parameter_list: &mut [Parameter]) -> Result<(), String> {
+    for p in parameter_list.iter_mut() {
+        if let Parameter::PointerTmp(id, exprs) = p {
+            let lengths = array_lengths(context, exprs)?;
+            *p = Parameter::Pointer(take(id), lengths)
+        }
+    }
+    Ok(())
+}
+
+fn process_function_body<'a>(
+    context: &mut SymbolTable<'a>,
+    return_void: bool,
+    parameter_list: &'a [Parameter],
+    block: &'a mut Block,
+    warnings: &mut Vec<String>,
+) -> Result<(), String> {
+    context.enter_scope();
+    for p in parameter_list.iter() {
+        match p {
+            Parameter::Int(identifier) => insert(context, identifier, Variable)?,
+            Parameter::Pointer(identifier, lengths) => insert(context, identifier, SymbolTableItem::Pointer(lengths))?,
+            _ => unreachable!(),
+        }
+    }
+    let mut labels = HashMap::new();
+    process_block(context, block, return_void, false, &mut labels, &Unassigned::new(), warnings)?;
+    // 有多个标签不存在时报告名字最小的一个，不依赖哈希表的遍历顺序
+    if let Some(label) = labels.iter().filter(|(_, defined)| !**defined).map(|(label, _)| label).min() {
+        return Err(context.formatter().undefined_label(label));
+    }
+    context.exit_scope();
+    Ok(())
+}
+
+enum GlobalTask<'a> {
+    Def(&'a mut Definition),
+    Body(bool, &'a [Parameter], &'a mut Block),
+}
+
+fn builtin<'a>(ret: Type<'a>, params: Vec<Type<'a>>) -> SymbolTableItem<'a> {
+    Function {
+        ret,
+        params,
+        is_builtin: true,
+    }
+}
+
+/// 插桩插入的调用所用的运行时库函数。只有对应的插桩打开时才登记，普通程序既不能调用，也可以定义同名的函数。
+fn hook<'a>(id: &str) -> SymbolTableItem<'a> {
+    match id {
+        coverage::WRITE_COUNTERS | profile::WRITE_PROFILE => builtin(Void, vec![Int, Pointer(&[])]),
+        bounds::CHECK_BOUND => builtin(Int, vec![Int, Int, Int]),
+        overflow::CHECK_OVERFLOW => builtin(Int, vec![Int, Int, Int, Int]),
+        _ => unreachable!(),
+    }
+}
+
+fn builtin_scope<'a>(hooks: &[&'a str]) -> HashMap<&'a str, SymbolTableItem<'a>> {
+    let mut scope = HashMap::from([
+        ("getint", builtin(Int, Vec::new())),
+        ("getch", builtin(Int, Vec::new())),
+        ("getarray", builtin(Int, vec![Pointer(&[])])),
+        ("putint", builtin(Void, vec![Int])),
+        ("putch", builtin(Void, vec![Int])),
+        ("putarray", builtin(Int, vec![Int, Pointer(&[])])),
+        ("starttime", builtin(Void, Vec::new())),
+        ("stoptime", builtin(Void, Vec::new())),
+        ("if", Keyword),
+        ("while", Keyword),
+        ("break", Keyword),
+        ("continue", Keyword),
+        ("return", Keyword),
+        ("int", Keyword),
+        ("const", Keyword),
+        ("void", Keyword),
+        ("goto", Keyword),
+    ]);
+    scope.extend(hooks.iter().map(|&id| (id, hook(id))));
+    scope
+}
+
+/// 登记函数签名，返回函数体的检查任务。
+fn register_function<'a>(
+    context: &mut SymbolTable<'a>,
+    return_void: bool,
+    id: &'a str,
+    parameter_list: &'a mut [Parameter],
+    block: &'a mut Block,
+) -> Result<GlobalTask<'a>, String> {
+    process_parameters(context, parameter_list)?;
+    let parameter_list: &[Parameter] = parameter_list;
+    let parameter_type = parameter_list
+        .iter()
+        .map(|p| match p {
+            Parameter::Int(_) => Int,
+            Parameter::Pointer(_, lengths) => Pointer(lengths),
+            _ => unreachable!(),
+        })
+        .collect();
+    let return_type = if return_void { Void } else { Int };
+    let function = Function {
+        ret: return_type,
+        params: parameter_type,
+        is_builtin: false,
+    };
+    insert(context, id, function)?;
+    Ok(GlobalTask::Body(return_void, parameter_list, block))
+}
+
+fn check_global_definition<'a>(
+    context: &mut SymbolTable<'a>,
+    definition: &'a mut Definition,
+    warnings: &mut Vec<String>,
+) -> Result<(), String> {
+    match definition {
+        VariableDef(id, Some(init)) => check_global_init(context, id, init)?,
+        ArrayDefTmp {
+            id,
+            init_list: Some(init_list),
+            ..
+        }
+        | ArrayDef {
+            id,
+            init_list: Some(init_list),
+            ..
+        } => check_global_init_list(context, id, init_list)?,
+        _ => (),
+    }
+    process_definition(context, definition, warnings)?;
+    Ok(())
+}
+
+fn check_main(context: &SymbolTable) -> Result<(), String> {
+    match context.search("main") {
+        Some(Function { ret: Int, params, .. }) if params.is_empty() => Ok(()),
+        _ => Err(context.formatter().missing_main()),
+    }
+}
+
+/// 检查若干个翻译单元，它们共享同一个全局作用域，检查通过后按顺序合并为一个翻译单元。
+/// 错误与警告都带有所在翻译单元的下标；`names` 仅用于在跨文件的重复定义错误中指明另一处定义。
+/// 检查后合并的语法树与警告，或第一个错误。
+pub type CheckResult = Result<(TranslationUnit, Vec<Diagnostic>), Diagnostic>;
+
+pub fn check(units: Vec<TranslationUnit>, names: &[&str]) -> CheckResult {
+    check_with_tracer(units, names, &ChineseFormatter, None).0
+}
+
+/// 同 check，检查插桩后的语法树。`hooks` 是插桩插入的调用所用的运行时库函数，登记为内置函数。
+pub fn check_instrumented(units: Vec<TranslationUnit>, names: &[&str], hooks: &[&'static str]) -> CheckResult {
+    check_units_with(units, names, hooks, &ChineseFormatter, None).0
+}
+
+/// 同 check_with_formatter，检查过程记录在 `tracer` 中。出错时也返回出错之前的记录。
+pub fn check_with_tracer(
+    units: Vec<TranslationUnit>,
+    names: &[&str],
+    formatter: &dyn ErrorFormatter,
+    tracer: Option<Tracer>,
+) -> (CheckResult, Option<Tracer>) {
+    check_units_with(units, names, &[], formatter, tracer)
+}
+
+fn check_units_with(
+    mut units: Vec<TranslationUnit>,
+    names: &[&str],
+    hooks: &[&'static str],
+    formatter: &dyn ErrorFormatter,
+    tracer: Option<Tracer>,
+) -> (CheckResult, Option<Tracer>) {
+    let mut context = SymbolTable::new(builtin_scope(hooks));
+    context.formatter = formatter;
+    context.tracer = tracer;
+    let result = check_units(&mut units, names, &mut context);
+    let tracer = context.tracer.take();
+    (
+        result.map(|warnings| (units.into_iter().flatten().collect(), warnings)),
+        tracer,
+    )
+}
+
+/// 同 check，诊断信息由 `formatter` 生成。
+pub fn check_with_formatter(units: Vec<TranslationUnit>, names: &[&str], formatter: &dyn ErrorFormatter) -> CheckResult {
+    check_with_tracer(units, names, formatter, None).0
+}
+
+fn check_units<'a>(
+    units: &'a mut [TranslationUnit],
+    names: &[&str],
+    context: &mut SymbolTable<'a>,
+) -> Result<Vec<Diagnostic>, Diagnostic> {
+    let mut warnings = Vec::new();
+    let mut defined_in = HashMap::new();
+    for (file, unit) in units.iter().enumerate() {
+        for item in unit.iter() {
+            let id = global_item_id(item);
+            match defined_in.get(id) {
+                Some(&other) if other != file => {
+                    return Err((file, context.formatter().duplicate_global(id, names[other], names[file])));
+                }
+                _ => defined_in.insert(id, file),
+            };
+        }
+    }
+    let (consts, others): (Vec<_>, Vec<_>) = units
+        .iter_mut()
+        .enumerate()
+        .flat_map(|(file, unit)| unit.iter_mut().map(move |item| (file, item)))
+        .enumerate()
+        .partition(|(_, (_, i))| matches!(i.as_ref(), GlobalItem::Def(ConstVariableDefTmp(..) | ConstArrayDefTmp { .. } | ConstVariableDef(..) | ConstArrayDef { .. })));
+    let consts = consts
+        .into_iter()
+        .map(|(order, (file, i))| ((order, file), risk!(i.as_mut(), GlobalItem::Def(definition) => definition)))
+        .collect();
+    let mut defined = Vec::new();
+    for ((order, file), definition) in sort_global_consts(consts) {
+        let mut item_warnings = Vec::new();
+        let result = process_definition(context, definition, &mut item_warnings);
+        let id = context
+            .trace_result("全局常量定义", result, &item_warnings)
+            .map_err(|e| (file, e))?;
+        defined.push((order, file, id));
+        warnings.extend(item_warnings.into_iter().map(|warning| (file, warning)));
+    }
+    let mut consts = GlobalConsts::take(context, defined);
+    // 先登记所有函数的签名，函数之间（包括跨文件）的调用与定义顺序无关
+    let mut tasks = Vec::new();
+    for (order, (file, i)) in others {
         match i.as_mut() {
-            GlobalItem::Def(definition) => process_definition(&mut context, definition)?,
+            GlobalItem::Def(definition) => tasks.push((order, file, GlobalTask::Def(definition))),
             GlobalItem::FuncDef {
                 return_void,
                 id,
                 parameter_list,
                 block,
             } => {
-                for p in parameter_list.iter_mut() {
-                    if let Parameter::PointerTmp(id, exprs) = p {
-                        for expr in exprs.iter_mut() {
-                            expr.const_eval(&context)?;
-                        }
-                        *p = Parameter::Pointer(
-                            take(id),
-                            exprs
-                                .iter()
-                                .map(|p| risk!(p.inner, ExprInner::Num(i) => i as usize))
-                                .collect(),
-                        )
-                    }
+                consts.show(context, order, false)?;
+                let result = register_function(context, *return_void, id, parameter_list, block);
+                tasks.push((order, file, context.trace_result("函数签名", result, &[]).map_err(|e| (file, e))?));
+            }
+        }
+    }
+    for (order, file, task) in tasks {
+        consts.show(context, order, is_global_array(&task))?;
+        let mut item_warnings = Vec::new();
+        let (rule, result) = match task {
+            GlobalTask::Def(definition) => (
+                "全局变量定义",
+                check_global_definition(context, definition, &mut item_warnings),
+            ),
+            GlobalTask::Body(return_void, parameter_list, block) => (
+                "函数体",
+                process_function_body(context, return_void, parameter_list, block, &mut item_warnings),
+            ),
+        };
+        context.trace_result(rule, result, &item_warnings).map_err(|e| (file, e))?;
+        warnings.extend(item_warnings.into_iter().map(|warning| (file, warning)));
+    }
+    consts.show(context, usize::MAX, true)?;
+    let result = check_main(context);
+    context.trace_result("main 函数", result, &[]).map_err(|e| (0, e))?;
+    Ok(warnings)
+}
+
+/// 一个函数体的检查结果：出错时为错误信息，否则为警告。
+pub type BodyResult = Result<Vec<String>, String>;
+
+/// 全局定义或签名出错时，出错的项的下标（没有 main 函数时为 None）与错误信息。
+pub type ItemError = (Option<usize>, String);
+
+/// 逐项检查单个翻译单元，供增量检查使用。全局定义与函数签名总是全部处理，函数体只检查
+/// `selected` 中对应位置为 true 的，一个函数体出错不影响其他函数体。返回产生了警告的全局定义与各个被检查的
+/// 函数体的下标与结果。
+pub fn check_items(unit: &mut TranslationUnit, selected: &[bool]) -> Result<Vec<(usize, BodyResult)>, ItemError> {
+    let mut context = SymbolTable::new(builtin_scope(&[]));
+    let (consts, others): (Vec<_>, Vec<_>) = unit
+        .iter_mut()
+        .enumerate()
+        .partition(|(_, i)| matches!(i.as_ref(), GlobalItem::Def(ConstVariableDefTmp(..) | ConstArrayDefTmp { .. } | ConstVariableDef(..) | ConstArrayDef { .. })));
+    let consts = consts
+        .into_iter()
+        .map(|(index, i)| (index, risk!(i.as_mut(), GlobalItem::Def(definition) => definition)))
+        .collect();
+    let mut results = Vec::new();
+    let mut defined = Vec::new();
+    for (index, definition) in sort_global_consts(consts) {
+        let mut warnings = Vec::new();
+        let id = process_definition(&mut context, definition, &mut warnings).map_err(|e| (Some(index), e))?;
+        defined.push((index, index, id));
+        if !warnings.is_empty() {
+            results.push((index, Ok(warnings)));
+        }
+    }
+    let mut consts = GlobalConsts::take(&mut context, defined);
+    let mut tasks = Vec::new();
+    for (index, i) in others {
+        match i.as_mut() {
+            GlobalItem::Def(definition) => tasks.push((index, GlobalTask::Def(definition))),
+            GlobalItem::FuncDef {
+                return_void,
+                id,
+                parameter_list,
+                block,
+            } => {
+                consts.show(&mut context, index, false).map_err(|(index, e)| (Some(index), e))?;
+                let task =
+                    register_function(&mut context, *return_void, id, parameter_list, block).map_err(|e| (Some(index), e))?;
+                tasks.push((index, task));
+            }
+        }
+    }
+    for (index, task) in tasks {
+        consts
+            .show(&mut context, index, is_global_array(&task))
+            .map_err(|(index, e)| (Some(index), e))?;
+        match task {
+            GlobalTask::Def(definition) => {
+                let mut warnings = Vec::new();
+                check_global_definition(&mut context, definition, &mut warnings).map_err(|e| (Some(index), e))?;
+                if !warnings.is_empty() {
+                    results.push((index, Ok(warnings)));
                 }
-                let parameter_type = parameter_list
-                    .iter()
-                    .map(|p| match p {
-                        Parameter::Int(_) => Int,
-                        Parameter::Pointer(_, lengths) => Pointer(lengths),
-                        _ => unreachable!(),
-                    })
-                    .collect();
-                let return_type = if *return_void { Void } else { Int };
-                context.insert_definition(id, Function(return_type, parameter_type))?;
-                context.enter_scope();
-                for p in parameter_list.iter() {
-                    match p {
-                        Parameter::Int(identifier) => context.insert_definition(identifier, Variable)?,
-                        Parameter::Pointer(identifier, lengths) => {
-                            context.insert_definition(identifier, SymbolTableItem::Pointer(lengths))?
-                        }
-                        _ => unreachable!(),
-                    }
+            }
+            GlobalTask::Body(return_void, parameter_list, block) if selected[index] => {
+                let (transaction, mut warnings) = (context.begin_transaction(), Vec::new());
+                let result = process_function_body(&mut context, return_void, parameter_list, block, &mut warnings);
+                // 出错时撤销函数体中尚未退出的作用域
+                match result {
+                    Ok(()) => context.commit(transaction),
+                    Err(_) => context.rollback(transaction),
                 }
-                process_block(&mut context, block, *return_void, false)?;
-                context.exit_scope();
+                results.push((index, result.map(|_| warnings)));
             }
+            GlobalTask::Body(..) => (),
         }
     }
-    match context.search("main") {
-        Some(Function(Int, vec)) if vec.is_empty() => Ok(ast),
-        _ => Err("没有 main 函数，或 main 函数不符合要求".to_string()),
+    consts.show(&mut context, usize::MAX, true).map_err(|(index, e)| (Some(index), e))?;
+    check_main(&context).map_err(|e| (None, e))?;
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parser::build_ast;
+    use super::*;
+
+    const VARIABLE: SymbolTableItem = Variable;
+
+    fn check_code(code: &str) -> Result<(TranslationUnit, Vec<String>), String> {
+        let (ast, warnings) = check(vec![build_ast(code)?], &["test.sy"]).map_err(|(_, e)| e)?;
+        Ok((ast, warnings.into_iter().map(|(_, warning)| warning).collect()))
+    }
+
+    /// 每个用例展开为一个测试。检查结果为 `Result<(), &str>`，与给出的模式匹配。
+    macro_rules! check_cases {
+        ($($name:ident: $code:expr => $expected:pat $(if $guard:expr)?,)*) => {
+            $(
+                #[test]
+                #[allow(clippy::redundant_pattern_matching)]
+                fn $name() {
+                    let result = check_code($code);
+                    let result = result.as_ref().map(|_| ()).map_err(String::as_str);
+                    assert!(matches!(result, $expected $(if $guard)?), "{:?}", result);
+                }
+            )*
+        };
+    }
+
+    /// 在 main 中对表达式求类型，可以使用的标识符见 `expr_type_of`。
+    macro_rules! expr_type_cases {
+        ($($name:ident: $expr:expr => $expected:pat,)*) => {
+            $(
+                #[test]
+                #[allow(clippy::redundant_pattern_matching)]
+                fn $name() {
+                    let result = expr_type_of($expr);
+                    assert!(matches!(result, $expected), "{:?}", result);
+                }
+            )*
+        };
+    }
+
+    fn expr_type_of(expr: &str) -> Result<SimpleType, String> {
+        let code = format!(
+            "int x; int a[2][3]; const int c = 2; void v() {{}} int f(int p[]) {{ return 0; }} int main() {{ {}; return 0; }}",
+            expr
+        );
+        let (ast, _) = check_code(&code)?;
+        let block = risk!(ast.last().unwrap().as_ref(), GlobalItem::FuncDef { block, .. } => block);
+        let statement = risk!(&block.as_slice()[0], BlockItem::Statement(statement) => statement);
+        Ok(risk!(statement.as_ref(), Statement::Expr(expr) => expr.type_))
+    }
+
+    expr_type_cases! {
+        variable_is_int: "x" => Ok(SimpleType::Int),
+        constant_is_int: "c * 3" => Ok(SimpleType::Int),
+        array_row_is_pointer: "a[1]" => Ok(SimpleType::Pointer),
+        array_element_is_int: "a[1][2]" => Ok(SimpleType::Int),
+        assignment_is_int: "x = a[0][0] + 1" => Ok(SimpleType::Int),
+        void_call_is_void: "v()" => Ok(SimpleType::Void),
+        pointer_argument: "f(a[0])" => Ok(SimpleType::Int),
+        pointer_arithmetic: "a + 1" => Err(_),
+        void_in_arithmetic: "v() + 1" => Err(_),
+        assign_to_constant: "c = 1" => Err(_),
+        wrong_pointer_argument: "f(a)" => Err(_),
+        int_as_pointer_argument: "f(x)" => Err(_),
+        array_literal_is_pointer: "{x, c * 2}" => Ok(SimpleType::Pointer),
+        array_literal_argument: "f({1, x = 2})" => Ok(SimpleType::Int),
+        array_literal_of_pointers: "{a[0]}" => Err(_),
+        array_literal_for_two_dimensions: "putarray(1, {{1}})" => Err(_),
+        array_literal_as_int: "x = {1}" => Err(_),
+    }
+
+    check_cases! {
+        self_referential_initializer_without_outer_definition: "int main() { int x = x + 1; return x; }" => Err(_),
+        break_outside_loop: "int main() { if (1) { { break; } } return 0; }" => Err("[E005] break 只能用在循环内"),
+        continue_outside_loop: "void f() { continue; } int main() { return 0; }" => Err("[E005] continue 只能用在循环内"),
+        break_in_nested_block: "int main() { while (1) { if (1) { break; } } return 0; }" => Ok(()),
+        pointer_if_condition: "int f(int arr[]) { if (arr) return 1; return 0; } int main() { return 0; }"
+            => Err("[E004] if 条件应为整型，实际类型为 int*"),
+        pointer_while_condition: "int a[2][3]; int main() { while (a[1]) {} return 0; }" => Err("[E004] while 条件应为整型，实际类型为 int*"),
+        void_if_condition: "void v() {} int main() { if (v()) return 1; return 0; }" => Err("[E004] if 条件应为整型，实际类型为 void"),
+        goto_forward: "int main() { if (1) goto end; putint(1); end: return 0; }" => Ok(()),
+        goto_backward: "int main() { start: putint(1); goto start; return 0; }" => Err(_),
+        goto_undefined_label: "int main() { goto end; return 0; }" => Err(_),
+        goto_undefined_labels: "int main() { goto d; goto b; goto c; goto a; a: return 0; }" => Err("[E006] goto 的目标标签 b 不存在"),
+        goto_duplicate_label: "int main() { goto end; end: end: return 0; }" => Err(_),
+        int_min_literal: "const int x = -2147483648; int main() { return x; }" => Ok(()),
+        literal_out_of_range: "const int x = 2147483648; int main() { return x; }" => Err(_),
+        constant_overflow: "int main() { return 2147483647 + 1; }" => Err(_),
+        constant_division_by_zero: "int main() { return 1 / 0; }" => Err(_),
+        calling_variable: "int main() { int f = 1; return f(5); }" => Err("[E004] 'f' 是变量，不能作为函数调用"),
+        calling_array: "int main() { int f[2]; return f(5); }" => Err("[E004] 'f' 是数组，不能作为函数调用"),
+        calling_undefined_function: "int main() { return f(5); }" => Err("[E001] 函数 'f' 不存在"),
+        // 插桩用的运行时库函数只在插桩时登记
+        calling_instrumentation_hook: "int main() { return _sysy_check_bound(1, 2, 3); }"
+            => Err("[E001] 函数 '_sysy_check_bound' 不存在"),
+        defining_instrumentation_hook: "void _sysy_write_counters() {} int main() { _sysy_write_counters(); return 0; }" => Ok(()),
+        comma_in_constant: "const int c = 1; const int x = (c, 5); int a[x]; int main() { return 0; }" => Ok(()),
+        comma_with_variable_is_not_constant: "int main() { int i = 0; const int x = (i, 5); return x; }"
+            => Err(e) if e.ends_with("不是常量表达式"),
+        non_constant_parameter_length: "int n; int f(int a[][n]) { return 0; }" => Err(_),
+        zero_parameter_length: "int f(int a[][0]) { return 0; } int main() { return 0; }" => Err("[E008] 数组维度不能为零"),
+        negative_parameter_length: "const int N = -2; int f(int a[][2][N]) { return 0; } int main() { return 0; }"
+            => Err(e) if e.ends_with("的值小于等于 0"),
+        zero_length_array: "int main() { int a[0] = {}; return 0; }" => Err("[E008] 数组维度不能为零"),
+        zero_length_const_array: "const int a[0][2] = {}; int main() { return 0; }" => Err("[E008] 数组维度不能为零"),
+        zero_length_from_constant: "const int N = 2; int a[2][N - 2]; int main() { return 0; }" => Err("[E008] 数组维度不能为零"),
+        array_too_large: "int a[65536][65536][65536][65536]; int main() { return 0; }"
+            => Err("[E008] 数组 int[65536][65536][65536][65536] 过大，超出了 32 位地址空间"),
+        parameter_too_large: "int f(int p[][65536][65536]) { return 0; } int main() { return 0; }"
+            => Err("[E008] 数组 int[65536][65536] 过大，超出了 32 位地址空间"),
+        largest_array: "int a[1073741823]; int main() { return 0; }" => Ok(_),
+        negative_length_array: "int main() { int a[-2147483647 - 1]; return 0; }" => Err(e) if e.ends_with("的值小于等于 0"),
+        redefine_builtin_function: "int getint() { return 0; } int main() { return 0; }"
+            => Err("[E002] 函数 getint 是运行时库函数，不能重定义"),
+        redefine_function: "int f() { return 0; } int f() { return 1; } int main() { return 0; }"
+            => Err("[E002] 标识符 f 在当前作用域中已定义为 int ()"),
+        redefine_constant: "int main() { const int x = 5; int x; return 0; }" => Err("[E002] 标识符 x 在当前作用域中已定义为 const int 5"),
+        redefine_parameter: "void f(int a, int a[]) {} int main() { return 0; }" => Err("[E002] 标识符 a 在当前作用域中已定义为 int"),
+        global_const_used_before_definition: "int a[N]; const int N = M + 1; const int M = 2; int main() { return a[N - 1]; }"
+            => Ok(()),
+        function_uses_later_global_const: "int f() { return N; } const int N = 1; int main() { return f(); }"
+            => Err("[E001] N 不存在，或不是整型、数组或指针变量"),
+        variable_uses_later_global_const: "int b = N; const int N = 1; int main() { return b; }"
+            => Err("[E001] N 不存在，或不是整型、数组或指针变量"),
+        parameter_uses_later_global_const: "int f(int a[][N]) { return 0; } const int N = 1; int main() { return 0; }"
+            => Err(e) if e.starts_with("[E001] N 不存在"),
+        array_after_function_uses_later_global_const:
+            "int a[N]; int f() { return a[0] + N; } const int N = 1; int main() { return f(); }"
+            => Err("[E001] N 不存在，或不是整型、数组或指针变量"),
+        function_uses_earlier_global_const: "const int N = 1; int f() { return N; } int main() { return f(); }" => Ok(()),
+        variable_before_global_const_with_same_name: "int N; const int N = 1; int main() { return N; }"
+            => Err("[E002] 标识符 N 在当前作用域中已定义为 int"),
+        global_const_before_variable_with_same_name: "const int N = 1; int N; int main() { return N; }"
+            => Err("[E002] 标识符 N 在当前作用域中已定义为 const int 1"),
+        cyclic_global_consts: "const int A = B; const int B = A; int main() { return 0; }" => Err(_),
+        global_initializer_uses_variable: "int a = 5; int b = a + 1; int main() { return b; }"
+            => Err("[E003] 全局变量 b 的初始化表达式不是常量表达式"),
+        global_init_list_uses_variable: "int a; int b[2] = {1, a}; int main() { return 0; }"
+            => Err("[E003] 全局变量 b 的初始化表达式不是常量表达式"),
+        global_init_list_uses_global_variable: "const int a = 5; int b = a + 1; int c[2] = {a, b}; int main() { return b; }"
+            => Err(_),
+        global_init_list_of_constants: "const int a = 5; int b = a + 1; int c[2] = {a, 2}; int main() { return b; }" => Ok(()),
+        call_function_defined_later: "int main() { return f(1); } int f(int x) { return x; }" => Ok(()),
+        call_main_from_main: "int main() { return main(); }" => Err("[E010] 不允许递归调用 main 函数"),
+        call_main_from_function: "int f() { return main(); } int main() { return f(); }" => Err("[E010] 不允许递归调用 main 函数"),
+        variable_named_main: "int main() { int main = 1; return main; }" => Ok(()),
+        return_without_value_in_int_function: "int f() { return; } int main() { return f(); }"
+            => Err("[E004] int 函数中的 return 语句未返回表达式"),
+        return_value_in_void_function: "void f() { return 1; } int main() { f(); return 0; }"
+            => Err("[E004] 在 void 函数中返回了表达式 1"),
+        return_void_call_in_int_function: "void v() {} int main() { return v(); }"
+            => Err("[E004] return 语句返回的 v() 类型与函数定义不匹配"),
+    }
+
+    #[test]
+    fn read_before_assignment() {
+        let warnings = |code| check_code(code).unwrap().1;
+        assert_eq!(
+            warnings("int main() { int x, y; y = x + x; x += 1; return x + y; }"),
+            ["[W006] 变量 x 在赋值之前被读取"]
+        );
+        assert_eq!(
+            warnings("int main() { int x; x++; int a[2] = {x, 0}; return a[0]; }"),
+            ["[W006] 变量 x 在赋值之前被读取"]
+        );
+        // 内层语句块中的赋值修改外层变量的状态；全局变量、形参与有初始值的变量都已赋值
+        assert!(
+            warnings("int g; int f(int p) { int x; { x = p; } int y = x; return g + y; } int main() { return f(1); }").is_empty()
+        );
+        assert!(warnings("int main() { int x; if (getint()) x = 1; else x = 2; return x; }").is_empty());
+        // 只在一个分支中赋值、只在循环体中赋值，之后的读取都可能读到未赋值的变量
+        assert_eq!(
+            warnings("int main() { int x; if (getint()) x = 1; return x; }"),
+            ["[W006] 变量 x 在赋值之前被读取"]
+        );
+        assert_eq!(
+            warnings("int main() { int x, i = 0; while (i < 2) { x = i; i++; } return x; }"),
+            ["[W006] 变量 x 在赋值之前被读取"]
+        );
+        // 条件中的赋值一定发生；同一表达式中先赋值后读取不警告；内层的同名定义有初始值
+        assert!(warnings("int main() { int x, y; if ((x = getint()) > 0) y = x; else y = -x; return y; }").is_empty());
+        assert_eq!(
+            warnings("int main() { int x; int y = (x = 1, x + 1); { int x = 2; y = y + x; } return y; }"),
+            ["[W002] 标识符 x 遮蔽了外层作用域中的同名定义"]
+        );
+    }
+
+    #[test]
+    fn unused_variables() {
+        let warnings = |code| check_code(code).unwrap().1;
+        assert_eq!(
+            warnings("int main() { int x = 1; int a[2]; const int c = 2; return 0; }"),
+            ["[W001] 局部变量 x 定义后从未使用", "[W001] 局部变量 a 定义后从未使用"]
+        );
+        // 在内层语句块、条件、常量的长度或同名定义的初始值中使用都计入；同名定义之后使用的是新的定义
+        assert!(warnings("int main() { int x = 1; { if (x) return 1; } return 0; }").is_empty());
+        assert!(warnings("int main() { const int n = 2; int a[n] = {}; return a[0]; }").is_empty());
+        assert_eq!(
+            warnings("int main() { int x = 1; { int x = x + 1; return x; } }"),
+            ["[W002] 标识符 x 遮蔽了外层作用域中的同名定义"]
+        );
+        assert_eq!(
+            warnings("int main() { int x = 1; { int y = 0; int x = 2; return x; } }"),
+            [
+                "[W001] 局部变量 x 定义后从未使用",
+                "[W001] 局部变量 y 定义后从未使用",
+                "[W002] 标识符 x 遮蔽了外层作用域中的同名定义"
+            ]
+        );
+    }
+
+    /// 检查过的语法树（常量已折叠、数组定义已展开）再检查一遍，结果与第一遍相同。
+    #[test]
+    fn check_is_idempotent() {
+        let dumps = |ast: &TranslationUnit| {
+            (super::super::json::dump_ast(ast), super::super::dump::dump_ir(ast), super::super::symbols::dump_symbols(ast))
+        };
+        for code in [
+            "const int N = 2 * 3; int g[N / 2][2] = {1, 2, {3}}; int main() { return g[1][0] + N; }",
+            "int f(int a[][2], int n) { int x; if (1) x = n; while (0) {} return a[0][1] + x; }
+             int main() { int a[2][2] = {}; int b = 1; { int b = 2; putint(b); } return f(a, b); }",
+            "int main() { const int c[3] = {1, 2}; int s = 0, i = 0; while (i < 3) { s = s + c[i]; i = i + 1; } return s; }",
+            "const int M = 4; int main() { int x = getint(); if (M > 2) goto end; x = M + 1; end: return x; }",
+            "int a[M]; const int M = 2; int main() { while (M - 2) { break; } return a[M - 1]; }",
+        ] {
+            let (ast, warnings) = check_code(code).unwrap();
+            let (again, warnings_again) = check(vec![ast.clone()], &["test.sy"]).unwrap();
+            assert_eq!(dumps(&again), dumps(&ast), "{}", code);
+            assert_eq!(warnings_again.into_iter().map(|(_, warning)| warning).collect::<Vec<_>>(), warnings, "{}", code);
+        }
+    }
+
+    #[test]
+    fn self_referential_initializer_uses_outer_definition() {
+        let (ast, warnings) = check_code("const int x = 5; int main() { { const int x = x + 1; return x; } }").unwrap();
+        assert_eq!(warnings, ["[W002] 标识符 x 遮蔽了外层作用域中的同名定义"]);
+        let GlobalItem::FuncDef { block, .. } = ast[1].as_ref() else {
+            unreachable!()
+        };
+        let BlockItem::Block(inner) = &block.as_slice()[0] else {
+            unreachable!()
+        };
+        assert!(
+            matches!(&inner.as_slice()[0], BlockItem::Def(def) if matches!(def.as_ref(), ConstVariableDef(id, 6) if id == "x"))
+        );
+    }
+
+    #[test]
+    fn symbol_table_item_display() {
+        let (lengths, values, pointer) = (vec![3, 4], vec![0; 12], vec![4]);
+        assert_eq!(ConstVariable(42).to_string(), "const int 42");
+        assert_eq!(VARIABLE.to_string(), "int");
+        assert_eq!(ConstArray(&lengths, &values).to_string(), "const int[3][4]");
+        assert_eq!(Array(&lengths).to_string(), "int[3][4]");
+        assert_eq!(
+            Function {
+                ret: Int,
+                params: vec![Int, Pointer(&pointer)],
+                is_builtin: false,
+            }
+            .to_string(),
+            "int (int, int*[4])"
+        );
+        assert_eq!(builtin(Void, Vec::new()).to_string(), "void ()");
+        assert_eq!(SymbolTableItem::Pointer(&pointer).to_string(), "int*[4]");
+    }
+
+    #[test]
+    fn pointer_parameter_lengths() {
+        let (ast, _) =
+            check_code("const int N = 2; int f(int a[][N + 1][N * 2]) { return 0; } int main() { return 0; }").unwrap();
+        let parameter_list = risk!(ast[1].as_ref(), GlobalItem::FuncDef { parameter_list, .. } => parameter_list);
+        assert!(matches!(&parameter_list[0], Parameter::Pointer(_, lengths) if lengths == &[3, 4]));
+    }
+
+    #[test]
+    fn iterate_visible_symbols() {
+        let mut context = SymbolTable::new(HashMap::from([("a", VARIABLE), ("b", VARIABLE)]));
+        context.enter_scope();
+        context.insert_definition("a", ConstVariable(1)).unwrap();
+        let symbols: Vec<_> = (&context).into_iter().map(|(id, symbol)| (id, symbol.to_string())).collect();
+        assert_eq!(symbols[0], ("a", "const int 1".to_string()));
+        assert_eq!(symbols.len(), 2);
+        assert!(symbols.contains(&("b", "int".to_string())));
+        assert_eq!(context.symbol_count(), 3);
+        assert_eq!(context.all_symbols_flat(), [(0, "a"), (0, "b"), (1, "a")]);
+    }
+
+    #[test]
+    fn constant_if_condition() {
+        let warnings = |code| check_code(code).unwrap().1;
+        assert_eq!(
+            warnings("const int DEBUG = 0; int main() { if (DEBUG) return 1; return 0; }"),
+            ["[W003] if 的条件是常量 0，then 分支永远不会执行"]
+        );
+        assert_eq!(
+            warnings("int main() { if (2 > 1) return 1; else return 0; }"),
+            ["[W003] if 的条件是常量 1，else 分支永远不会执行"]
+        );
+        assert!(warnings("int main() { if (1) return 1; return 0; }").is_empty());
+        assert!(warnings("int main() { int x = 0; if (x) return 1; else return 0; }").is_empty());
+    }
+
+    #[test]
+    fn constant_while_condition() {
+        let warnings = |code| check_code(code).unwrap().1;
+        assert_eq!(
+            warnings("int main() { while (0) putint(1); return 0; }"),
+            ["[W004] while 的条件是编译期常量，值为 0，循环体永远不会执行"]
+        );
+        assert_eq!(
+            warnings("int main() { while (2) { while (1) break; } return 0; }"),
+            ["[W005] while(2) 是无限循环"]
+        );
+        assert_eq!(
+            warnings("int main() { while (1) { if (getint()) goto a; putint(1); a: putint(2); } return 0; }"),
+            ["[W005] while(1) 是无限循环"]
+        );
+        assert!(warnings("int main() { while (1) { if (getint()) return 0; } return 1; }").is_empty());
+        assert!(warnings("int main() { while (1) { while (getint()) { goto end; } } end: return 0; }").is_empty());
+    }
+
+    #[test]
+    fn parameters_are_visible_in_function_body() {
+        let mut context = SymbolTable::new(HashMap::new());
+        context.enter_scope();
+        context.insert_definition("n", VARIABLE).unwrap();
+        assert!(context.contains_in_current_scope("n"));
+        let mut expr: Expr = ExprInner::Identifier("n".to_string()).into();
+        assert!(matches!(expr.expr_type(&context), Ok(Int)));
+
+        let (_, warnings) = check_code("int f(int n) { int n = 1; return n; } int main() { return f(0); }").unwrap();
+        assert_eq!(warnings, ["[W002] 标识符 n 遮蔽了外层作用域中的同名定义"]);
+    }
+
+    #[test]
+    fn assigned_variables() {
+        let assigned_in = |body: &str| {
+            let mut ast = build_ast(&format!("int main() {{ {} }}", body)).unwrap();
+            let block = risk!(ast[0].as_mut(), GlobalItem::FuncDef { block, .. } => block);
+            let mut context = SymbolTable::new(HashMap::from([("x", VARIABLE), ("y", VARIABLE), ("z", VARIABLE)]));
+            let assigned = process_block(
+                &mut context,
+                block,
+                false,
+                false,
+                &mut HashMap::new(),
+                &Unassigned::new(),
+                &mut Vec::new(),
+            )
+            .unwrap();
+            let mut assigned: Vec<String> = assigned.into_iter().map(String::from).collect();
+            assigned.sort();
+            assigned
+        };
+        for (body, expected) in [
+            ("x = 1; y += 2; z;", vec!["x", "y"]),
+            ("x = 1; return 0; y = 1;", vec!["x"]),
+            ("if (z) { x = 1; } else { x = 2; y = 3; }", vec!["x"]),
+            ("if (z) { x = 1; }", vec![]),
+            ("if ((y = z)) { x = 1; return 0; } else { x = 2; }", vec!["x", "y"]),
+            ("while ((x = z)) { y = 1; }", vec!["x"]),
+            ("z && (x = 1); y++;", vec!["y"]),
+            ("int x = 1; x = 2; { int y; y = 1; } z = 1;", vec!["z"]),
+            ("x = 1; if (z) { goto end; } y = 1; end: z = 1;", vec!["z"]),
+            ("x = 1; if (z) { goto end; } { end: y = 1; }", vec!["y"]),
+        ] {
+            assert_eq!(assigned_in(body), expected, "{}", body);
+        }
+    }
+
+    /// 深度为 `depth` 的加法，`left` 时向左结合（`x + x + ...`），否则向右嵌套（`x + (x + (...))`）。
+    fn nested_addition(leaf: &ExprInner, depth: usize, left: bool) -> Expr {
+        (0..depth).fold(leaf.clone().into(), |expr, _| {
+            let (lhs, rhs) = match left {
+                true => (expr, leaf.clone().into()),
+                false => (leaf.clone().into(), expr),
+            };
+            ExprInner::InfixExpr(Box::new(lhs), InfixOp::Arith(ArithmeticOp::Add), Box::new(rhs)).into()
+        })
+    }
+
+    #[test]
+    fn deeply_nested_expressions() {
+        // 在测试线程默认的栈上运行，求值、检查与释放都不能递归
+        const DEPTH: usize = 100_000;
+        let context = SymbolTable::new(HashMap::new());
+        let one = ExprInner::Num(1);
+        assert_eq!(nested_addition(&one, DEPTH, true).const_eval(&context), Ok(DEPTH as i32 + 1));
+        assert_eq!(nested_addition(&one, DEPTH, false).const_eval(&context), Ok(DEPTH as i32 + 1));
+
+        let x = ExprInner::Identifier("x".to_string());
+        let main = |expr: Expr| GlobalItem::FuncDef {
+            return_void: false,
+            id: "main".to_string(),
+            parameter_list: Vec::new(),
+            block: [
+                BlockItem::Def(Box::new(VariableDef(
+                    "x".to_string(),
+                    Some(ExprInner::FunctionCall("getint".to_string(), Vec::new(), 1).into()),
+                ))),
+                BlockItem::Statement(Box::new(Statement::Expr(expr))),
+                BlockItem::Statement(Box::new(Statement::Return(Some(
+                    ExprInner::Identifier("g".to_string()).into(),
+                )))),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        for left in [true, false] {
+            let unit = vec![
+                Box::new(GlobalItem::Def(VariableDef(
+                    "g".to_string(),
+                    Some(nested_addition(&one, DEPTH, left)),
+                ))),
+                Box::new(main(nested_addition(&x, DEPTH, left))),
+            ];
+            let (ast, warnings) = check(vec![unit], &["test.sy"]).unwrap();
+            assert!(warnings.is_empty(), "{:?}", warnings);
+            assert!(matches!(
+                &*ast[0],
+                GlobalItem::Def(VariableDef(
+                    _,
+                    Some(Expr {
+                        inner: ExprInner::Num(100_001),
+                        ..
+                    })
+                ))
+            ));
+        }
+        // 出错时子表达式都放回原处
+        let mut expr = nested_addition(&ExprInner::Identifier("y".to_string()), 3, true);
+        assert_eq!(
+            expr.const_eval(&context),
+            Err("[E001] y 不存在，或不是整型、数组或指针变量".to_string())
+        );
+        assert_eq!(expr_str(&expr), "y + y + y + y");
+    }
+
+    #[test]
+    fn const_eval_results_are_cached() {
+        let mut expr: Expr = ExprInner::InfixExpr(
+            Box::new(ExprInner::Identifier("A".to_string()).into()),
+            InfixOp::Arith(ArithmeticOp::Multiply),
+            Box::new(ExprInner::Num(3).into()),
+        )
+        .into();
+        assert_eq!(
+            expr.const_eval(&SymbolTable::new(HashMap::from([("A", ConstVariable(2))]))),
+            Ok(6)
+        );
+        assert!(matches!(expr.inner, ExprInner::Num(6)));
+        // 第二次求值不再查找 A
+        assert_eq!(expr.const_eval(&SymbolTable::new(HashMap::new())), Ok(6));
+    }
+
+    #[test]
+    fn const_array_flat_index() {
+        use super::super::expr::const_eval::flat_index;
+        assert_eq!(flat_index(&[1, 2], &[2, 3]), Some(5));
+        assert_eq!(flat_index(&[0, 0], &[2, 3]), Some(0));
+        assert_eq!(flat_index(&[1, 0, 3], &[2, 3, 4]), Some(15));
+        assert_eq!(flat_index(&[1, 2, 3], &[2, 3, 4]), Some(23));
+        assert_eq!(flat_index(&[2, 0], &[2, 3]), None);
+        assert_eq!(flat_index(&[0, 3], &[2, 3]), None);
+        assert_eq!(flat_index(&[0, -1], &[2, 3]), None);
+
+        let (lengths, values) = (vec![2, 3, 4], (0..24).map(|i| i * 10).collect());
+        let context = SymbolTable::new(HashMap::from([("a", ConstArray(&lengths, &values))]));
+        let element = |indices: [i64; 3]| {
+            let subscripts = indices.iter().map(|&i| ExprInner::Num(i).into()).collect();
+            Expr::from(ExprInner::ArrayElement("a".to_string(), subscripts, false)).const_eval(&context)
+        };
+        assert_eq!(element([0, 0, 0]), Ok(0));
+        assert_eq!(element([0, 2, 3]), Ok(110));
+        assert_eq!(element([1, 2, 3]), Ok(230));
+        assert_eq!(element([1, 3, 0]), Err("[E007] 下标超出范围".to_string()));
+    }
+
+    fn trace_of(code: &str, const_eval: bool, checker: bool) -> Vec<String> {
+        let (_, tracer) = check_with_tracer(
+            vec![build_ast(code).unwrap()],
+            &["test.sy"],
+            &ChineseFormatter,
+            Some(Tracer::new(const_eval, checker)),
+        );
+        tracer.unwrap().into_lines()
+    }
+
+    #[test]
+    fn trace_const_eval() {
+        let trace = trace_of(
+            "const int N = 2 * 3;\nint a[N - 1];\nint main() { return 2147483647 + N; }",
+            true,
+            false,
+        );
+        assert_eq!(
+            trace,
+            [
+                "const-eval: 2 * 3 => 6",
+                "const-eval:   2 => 2",
+                "const-eval:   3 => 3",
+                "const-eval: N - 1 => 5",
+                "const-eval:   N => 6",
+                "const-eval:   1 => 1",
+                "const-eval: 2147483647 + N => 2147483653",
+                "const-eval:   2147483647 => 2147483647",
+                "const-eval:   N => 6",
+            ]
+        );
+    }
+
+    #[test]
+    fn trace_checker() {
+        let trace = trace_of("int x;\nint main() { int y = x; { int x = y; } return z; }", false, true);
+        assert_eq!(
+            trace,
+            [
+                "checker: x 解析为全局作用域中的 int",
+                "checker: y 未定义",
+                "checker: y 解析为第 2 层作用域中的 int",
+                "checker: x 解析为全局作用域中的 int",
+                "checker: z 未定义",
+                "checker: 函数体产生警告: [W002] 标识符 x 遮蔽了外层作用域中的同名定义",
+                "checker: 函数体产生警告: [W001] 局部变量 x 定义后从未使用",
+                "checker: 函数体产生错误: [E001] z 不存在，或不是整型、数组或指针变量",
+            ]
+        );
+    }
+
+    struct EnglishFormatter;
+
+    impl ErrorFormatter for EnglishFormatter {
+        fn undefined_identifier(&self, id: &str) -> String {
+            format!("'{}' is undefined", id)
+        }
+
+        fn undefined_function(&self, id: &str) -> String {
+            format!("function '{}' is undefined", id)
+        }
+
+        fn not_callable(&self, id: &str, _: ItemKind) -> String {
+            format!("'{}' is not a function", id)
+        }
+
+        fn redefinition(&self, id: &str, _: ItemKind, declared: &str) -> String {
+            format!("'{}' is already defined as {}", id, declared)
+        }
+
+        fn argument_mismatch(&self, arg: &str, expected: &str) -> String {
+            format!("cannot convert {} to {}", arg, expected)
+        }
+
+        fn argument_count(&self, id: &str, expected: usize, got: usize) -> String {
+            format!("{} takes {} arguments but {} were given", id, expected, got)
+        }
+
+        fn recursive_main(&self) -> String {
+            "main cannot be called".to_string()
+        }
+
+        fn not_integer(&self, expr: &str) -> String {
+            format!("{} is not an int", expr)
+        }
+
+        fn non_constant_global(&self, id: &str) -> String {
+            format!("initializer of global {} is not constant", id)
+        }
+
+        fn condition_type(&self, statement: &str, got: &str) -> String {
+            format!("{} condition must be int, found {}", statement, got)
+        }
+
+        fn missing_return_value(&self) -> String {
+            "return without a value in int function".to_string()
+        }
+
+        fn unexpected_return_value(&self, expr: &str) -> String {
+            format!("void function returns {}", expr)
+        }
+
+        fn return_type(&self, expr: &str) -> String {
+            format!("cannot return {}", expr)
+        }
+
+        fn outside_loop(&self, statement: &str) -> String {
+            format!("{} outside of loop", statement)
+        }
+
+        fn backward_goto(&self, label: &str) -> String {
+            format!("goto jumps back to label {}", label)
+        }
+
+        fn duplicate_label(&self, label: &str) -> String {
+            format!("label {} is already defined", label)
+        }
+
+        fn undefined_label(&self, label: &str) -> String {
+            format!("label {} is undefined", label)
+        }
+
+        fn missing_main(&self) -> String {
+            "no valid main function".to_string()
+        }
+
+        fn duplicate_global(&self, id: &str, previous: &str, file: &str) -> String {
+            format!("'{}' is defined in both {} and {}", id, previous, file)
+        }
+
+        fn type_mismatch(&self, expected: &str, got: &str) -> String {
+            format!("expected {}, found {}", expected, got)
+        }
+
+        fn not_left_value(&self, expr: &str) -> String {
+            format!("{} is not assignable", expr)
+        }
+
+        fn not_constant(&self, expr: &str) -> String {
+            format!("{} is not constant", expr)
+        }
+
+        fn out_of_range(&self, value: i64) -> String {
+            format!("{} is out of range", value)
+        }
+
+        fn constant_overflow(&self, expr: &str) -> String {
+            format!("{} overflows", expr)
+        }
+
+        fn array_literal_element(&self, expr: &str) -> String {
+            format!("array element {} is not an int", expr)
+        }
+
+        fn subscript_count(&self, id: &str) -> String {
+            format!("wrong number of subscripts for {}", id)
+        }
+
+        fn subscript_out_of_range(&self) -> String {
+            "subscript out of range".to_string()
+        }
+
+        fn not_subscriptable(&self, id: &str) -> String {
+            format!("{} is not an array", id)
+        }
+
+        fn const_array_as_pointer(&self, id: &str) -> String {
+            format!("const array {} cannot decay", id)
+        }
+
+        fn zero_length(&self) -> String {
+            "zero-length array".to_string()
+        }
+
+        fn negative_length(&self, expr: &str) -> String {
+            format!("array length {} is negative", expr)
+        }
+
+        fn array_too_large(&self, type_: &str) -> String {
+            format!("{} is too large", type_)
+        }
+
+        fn nested_init_list(&self, init_list: &str) -> String {
+            format!("unexpected initializer list {}", init_list)
+        }
+
+        fn init_list_too_long(&self) -> String {
+            "too many initializers".to_string()
+        }
+
+        fn unused_variable(&self, id: &str) -> String {
+            format!("{} is never used", id)
+        }
+
+        fn shadowing(&self, id: &str) -> String {
+            format!("{} shadows an outer definition", id)
+        }
+
+        fn read_before_assignment(&self, id: &str) -> String {
+            format!("{} is read before assignment", id)
+        }
+
+        fn constant_if(&self, value: i64) -> String {
+            format!("if condition is always {}", value)
+        }
+
+        fn constant_while(&self, value: i64) -> String {
+            format!("while condition is always {}", value)
+        }
+    }
+
+    #[test]
+    fn custom_formatter() {
+        let check_english = |code: &str| {
+            check_with_formatter(vec![build_ast(code).unwrap()], &["test.sy"], &EnglishFormatter)
+                .map(|_| ())
+                .map_err(|(_, e)| e)
+        };
+        let cases = [
+            ("int main() { return x; }", "'x' is undefined"),
+            ("int main() { return f(); }", "function 'f' is undefined"),
+            ("int main() { int f; return f(); }", "'f' is not a function"),
+            ("int main() { int a; int a; return 0; }", "'a' is already defined as int"),
+            (
+                "void f(int a[]) {} int main() { int b; f(b + 1); return 0; }",
+                "cannot convert b + 1 to int*",
+            ),
+            (
+                "int a[2]; int main() { while (a) {} return 0; }",
+                "while condition must be int, found int*",
+            ),
+            (
+                "int f(int a) { return a; } int main() { return f(1, 2); }",
+                "f takes 1 arguments but 2 were given",
+            ),
+            ("int main() { return main(); }", "main cannot be called"),
+            ("int a[2]; int main() { int b = a; return 0; }", "a is not an int"),
+            (
+                "int a; int b = a; int main() { return 0; }",
+                "initializer of global b is not constant",
+            ),
+            ("int main() { return; }", "return without a value in int function"),
+            (
+                "void f() { return 1 + 2; } int main() { return 0; }",
+                "void function returns 1 + 2",
+            ),
+            ("int a[2]; int main() { return a; }", "cannot return a"),
+            ("int main() { break; }", "break outside of loop"),
+            ("int main() { l: goto l; }", "goto jumps back to label l"),
+            ("int main() { l: l: return 0; }", "label l is already defined"),
+            ("int main() { goto end; return 0; }", "label end is undefined"),
+            ("int f() { return 0; }", "no valid main function"),
+            ("int main() { int a[2]; a = 1; return 0; }", "a is not assignable"),
+            ("int main() { const int a[2] = {}; return a[0][1]; }", "wrong number of subscripts for a"),
+            ("int main() { return 2147483647 + 1; }", "2147483648 is out of range"),
+            ("int main() { return 1 << 64; }", "1 << 64 overflows"),
+            ("int a[2] = {1, 2, 3}; int main() { return 0; }", "too many initializers"),
+            ("int main() { int a[0]; return 0; }", "zero-length array"),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(check_english(code), Err(expected.to_string()), "{}", code);
+        }
+        assert_eq!(check_english("int main() { return 0; }"), Ok(()));
+        let units = vec![build_ast("int x;").unwrap(), build_ast("int x; int main() { return 0; }").unwrap()];
+        assert_eq!(
+            check_with_formatter(units, &["a.sy", "b.sy"], &EnglishFormatter).unwrap_err(),
+            (1, "'x' is defined in both a.sy and b.sy".to_string())
+        );
+        let (_, warnings) = check_with_formatter(
+            vec![build_ast("int x; int main() { int x; while (0) {} return x; }").unwrap()],
+            &["test.sy"],
+            &EnglishFormatter,
+        )
+        .unwrap();
+        let warnings: Vec<_> = warnings.into_iter().map(|(_, warning)| warning).collect();
+        assert_eq!(
+            warnings,
+            [
+                "x shadows an outer definition",
+                "while condition is always 0",
+                "x is read before assignment"
+            ]
+        );
+    }
+
+    #[test]
+    fn trace_with_custom_formatter() {
+        let (_, tracer) = check_with_tracer(
+            vec![build_ast("int main() { return y; }").unwrap()],
+            &["test.sy"],
+            &EnglishFormatter,
+            Some(Tracer::new(false, true)),
+        );
+        let lines = tracer.unwrap().into_lines();
+        assert_eq!(lines.last().unwrap(), "checker: 函数体产生错误: 'y' is undefined");
+    }
+
+    #[test]
+    fn take_current_scope() {
+        let mut context = SymbolTable::new(HashMap::from([("a", VARIABLE)]));
+        context.enter_scope();
+        context.insert_definition("b", VARIABLE).unwrap();
+        context.insert_definition("c", ConstVariable(1)).unwrap();
+        let scope = context.take_current_scope();
+        let mut identifiers: Vec<_> = scope.keys().copied().collect();
+        identifiers.sort();
+        assert_eq!(identifiers, ["b", "c"]);
+        assert_eq!(context.scope_depth(), 1);
+        assert_eq!(context.all_symbols_flat(), [(0, "a")]);
+    }
+
+    #[test]
+    fn transactions() {
+        let mut context = SymbolTable::new(HashMap::from([("a", VARIABLE)]));
+        let outer = context.begin_transaction();
+        context.insert_definition("b", VARIABLE).unwrap();
+        let inner = context.begin_transaction();
+        context.insert_definition("c", VARIABLE).unwrap();
+        context.enter_scope();
+        context.insert_definition("d", VARIABLE).unwrap();
+        context.rollback(inner);
+        assert_eq!(context.all_symbols_flat(), [(0, "a"), (0, "b")]);
+        let inner = context.begin_transaction();
+        context.enter_scope();
+        context.insert_definition("e", VARIABLE).unwrap();
+        context.commit(inner);
+        assert_eq!(context.all_symbols_flat(), [(0, "a"), (0, "b"), (1, "e")]);
+        context.rollback(outer);
+        assert_eq!(context.all_symbols_flat(), [(0, "a")]);
+        // 没有事务时不记录
+        context.insert_definition("f", VARIABLE).unwrap();
+        assert!(context.journal.is_empty());
+    }
+
+    #[test]
+    fn snapshot_and_restore() {
+        let mut context = SymbolTable::new(HashMap::from([("a", VARIABLE)]));
+        context.enter_scope();
+        let snapshot = context.snapshot();
+        context.insert_definition("b", VARIABLE).unwrap();
+        context.exit_scope();
+        context.insert_definition("c", ConstVariable(1)).unwrap();
+        context.restore(snapshot);
+        assert_eq!(context.scope_depth(), 2);
+        assert_eq!(context.all_symbols_flat(), [(0, "a")]);
+        assert!(matches!(context.search("a"), Some(Variable)));
+        // 事务中恢复后，回滚只撤销快照之前的定义
+        let transaction = context.begin_transaction();
+        context.insert_definition("d", VARIABLE).unwrap();
+        let snapshot = context.snapshot();
+        context.insert_definition("e", VARIABLE).unwrap();
+        context.restore(snapshot);
+        assert_eq!(context.journal.len(), 1);
+        context.rollback(transaction);
+        assert_eq!(context.all_symbols_flat(), [(0, "a")]);
+    }
+
+    #[test]
+    fn failed_definition_keeps_previous() {
+        let mut context = SymbolTable::new(HashMap::from([("a", VARIABLE)]));
+        let transaction = context.begin_transaction();
+        assert!(matches!(context.insert_definition("a", ConstVariable(1)), Err(Variable)));
+        context.rollback(transaction);
+        assert!(matches!(context.search("a"), Some(Variable)));
+    }
+
+    #[test]
+    fn empty_init_list() {
+        let (ast, _) =
+            check_code("const int b[3] = {}; const int c[2][2] = {}; int main() { int a[5] = {}; return b[0]; }").unwrap();
+        let values = |i: usize| risk!(ast[i].as_ref(), GlobalItem::Def(ConstArrayDef { values, .. }) => values.clone());
+        assert_eq!(values(0), [0, 0, 0]);
+        assert_eq!(values(1), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn reused_scopes_are_empty() {
+        let mut context = SymbolTable::new(HashMap::new());
+        context.enter_scope();
+        context.insert_definition("a", VARIABLE).unwrap();
+        context.exit_scope();
+        context.enter_scope();
+        assert_eq!(context.symbol_count(), 0);
+        assert!(context.insert_definition("a", ConstVariable(1)).is_ok());
+        assert!(context.contains_in_current_scope("a"));
+    }
+
+    #[test]
+    fn functions_in_different_files() {
+        let units = vec![
+            build_ast("int f() { return 0; }").unwrap(),
+            build_ast("int f() { return 1; } int main() { return f(); }").unwrap(),
+        ];
+        assert_eq!(
+            check(units, &["a.sy", "b.sy"]).unwrap_err(),
+            (1, "[E002] 全局标识符 f 在 a.sy 与 b.sy 中重复定义".to_string())
+        );
     }
 }
@@ -0,0 +1,410 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! 语句覆盖率: --coverage 的插桩、映射文件与 xenon cov-report 的报告。
+//!
+//! 每条语句（标签除外）之前插入一次计数器加一，计数器按语句在语法树中的先序遍历编号，同一份源代码的编号总是相同。
+//! main 返回之前调用运行时库的 `_sysy_write_counters`，向标准错误输出一行以 [`COUNTS_PREFIX`] 开头的计数，
+//! 不影响程序的标准输出。
+
+use super::ast::*;
+use super::Source;
+use std::collections::BTreeMap;
+use std::mem::take;
+
+/// 计数器数组的名字。以两个下划线开头，不会与评测程序中的标识符冲突。
+const COUNTERS: &str = "__xenon_coverage";
+const RETURN_VALUE: &str = "__xenon_return";
+pub const WRITE_COUNTERS: &str = "_sysy_write_counters";
+/// 运行时输出的计数行的开头，其后是计数器个数和各个计数，以空格分隔。
+pub const COUNTS_PREFIX: &str = "xenon-coverage:";
+
+struct Instrumenter<'l> {
+    file: usize,
+    lines: std::slice::Iter<'l, usize>,
+    /// 每个计数器所在的 (文件, 行)
+    counters: Vec<(usize, usize)>,
+}
+
+/// 语句 `array[id] += 1;`。
+pub(super) fn increase(array: &str, id: usize) -> BlockItem {
+    let element = ExprInner::ArrayElement(array.to_string(), vec![ExprInner::Num(id as i64).into()], false);
+    let increase = ExprInner::InfixExpr(
+        Box::new(element.into()),
+        InfixOp::Assign(AssignOp::AddAssign),
+        Box::new(ExprInner::Num(1).into()),
+    );
+    BlockItem::Statement(Box::new(Statement::Expr(increase.into())))
+}
+
+impl Instrumenter<'_> {
+    fn counter(&mut self, line: usize) -> BlockItem {
+        self.counters.push((self.file, line));
+        increase(COUNTERS, self.counters.len() - 1)
+    }
+
+    fn block(&mut self, block: &mut Block) {
+        *block = take(block).into_iter().flat_map(|item| self.item(item)).collect();
+    }
+
+    fn item(&mut self, item: BlockItem) -> Vec<BlockItem> {
+        match item {
+            BlockItem::Def(_) => {
+                // 定义也占一个行号，不计数
+                self.lines.next();
+                vec![item]
+            }
+            BlockItem::Block(mut block) => {
+                self.block(&mut block);
+                vec![BlockItem::Block(block)]
+            }
+            BlockItem::Statement(mut statement) => {
+                let line = self.lines.next().copied().unwrap_or(0);
+                // 标签不是可执行的语句；计数器放在标签之后，goto 跳转到标签时也会计数
+                let counter = match statement.as_ref() {
+                    Statement::Label(_) => None,
+                    _ => Some(self.counter(line)),
+                };
+                match statement.as_mut() {
+                    Statement::If {
+                        then_block, else_block, ..
+                    } => {
+                        self.block(then_block);
+                        self.block(else_block);
+                    }
+                    Statement::While { block, .. } => self.block(block),
+                    _ => (),
+                }
+                counter.into_iter().chain([BlockItem::Statement(statement)]).collect()
+            }
+        }
+    }
+}
+
+/// 语句 `write(count, array);`。
+fn write_counters(write: &str, array: &str, count: usize) -> BlockItem {
+    let arguments = vec![
+        ExprInner::Num(count as i64).into(),
+        ExprInner::Identifier(array.to_string()).into(),
+    ];
+    let call = ExprInner::FunctionCall(write.to_string(), arguments, 0);
+    BlockItem::Statement(Box::new(Statement::Expr(call.into())))
+}
+
+/// 把 main 中的 `return e;` 改写为先求值 `e`、再输出计数、最后返回，使 `e` 中的调用也被计数。
+fn write_before_return(block: &mut Block, write: &BlockItem) {
+    for item in block.iter_mut() {
+        match item {
+            BlockItem::Block(block) => write_before_return(block, write),
+            BlockItem::Statement(statement) => match statement.as_mut() {
+                Statement::If {
+                    then_block, else_block, ..
+                } => {
+                    write_before_return(then_block, write);
+                    write_before_return(else_block, write);
+                }
+                Statement::While { block, .. } => write_before_return(block, write),
+                // 没有返回值的 return 留给检查报错
+                Statement::Return(value @ Some(_)) => {
+                    let value = value.take().unwrap();
+                    *item = BlockItem::Block(Box::new(Block::from_iter([
+                        BlockItem::Def(Box::new(Definition::VariableDef(RETURN_VALUE.to_string(), Some(value)))),
+                        write.clone(),
+                        BlockItem::Statement(Box::new(Statement::Return(Some(
+                            ExprInner::Identifier(RETURN_VALUE.to_string()).into(),
+                        )))),
+                    ])));
+                }
+                _ => (),
+            },
+            BlockItem::Def(_) => (),
+        }
+    }
+}
+
+/// 插桩检查之前的语法树。`lines[i]` 是第 i 个文件中各语句与定义的行号，见 `parser::statement_lines`。
+/// 返回每个计数器所在的 (文件, 行)。
+pub fn instrument(units: &mut [TranslationUnit], lines: &[Vec<usize>]) -> Vec<(usize, usize)> {
+    let mut counters = Vec::new();
+    for (file, unit) in units.iter_mut().enumerate() {
+        let mut instrumenter = Instrumenter {
+            file,
+            lines: lines[file].iter(),
+            counters: take(&mut counters),
+        };
+        for item in unit.iter_mut() {
+            if let GlobalItem::FuncDef { block, .. } = item.as_mut() {
+                instrumenter.block(block);
+            }
+        }
+        counters = instrumenter.counters;
+    }
+    finish(units, COUNTERS, WRITE_COUNTERS, counters.len());
+    counters
+}
+
+/// 定义 `count` 个计数器的全局数组 `array`，并在 main 返回之前调用运行时库的 `write` 输出计数。
+pub(super) fn finish(units: &mut [TranslationUnit], array: &str, write: &str, count: usize) {
+    let write = write_counters(write, array, count);
+    for item in units.iter_mut().flatten() {
+        if let GlobalItem::FuncDef { id, block, .. } = item.as_mut() {
+            if id == "main" {
+                let returns_at_end = matches!(block.as_slice().last(), Some(BlockItem::Statement(statement))
+                    if matches!(statement.as_ref(), Statement::Return(_)));
+                write_before_return(block, &write);
+                // 执行到 main 的末尾时也要输出
+                if !returns_at_end {
+                    block.push(write.clone());
+                }
+            }
+        }
+    }
+    // 全局数组在所有函数之前定义；没有计数器时数组长度仍为 1
+    let array = Definition::ArrayDefTmp {
+        id: array.to_string(),
+        lengths: vec![ExprInner::Num(count.max(1) as i64).into()],
+        init_list: None,
+    };
+    if let Some(unit) = units.first_mut() {
+        unit.insert(0, Box::new(GlobalItem::Def(array)));
+    }
+}
+
+/// 映射文件: 注释行之后，先是每个源文件一行 `file <文件编号> <文件名>`，再是每个计数器一行 `<编号> <文件编号> <行号>`。
+pub fn map_text(names: &[&str], counters: &[(usize, usize)]) -> String {
+    let mut text = String::from("# xenon --coverage 映射文件: 计数器编号 文件编号 行号\n");
+    for (file, name) in names.iter().enumerate() {
+        text += &format!("file {} {}\n", file, name);
+    }
+    for (id, (file, line)) in counters.iter().enumerate() {
+        text += &format!("{} {} {}\n", id, file, line);
+    }
+    text
+}
+
+struct Map {
+    files: Vec<String>,
+    counters: Vec<(usize, usize)>,
+}
+
+fn parse_map(text: &str) -> Result<Map, String> {
+    let mut map = Map {
+        files: Vec::new(),
+        counters: Vec::new(),
+    };
+    for (number, line) in text.lines().enumerate() {
+        let invalid = || format!("映射文件第 {} 行格式错误", number + 1);
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        if let Some(file) = line.strip_prefix("file ") {
+            let (index, name) = file.split_once(' ').ok_or_else(invalid)?;
+            if index.parse() != Ok(map.files.len()) {
+                return Err(invalid());
+            }
+            map.files.push(name.to_string());
+            continue;
+        }
+        let fields: Vec<usize> = line
+            .split(' ')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid())?;
+        match fields[..] {
+            [id, file, line] if id == map.counters.len() && file < map.files.len() => map.counters.push((file, line)),
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(map)
+}
+
+/// 累加计数文件中的每一行计数，其他行（例如程序自己的标准错误输出）被忽略。
+fn parse_counts(text: &str, count: usize) -> Result<Vec<u64>, String> {
+    let mut totals = vec![0; count];
+    let mut found = false;
+    for line in text.lines() {
+        let Some(counts) = line.strip_prefix(COUNTS_PREFIX) else {
+            continue;
+        };
+        let invalid = || format!("计数行格式错误: {}", line);
+        let values: Vec<i32> = counts
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid())?;
+        let Some((&n, values)) = values.split_first() else {
+            return Err(invalid());
+        };
+        if n as usize != count || values.len() != count {
+            return Err(format!(
+                "计数行有 {} 个计数器，映射文件中有 {} 个，源代码可能在编译之后被修改过",
+                n, count
+            ));
+        }
+        // 计数器是 int，溢出后按无符号数解释
+        for (total, &value) in totals.iter_mut().zip(values) {
+            *total += u64::from(value as u32);
+        }
+        found = true;
+    }
+    match found {
+        true => Ok(totals),
+        false => Err(format!("计数文件中没有以 {} 开头的行", COUNTS_PREFIX)),
+    }
+}
+
+fn ratio(covered: usize, total: usize) -> String {
+    match total {
+        0 => "0/0".to_string(),
+        _ => format!("{}/{} ({:.1}%)", covered, total, covered as f64 * 100.0 / total as f64),
+    }
+}
+
+/// 逐行列出执行次数（一行有多条语句时取最大值；`-` 表示该行没有语句，`#####` 表示从未执行），
+/// 每个文件之后给出语句与行的覆盖率，多个文件时最后给出合计。`sources` 中是未经预处理的源代码。
+pub fn report(map: &str, counts: &str, sources: &[Source]) -> Result<String, String> {
+    let map = parse_map(map)?;
+    let counts = parse_counts(counts, map.counters.len())?;
+    let mut out = String::new();
+    let (mut covered, mut total) = (0, 0);
+    for (file, name) in map.files.iter().enumerate() {
+        let source = sources
+            .iter()
+            .find(|source| &source.name == name)
+            .ok_or_else(|| format!("映射文件中的源文件 {} 不在参数中", name))?;
+        let mut lines: BTreeMap<usize, u64> = BTreeMap::new();
+        let (mut file_covered, mut file_total) = (0, 0);
+        for (&(_, line), &count) in map.counters.iter().zip(&counts).filter(|((f, _), _)| *f == file) {
+            let hits = lines.entry(line).or_default();
+            *hits = (*hits).max(count);
+            file_total += 1;
+            file_covered += usize::from(count > 0);
+        }
+        out += &format!("{}:\n", name);
+        for (number, text) in source.code.lines().enumerate() {
+            let hits = match lines.get(&(number + 1)) {
+                None => "-".to_string(),
+                Some(0) => "#####".to_string(),
+                Some(hits) => hits.to_string(),
+            };
+            out += &format!("{:>9}:{:>5}:{}\n", hits, number + 1, text);
+        }
+        let lines_covered = lines.values().filter(|&&hits| hits > 0).count();
+        out += &format!(
+            "{}: 语句 {}，行 {}\n",
+            name,
+            ratio(file_covered, file_total),
+            ratio(lines_covered, lines.len())
+        );
+        covered += file_covered;
+        total += file_total;
+    }
+    if map.files.len() > 1 {
+        out += &format!("合计: 语句 {}\n", ratio(covered, total));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{checker::check_instrumented, interpreter, parser};
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    const PROGRAM: &str = "int twice(int x) {
+  return x * 2;
+}
+int main() {
+  int i = 0, s = 0;
+  while (i < 3) {
+    if (i == 5) s = -1;
+    s = s + twice(i);
+    i = i + 1;
+  }
+  putint(s);
+  return twice(s);
+}
+";
+
+    fn sources() -> Vec<Source> {
+        vec![Source {
+            name: "test.sy".to_string(),
+            code: PROGRAM.to_string(),
+        }]
+    }
+
+    /// 运行插桩后的程序，返回映射文件、标准输出、标准错误与返回值。
+    fn run_instrumented(code: &str) -> (String, String, String, i32) {
+        let mut units = vec![parser::build_ast(code).unwrap()];
+        let counters = instrument(&mut units, &[parser::statement_lines(code).unwrap()]);
+        let (ast, _) = check_instrumented(units, &["test.sy"], &[WRITE_COUNTERS]).unwrap();
+        let (mut output, mut errors) = (Vec::new(), Vec::new());
+        let value = interpreter::run(&ast, &mut "".as_bytes(), &mut output, &mut errors, &AtomicBool::new(false)).unwrap();
+        let text = |bytes| String::from_utf8(bytes).unwrap();
+        (map_text(&["test.sy"], &counters), text(output), text(errors), value)
+    }
+
+    #[test]
+    fn counts_each_statement() {
+        let (map, output, errors, value) = run_instrumented(PROGRAM);
+        // 输出与返回值不受插桩影响
+        assert_eq!((output.as_str(), value), ("6", 12));
+        assert_eq!(
+            map,
+            "# xenon --coverage 映射文件: 计数器编号 文件编号 行号
+file 0 test.sy
+0 0 2
+1 0 6
+2 0 7
+3 0 7
+4 0 8
+5 0 9
+6 0 11
+7 0 12
+"
+        );
+        assert_eq!(errors, "xenon-coverage: 8 4 1 3 0 3 3 1 1\n");
+        // 两次运行的计数相加
+        let report = report(
+            &map,
+            &(errors.clone() + "Timer@0001-0002: 0H-0M-0S-1us\n" + &errors),
+            &sources(),
+        )
+        .unwrap();
+        assert!(report.starts_with("test.sy:\n        -:    1:int twice(int x) {\n        8:    2:  return x * 2;\n"));
+        assert!(report.contains("\n        6:    7:    if (i == 5) s = -1;\n"));
+        assert!(report.ends_with("test.sy: 语句 7/8 (87.5%)，行 7/7 (100.0%)\n"));
+    }
+
+    #[test]
+    fn falls_off_main() {
+        let (_, output, errors, _) = run_instrumented("int main() { putch(65); { return 3; } }");
+        assert_eq!((output.as_str(), errors.as_str()), ("A", "xenon-coverage: 2 1 1\n"));
+        let (_, _, errors, value) = run_instrumented("int main() { int x = 1; if (x) { x = 2; } }");
+        assert_eq!((errors.as_str(), value), ("xenon-coverage: 2 1 1\n", 0));
+    }
+
+    #[test]
+    fn stale_counts() {
+        let map = map_text(&["test.sy"], &[(0, 2)]);
+        assert_eq!(
+            report(&map, "xenon-coverage: 2 1 1\n", &sources()).unwrap_err(),
+            "计数行有 2 个计数器，映射文件中有 1 个，源代码可能在编译之后被修改过"
+        );
+        assert!(report(&map, "", &sources()).is_err());
+    }
+}
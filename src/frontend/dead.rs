@@ -0,0 +1,499 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! 未使用与不可达代码的报告，供 --report-dead 与 --report-dead-json 输出。只读取语法树，不修改程序。
+
+use super::ast::*;
+use super::checker::{ChineseFormatter, ErrorFormatter};
+use super::json::{json_text, Json};
+use super::lexer::{tokenize, TokenKind};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// 从 main 出发不可达的全局定义。`used_by` 为空时表示从未被使用，否则列出使用它的、同样不可达的全局定义。
+#[derive(Debug, PartialEq, Eq)]
+pub struct DeadSymbol {
+    pub name: String,
+    pub kind: &'static str,
+    pub file: usize,
+    /// 定义处标识符的 (行, 列)
+    pub position: (usize, usize),
+    pub used_by: Vec<String>,
+}
+
+/// 条件为常量、因而永远不会执行的分支或循环体。位置为 if 或 while 关键字的 (行, 列)。
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnreachableCode {
+    pub function: String,
+    pub file: usize,
+    pub position: (usize, usize),
+    pub message: String,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DeadCodeReport {
+    pub symbols: Vec<DeadSymbol>,
+    pub statements: Vec<UnreachableCode>,
+}
+
+/// 一个全局定义中引用的全局标识符。局部变量与形参会遮蔽同名的全局变量。
+#[derive(Default)]
+struct References<'a> {
+    scopes: Vec<HashSet<&'a str>>,
+    used: BTreeSet<&'a str>,
+}
+
+impl<'a> References<'a> {
+    fn declare(&mut self, id: &'a str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(id);
+        }
+    }
+
+    fn use_variable(&mut self, id: &'a str) {
+        if !self.scopes.iter().any(|scope| scope.contains(id)) {
+            self.used.insert(id);
+        }
+    }
+
+    fn expr(&mut self, expr: &'a Expr) {
+        match &expr.inner {
+            ExprInner::InfixExpr(lhs, _, rhs) | ExprInner::Comma(lhs, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ExprInner::UnaryExpr(_, expr) => self.expr(expr),
+            ExprInner::Num(_) => (),
+            ExprInner::Identifier(id) => self.use_variable(id),
+            ExprInner::FunctionCall(id, exprs, _) => {
+                self.used.insert(id);
+                exprs.iter().for_each(|expr| self.expr(expr));
+            }
+            ExprInner::ArrayElement(id, exprs, _) => {
+                self.use_variable(id);
+                exprs.iter().for_each(|expr| self.expr(expr));
+            }
+            ExprInner::ArrayInit(exprs) => exprs.iter().for_each(|expr| self.expr(expr)),
+        }
+    }
+
+    fn init_list(&mut self, init_list: &'a InitList) {
+        for item in init_list {
+            match item {
+                InitListItem::InitList(init_list) => self.init_list(init_list),
+                InitListItem::Expr(expr) => self.expr(expr),
+            }
+        }
+    }
+
+    fn definition(&mut self, definition: &'a Definition) {
+        match definition {
+            Definition::ConstVariableDefTmp(_, expr) | Definition::VariableDef(_, Some(expr)) => self.expr(expr),
+            Definition::ConstArrayDefTmp { lengths, init_list, .. } => {
+                lengths.iter().for_each(|expr| self.expr(expr));
+                self.init_list(init_list);
+            }
+            Definition::ArrayDefTmp { lengths, init_list, .. } => {
+                lengths.iter().for_each(|expr| self.expr(expr));
+                init_list.iter().for_each(|init_list| self.init_list(init_list));
+            }
+            Definition::ArrayDef { init_list, .. } => init_list.iter().for_each(|init_list| self.init_list(init_list)),
+            _ => (),
+        }
+        self.declare(definition_id(definition));
+    }
+
+    fn block(&mut self, block: &'a Block) {
+        self.scopes.push(HashSet::new());
+        for item in block {
+            match item {
+                BlockItem::Def(definition) => self.definition(definition),
+                BlockItem::Block(block) => self.block(block),
+                BlockItem::Statement(statement) => match statement.as_ref() {
+                    Statement::Expr(expr) | Statement::Return(Some(expr)) => self.expr(expr),
+                    Statement::If {
+                        condition,
+                        then_block,
+                        else_block,
+                    } => {
+                        self.expr(condition);
+                        self.block(then_block);
+                        self.block(else_block);
+                    }
+                    Statement::While { condition, block } => {
+                        self.expr(condition);
+                        self.block(block);
+                    }
+                    _ => (),
+                },
+            }
+        }
+        self.scopes.pop();
+    }
+
+    fn function(&mut self, parameter_list: &'a [Parameter], block: &'a Block) {
+        self.scopes.push(HashSet::new());
+        for parameter in parameter_list {
+            match parameter {
+                Parameter::Int(id) | Parameter::Pointer(id, _) => self.declare(id),
+                Parameter::PointerTmp(id, lengths) => {
+                    lengths.iter().for_each(|expr| self.expr(expr));
+                    self.declare(id);
+                }
+            }
+        }
+        self.block(block);
+        self.scopes.pop();
+    }
+}
+
+fn definition_id(definition: &Definition) -> &str {
+    match definition {
+        Definition::ConstVariableDefTmp(id, _)
+        | Definition::ConstVariableDef(id, _)
+        | Definition::VariableDef(id, _)
+        | Definition::ConstArrayDefTmp { id, .. }
+        | Definition::ConstArrayDef { id, .. }
+        | Definition::ArrayDefTmp { id, .. }
+        | Definition::ArrayDef { id, .. } => id,
+    }
+}
+
+fn global_kind(item: &GlobalItem) -> &'static str {
+    match item {
+        GlobalItem::FuncDef { .. } => "函数",
+        GlobalItem::Def(Definition::ConstVariableDefTmp(..) | Definition::ConstVariableDef(..)) => "全局常量",
+        GlobalItem::Def(Definition::ConstArrayDefTmp { .. } | Definition::ConstArrayDef { .. }) => "常量数组",
+        GlobalItem::Def(Definition::VariableDef(..)) => "全局变量",
+        GlobalItem::Def(Definition::ArrayDefTmp { .. } | Definition::ArrayDef { .. }) => "全局数组",
+    }
+}
+
+/// 全局定义处标识符的位置: 不在任何括号内，且紧跟在 int、void 或逗号之后的第一个标识符。
+fn definition_positions(code: &str) -> HashMap<&str, (usize, usize)> {
+    let tokens = tokenize(code);
+    let mut positions = HashMap::new();
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.text {
+            "{" | "(" | "[" => depth += 1,
+            "}" | ")" | "]" => depth -= 1,
+            _ if depth == 0 && token.kind == TokenKind::Identifier && i > 0 => {
+                if matches!(tokens[i - 1].text, "int" | "void" | ",") {
+                    positions.entry(token.text).or_insert(token.start);
+                }
+            }
+            _ => (),
+        }
+    }
+    positions
+}
+
+/// 依次取出 if 与 while 关键字的位置。语法树中 if 与 while 语句的先序遍历顺序与它们在源代码中出现的顺序相同。
+fn unreachable_statements(
+    function: &str,
+    file: usize,
+    block: &Block,
+    keywords: &mut impl Iterator<Item = (usize, usize)>,
+    statements: &mut Vec<UnreachableCode>,
+) {
+    for item in block {
+        let statement = match item {
+            BlockItem::Block(block) => {
+                unreachable_statements(function, file, block, keywords, statements);
+                continue;
+            }
+            BlockItem::Statement(statement) => statement.as_ref(),
+            BlockItem::Def(_) => continue,
+        };
+        let (message, blocks) = match statement {
+            Statement::If {
+                condition,
+                then_block,
+                else_block,
+            } => (
+                // 与检查器产生的警告使用同一段文字
+                match condition.inner {
+                    ExprInner::Num(i) if i == 0 || !else_block.is_empty() => Some(ChineseFormatter.constant_if(i)),
+                    _ => None,
+                },
+                vec![then_block.as_ref(), else_block.as_ref()],
+            ),
+            Statement::While { condition, block } => (
+                matches!(condition.inner, ExprInner::Num(0)).then(|| ChineseFormatter.constant_while(0)),
+                vec![block.as_ref()],
+            ),
+            _ => continue,
+        };
+        let position = keywords.next().unwrap_or((0, 0));
+        if let Some(message) = message {
+            statements.push(UnreachableCode {
+                function: function.to_string(),
+                file,
+                position,
+                message,
+            });
+        }
+        for block in blocks {
+            unreachable_statements(function, file, block, keywords, statements);
+        }
+    }
+}
+
+/// `parsed` 是各个源文件检查之前的语法树，其中的常量引用尚未被求值替换；`checked` 是检查后合并的语法树，
+/// 用于找出条件为常量的语句；`codes` 是预处理后的源代码，用于定位。
+pub fn collect(parsed: &[TranslationUnit], checked: &TranslationUnit, codes: &[&str]) -> DeadCodeReport {
+    let mut definitions = Vec::new();
+    for (file, unit) in parsed.iter().enumerate() {
+        for item in unit {
+            let mut references = References::default();
+            let id = match item.as_ref() {
+                GlobalItem::Def(definition) => {
+                    references.definition(definition);
+                    definition_id(definition)
+                }
+                GlobalItem::FuncDef {
+                    id,
+                    parameter_list,
+                    block,
+                    ..
+                } => {
+                    references.function(parameter_list, block);
+                    id
+                }
+            };
+            references.used.remove(id);
+            definitions.push((id, global_kind(item), file, references.used));
+        }
+    }
+    let index: HashMap<&str, usize> = definitions.iter().enumerate().map(|(i, (id, ..))| (*id, i)).collect();
+    let mut live = vec![false; definitions.len()];
+    let mut pending: Vec<usize> = index.get("main").copied().into_iter().collect();
+    while let Some(i) = pending.pop() {
+        if !std::mem::replace(&mut live[i], true) {
+            pending.extend(definitions[i].3.iter().filter_map(|id| index.get(id).copied()));
+        }
+    }
+    let positions: Vec<_> = codes.iter().map(|code| definition_positions(code)).collect();
+    let mut report = DeadCodeReport::default();
+    for (i, (id, kind, file, _)) in definitions.iter().enumerate() {
+        if live[i] {
+            continue;
+        }
+        report.symbols.push(DeadSymbol {
+            name: id.to_string(),
+            kind,
+            file: *file,
+            position: positions[*file].get(id).copied().unwrap_or((0, 0)),
+            used_by: definitions
+                .iter()
+                .filter(|(user, _, _, used)| user != id && used.contains(id))
+                .map(|(user, ..)| user.to_string())
+                .collect(),
+        });
+    }
+    let mut items = checked.iter();
+    for (file, unit) in parsed.iter().enumerate() {
+        let mut keywords = tokenize(codes[file])
+            .into_iter()
+            .filter(|token| token.kind == TokenKind::Keyword && matches!(token.text, "if" | "while"))
+            .map(|token| token.start);
+        for item in items.by_ref().take(unit.len()) {
+            if let GlobalItem::FuncDef { id, block, .. } = item.as_ref() {
+                unreachable_statements(id, file, block, &mut keywords, &mut report.statements);
+            }
+        }
+    }
+    report
+}
+
+fn symbol_message(symbol: &DeadSymbol) -> String {
+    let verb = match symbol.kind {
+        "函数" => "调用",
+        _ => "使用",
+    };
+    match symbol.used_by.is_empty() {
+        true => format!("{} {} 从未被{}", symbol.kind, symbol.name, verb),
+        false => format!(
+            "{} {} 从 main 不可达，只被同样不可达的 {} {}",
+            symbol.kind,
+            symbol.name,
+            symbol.used_by.join("、"),
+            verb
+        ),
+    }
+}
+
+/// 每条一行，形如 `文件:行:列: 说明`。先列出不可达的全局定义，再列出不会执行的语句，均按源代码中的顺序。
+pub fn report_text(report: &DeadCodeReport, names: &[&str]) -> String {
+    let mut out = String::new();
+    for symbol in &report.symbols {
+        let (line, column) = symbol.position;
+        out += &format!("{}:{}:{}: {}\n", names[symbol.file], line, column, symbol_message(symbol));
+    }
+    for statement in &report.statements {
+        let (line, column) = statement.position;
+        out += &format!(
+            "{}:{}:{}: 在函数 {} 中，{}\n",
+            names[statement.file], line, column, statement.function, statement.message
+        );
+    }
+    out
+}
+
+pub fn report_json(report: &DeadCodeReport, names: &[&str]) -> String {
+    let location = |file: usize, (line, column): (usize, usize)| {
+        vec![
+            ("file", Json::String(names[file].to_string())),
+            ("line", Json::Number(line as i64)),
+            ("column", Json::Number(column as i64)),
+        ]
+    };
+    let symbols = report
+        .symbols
+        .iter()
+        .map(|symbol| {
+            let kind = match symbol.kind {
+                "函数" => "function",
+                "全局常量" => "const",
+                "常量数组" => "const_array",
+                "全局变量" => "variable",
+                _ => "array",
+            };
+            let mut fields = vec![
+                ("name", Json::String(symbol.name.clone())),
+                ("kind", Json::String(kind.to_string())),
+            ];
+            fields.extend(location(symbol.file, symbol.position));
+            fields.push((
+                "used_by",
+                Json::Array(symbol.used_by.iter().map(|user| Json::String(user.clone())).collect()),
+            ));
+            fields.push(("message", Json::String(symbol_message(symbol))));
+            Json::Object(fields)
+        })
+        .collect();
+    let statements = report
+        .statements
+        .iter()
+        .map(|statement| {
+            let mut fields = vec![("function", Json::String(statement.function.clone()))];
+            fields.extend(location(statement.file, statement.position));
+            fields.push(("message", Json::String(statement.message.clone())));
+            Json::Object(fields)
+        })
+        .collect();
+    json_text(&Json::Object(vec![
+        ("symbols", Json::Array(symbols)),
+        ("statements", Json::Array(statements)),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{checker::check, parser::build_ast};
+    use super::*;
+
+    const PROGRAM: &str = "const int TABLE[3] = {1, 2, 4};
+int counter;
+int unused;
+int live(int x) {
+  return x + 1;
+}
+int dead(int i) {
+  counter = counter + 1;
+  return TABLE[1] + TABLE[i];
+}
+int main() {
+  int unused = live(2);
+  if (0) {
+    unused = 3;
+  }
+  while (unused < 0) {}
+  return counter + unused;
+}
+";
+
+    fn report(code: &str) -> DeadCodeReport {
+        let units = vec![build_ast(code).unwrap()];
+        let (ast, _) = check(units.clone(), &["test.sy"]).unwrap();
+        collect(&units, &ast, &[code])
+    }
+
+    #[test]
+    fn helpers_and_table() {
+        let report = report(PROGRAM);
+        let summary: Vec<_> = report
+            .symbols
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.kind, symbol.position, symbol.used_by.clone()))
+            .collect();
+        // 局部变量 unused 遮蔽了同名的全局变量；TABLE[1] 在检查后被替换为常量，但仍算作引用
+        assert_eq!(
+            summary,
+            [
+                ("TABLE", "常量数组", (1, 11), vec!["dead".to_string()]),
+                ("unused", "全局变量", (3, 5), vec![]),
+                ("dead", "函数", (7, 5), vec![]),
+            ]
+        );
+        assert_eq!(
+            report.statements,
+            [UnreachableCode {
+                function: "main".to_string(),
+                file: 0,
+                position: (13, 3),
+                message: "[W003] if 的条件是常量 0，then 分支永远不会执行".to_string(),
+            }]
+        );
+        assert_eq!(
+            report_text(&report, &["test.sy"]),
+            "\
+test.sy:1:11: 常量数组 TABLE 从 main 不可达，只被同样不可达的 dead 使用
+test.sy:3:5: 全局变量 unused 从未被使用
+test.sy:7:5: 函数 dead 从未被调用
+test.sy:13:3: 在函数 main 中，[W003] if 的条件是常量 0，then 分支永远不会执行
+"
+        );
+    }
+
+    #[test]
+    fn transitive_and_recursive() {
+        let code = "int g;
+int leaf() { return g; }
+int middle(int n) { if (n) return middle(n - 1); return leaf(); }
+int main() { while (0) { putint(1); } return 0; }
+";
+        let report = report(code);
+        let summary: Vec<_> = report
+            .symbols
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.used_by.clone()))
+            .collect();
+        // middle 递归调用自身不算被使用
+        assert_eq!(
+            summary,
+            [
+                ("g", vec!["leaf".to_string()]),
+                ("leaf", vec!["middle".to_string()]),
+                ("middle", vec![]),
+            ]
+        );
+        assert_eq!(report.statements.len(), 1);
+        assert_eq!(report.statements[0].position, (4, 14));
+        assert!(report_json(&report, &["test.sy"]).contains("\"used_by\": [\n        \"middle\"\n      ]"));
+    }
+}
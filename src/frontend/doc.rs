@@ -0,0 +1,115 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! 从源代码中函数定义前的 `///` 注释生成 HTML 文档。
+
+use super::ast::*;
+use super::lexer::{tokenize, TokenKind};
+use super::symbols::global_item_str;
+use std::collections::HashMap;
+
+const TEMPLATE: &str = include_str!("doc.html");
+
+/// 紧挨在函数定义之前的连续 `///` 注释行，去掉 `///` 和其后的一个空格。空行会打断注释与定义的关联。
+pub fn doc_comments(code: &str) -> HashMap<&str, Vec<&str>> {
+    let tokens = tokenize(code);
+    let mut comments = HashMap::new();
+    let (mut depth, mut previous_end) = (0, 0);
+    for (i, token) in tokens.iter().enumerate() {
+        let is_definition = depth == 0
+            && matches!(token.text, "int" | "void")
+            && tokens.get(i + 1).is_some_and(|id| id.kind == TokenKind::Identifier)
+            && tokens.get(i + 2).is_some_and(|paren| paren.text == "(");
+        if is_definition {
+            let gap = code[previous_end..token.offset].trim_end_matches([' ', '\t']);
+            let mut lines: Vec<&str> = gap
+                .lines()
+                .rev()
+                .map(str::trim)
+                .take_while(|line| line.starts_with("///"))
+                .map(|line| line["///".len()..].strip_prefix(' ').unwrap_or(&line["///".len()..]))
+                .collect();
+            lines.reverse();
+            comments.insert(tokens[i + 1].text, lines);
+        }
+        match token.text {
+            "{" => depth += 1,
+            "}" => depth -= 1,
+            _ => (),
+        }
+        previous_end = token.offset + token.text.len();
+    }
+    comments
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 空行分隔段落。
+fn paragraphs(lines: &[&str]) -> String {
+    lines
+        .split(|line| line.trim().is_empty())
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| format!("<p>{}</p>\n", escape(&paragraph.join("\n"))))
+        .collect()
+}
+
+/// 按函数在检查后的语法树中的顺序生成文档，签名与 `xenon symbols` 的输出相同。
+pub fn generate_doc(title: &str, ast: &TranslationUnit, comments: &HashMap<&str, Vec<&str>>) -> String {
+    let (mut index, mut functions) = (String::new(), String::new());
+    for item in ast {
+        if !matches!(**item, GlobalItem::FuncDef { .. }) {
+            continue;
+        }
+        let (id, signature) = global_item_str(item);
+        let body = match comments.get(id).map(|lines| paragraphs(lines)) {
+            Some(body) if !body.is_empty() => body,
+            _ => "<p class=\"undocumented\">没有文档</p>\n".to_string(),
+        };
+        index += &format!("<li><a href=\"#{0}\"><code>{0}</code></a></li>\n", escape(id));
+        functions += &format!(
+            "<section id=\"{}\">\n<h2><code>{}</code></h2>\n{}</section>\n",
+            escape(id),
+            escape(&signature),
+            body
+        );
+    }
+    TEMPLATE
+        .replace("{{title}}", &escape(title))
+        .replace("{{index}}", &index)
+        .replace("{{functions}}", &functions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_attach_to_the_next_definition() {
+        let code = "/// 不属于任何函数\n\n/// 求和。\n///\n///   第二段\nint sum(int a[], int n) {\n  /// 函数体内\n  int f(1);\n  return 0;\n}\n// 普通注释\nvoid g() {}\n/// h\n\nint h;\n";
+        let comments = doc_comments(code);
+        assert_eq!(comments["sum"], ["求和。", "", "  第二段"]);
+        assert!(comments["g"].is_empty());
+        assert!(!comments.contains_key("f") && !comments.contains_key("h"));
+        assert_eq!(paragraphs(&comments["sum"]), "<p>求和。</p>\n<p>  第二段</p>\n");
+        assert_eq!(paragraphs(&["a < b && c"]), "<p>a &lt; b &amp;&amp; c</p>\n");
+    }
+}
@@ -17,6 +17,8 @@
 
 use super::ast::{ArithmeticOp::*, ArithmeticUnaryOp::*, AssignOp::*, ExprInner::*, InfixOp::*, LogicOp::*, OtherUnaryOp::*};
 use super::ast::{SimpleType::*, UnaryOp::*, *};
+use crate::risk;
+use std::fmt::Write;
 use std::mem::take;
 
 struct Counter {
@@ -30,7 +32,7 @@ impl Counter {
     }
 }
 
-fn dump_array_elem_lvalue(counter: &mut Counter, id: &String, subscripts: &Vec<Expr>, id_is_pointer: bool) -> (String, String) {
+fn dump_array_elem_lvalue(counter: &mut Counter, id: &String, subscripts: &[Expr], id_is_pointer: bool) -> (String, String) {
     let mut last_id = counter.get();
     let mut old_id = format!("%{}", id);
     let (exp_str, exp_id) = dump_expr_rvalue(counter, &subscripts[0]);
@@ -58,7 +60,7 @@ fn dump_array_elem_lvalue(counter: &mut Counter, id: &String, subscripts: &Vec<E
 fn dump_array_elem_rvalue(
     counter: &mut Counter,
     id: &String,
-    subscripts: &Vec<Expr>,
+    subscripts: &[Expr],
     type_: SimpleType,
     id_is_pointer: bool,
 ) -> (String, String) {
@@ -166,10 +168,13 @@ fn dump_expr_rvalue(counter: &mut Counter, expr: &Expr) -> (String, String) {
                 Less => "lt",
                 LessOrEqual => "le",
             };
-            let (lhs_str, lhs_id) = dump_expr_rvalue(counter, lhs);
+            let (mut lhs_str, lhs_id) = dump_expr_rvalue(counter, lhs);
             let (rhs_str, rhs_id) = dump_expr_rvalue(counter, rhs);
             let id = counter.get();
-            (format!("{lhs_str}{rhs_str}    {id} = {op_name} {lhs_id}, {rhs_id}\n"), id)
+            // 在左操作数的结果后追加，a + b + c + ... 这样的长链不会被反复复制
+            lhs_str.push_str(&rhs_str);
+            writeln!(lhs_str, "    {id} = {op_name} {lhs_id}, {rhs_id}").unwrap();
+            (lhs_str, id)
         }
         UnaryExpr(Others(PrefixSelfIncrease), _) | UnaryExpr(Others(PrefixSelfDecrease), _) => {
             let (exp_str, exp_id) = dump_expr_lvalue(counter, expr);
@@ -214,6 +219,11 @@ fn dump_expr_rvalue(counter: &mut Counter, expr: &Expr) -> (String, String) {
             let id = counter.get();
             (format!("{expr_str}    {id} = eq {expr_id}, 0\n"), id)
         }
+        UnaryExpr(ArithUnary(Negative), expr) => {
+            let (expr_str, expr_id) = dump_expr_rvalue(counter, expr);
+            let id = counter.get();
+            (format!("{expr_str}    {id} = sub 0, {expr_id}\n"), id)
+        }
         Num(i) => (String::new(), i.to_string()),
         Identifier(id) => match expr.type_ {
             Int => {
@@ -223,17 +233,48 @@ fn dump_expr_rvalue(counter: &mut Counter, expr: &Expr) -> (String, String) {
             Pointer => (String::new(), format!("%{}", id)),
             _ => unreachable!(),
         },
-        FunctionCall(id, args) => {
+        FunctionCall(id, args, line) => {
+            let (arg_str, call) = dump_call(counter, id, args, *line);
+            let tmp_id = counter.get();
+            (format!("{}    {} = call @{}\n", arg_str, tmp_id, call), tmp_id)
+        }
+        ArrayElement(id, subscripts, id_is_pointer) => {
+            dump_array_elem_rvalue(counter, id, subscripts, expr.type_, *id_is_pointer)
+        }
+        Comma(lhs, rhs) => {
+            let lhs_str = dump_expr_xvalue(counter, lhs);
+            let (rhs_str, rhs_id) = dump_expr_rvalue(counter, rhs);
+            (format!("{}{}", lhs_str, rhs_str), rhs_id)
+        }
+        ArrayInit(elements) => {
+            let array_id = counter.get();
+            let mut text = format!("    {} = alloc [i32, {}]\n", array_id, elements.len());
+            for (i, element) in elements.iter().enumerate() {
+                let (element_str, element_id) = dump_expr_rvalue(counter, element);
+                let ptr_id = counter.get();
+                text.push_str(&format!(
+                    "{element_str}    {ptr_id} = getelemptr {array_id}, {i}\n    store {element_id}, {ptr_id}\n"
+                ));
+            }
+            let id = counter.get();
+            text.push_str(&format!("    {} = getelemptr {}, 0\n", id, array_id));
+            (text, id)
+        }
+    }
+}
+
+// starttime() 与 stoptime() 在运行时库中是宏，实际调用的是带行号参数的 _sysy_ 版本
+fn dump_call(counter: &mut Counter, id: &str, args: &[Expr], line: usize) -> (String, String) {
+    match id {
+        "starttime" | "stoptime" => (String::new(), format!("_sysy_{}({})", id, line)),
+        _ => {
             let (arg_str, arg_ids) = args
                 .iter()
                 .map(|expr| dump_expr_rvalue(counter, expr))
                 .reduce(|(l_str, l_id), (r_str, r_id)| (format!("{}{}", l_str, r_str), format!("{}, {}", l_id, r_id)))
                 .unwrap_or_default();
-            let tmp_id = counter.get();
-            (format!("{}    {} = call @{}({})\n", arg_str, tmp_id, id, arg_ids), tmp_id)
+            (arg_str, format!("{}({})", id, arg_ids))
         }
-        ArrayElement(id, subscripts, id_is_pointer) => dump_array_elem_rvalue(counter, id, subscripts, expr.type_, *id_is_pointer),
-        _ => unreachable!(),
     }
 }
 
@@ -242,18 +283,16 @@ fn dump_expr_xvalue(counter: &mut Counter, expr: &Expr) -> String {
         InfixExpr(_, Assign(_), _) => dump_expr_lvalue(counter, expr).0,
         InfixExpr(lhs, Arith(_), rhs) => format!("{}{}", dump_expr_xvalue(counter, lhs), dump_expr_xvalue(counter, rhs)),
         InfixExpr(_, Logic(_), _) => dump_expr_rvalue(counter, expr).0,
-        UnaryExpr(_, _) => todo!(),
+        UnaryExpr(Others(_), _) => dump_expr_rvalue(counter, expr).0,
+        UnaryExpr(ArithUnary(_), expr) => dump_expr_xvalue(counter, expr),
         Num(_) => String::new(),
         Identifier(_) => String::new(),
-        FunctionCall(id, args) => {
-            let (arg_str, arg_ids) = args
-                .iter()
-                .map(|expr| dump_expr_rvalue(counter, expr))
-                .reduce(|(l_str, l_id), (r_str, r_id)| (format!("{}{}", l_str, r_str), format!("{}, {}", l_id, r_id)))
-                .unwrap_or_default();
-            format!("{}    call @{}({})\n", arg_str, id, arg_ids)
+        FunctionCall(id, args, line) => {
+            let (arg_str, call) = dump_call(counter, id, args, *line);
+            format!("{}    call @{}\n", arg_str, call)
         }
-        ArrayElement(_, exprs, _) => exprs.iter().map(|expr| dump_expr_xvalue(counter, expr)).collect(),
+        ArrayElement(_, exprs, _) | ArrayInit(exprs) => exprs.iter().map(|expr| dump_expr_xvalue(counter, expr)).collect(),
+        Comma(lhs, rhs) => format!("{}{}", dump_expr_xvalue(counter, lhs), dump_expr_xvalue(counter, rhs)),
     }
 }
 
@@ -339,6 +378,8 @@ fn dump_statement(counter: &mut Counter, statement: &Statement, while_id: &str,
         },
         Statement::Break => format!("    jump {}\n", while_next_id),
         Statement::Continue => format!("    jump {}\n", while_id),
+        Statement::Goto(label) => format!("    jump %label_{}\n", label),
+        Statement::Label(label) => format!("    jump %label_{0}\n%label_{0}:\n", label),
     }
 }
 
@@ -355,12 +396,69 @@ fn dump_def(counter: &mut Counter, def: &Definition) -> String {
             }
             None => format!("    %{} = alloc i32\n", id),
         },
-        Definition::ArrayDef { id: _, lengths: _, init_list: _ } => "un impl \n".to_string(),
-        Definition::ConstArrayDef { id: _, lengths: _, init_list: _ } => "un impl \n".to_string(),
+        Definition::ArrayDef {
+            id: _,
+            lengths: _,
+            init_list: _,
+        } => "un impl \n".to_string(),
+        Definition::ConstArrayDef {
+            id: _,
+            lengths: _,
+            values: _,
+        } => "un impl \n".to_string(),
         _ => String::new(),
     }
 }
 
+fn array_type_str(lengths: &[usize]) -> String {
+    lengths
+        .iter()
+        .rev()
+        .fold("i32".to_string(), |state, len| format!("[{}, {}]", state, len))
+}
+
+fn aggregate_str(values: &[i32], lengths: &[usize]) -> String {
+    match lengths {
+        [] => values[0].to_string(),
+        [_, rest @ ..] => {
+            let size = rest.iter().product();
+            let elements: Vec<String> = values.chunks(size).map(|chunk| aggregate_str(chunk, rest)).collect();
+            format!("{{{}}}", elements.join(", "))
+        }
+    }
+}
+
+// 全局变量的初始化表达式已由检查器求值为常量，初始化列表已按维度嵌套，缺省的元素为 0
+fn init_list_is_zero(init_list: &[InitListItem]) -> bool {
+    init_list.iter().all(|item| match item {
+        InitListItem::InitList(l) => init_list_is_zero(l),
+        InitListItem::Expr(expr) => matches!(expr.inner, Num(0)),
+    })
+}
+
+/// 按初始化列表逐个元素写出聚合常量，不展开为完整的数组。
+fn write_init_list_aggregate(out: &mut String, init_list: &[InitListItem], lengths: &[usize]) {
+    out.push('{');
+    for i in 0..lengths[0] {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        match (init_list.get(i), &lengths[1..]) {
+            (Some(InitListItem::Expr(expr)), []) => write!(out, "{}", risk!(expr.inner, Num(i) => i as i32)).unwrap(),
+            (Some(InitListItem::InitList(l)), rest) => write_init_list_aggregate(out, l, rest),
+            (None, []) => out.push('0'),
+            (None, rest) => write_init_list_aggregate(out, &[], rest),
+            _ => unreachable!(),
+        }
+    }
+    out.push('}');
+}
+
+fn global_array_str(id: &str, lengths: &[usize], init: String) -> String {
+    format!("global %{} = alloc {}, {}\n", id, array_type_str(lengths), init)
+}
+
+// 全局数组在 main 之前已全部初始化：全为 0 时用 zeroinit 表示，由后端放入 .bss 段，否则给出完整的聚合常量，放入 .data 段
 fn dump_global_def(counter: &mut Counter, def: &Definition) -> String {
     match def {
         Definition::VariableDef(id, init) => match init {
@@ -370,8 +468,24 @@ fn dump_global_def(counter: &mut Counter, def: &Definition) -> String {
             }
             None => format!("global %{} = alloc i32, 0\n", id),
         },
-        Definition::ArrayDef { id: _, lengths: _, init_list: _ } => "un impl \n".to_string(),
-        Definition::ConstArrayDef { id: _, lengths: _, init_list: _ } => "un impl \n".to_string(),
+        Definition::ArrayDef { id, lengths, init_list } => {
+            let init = match init_list {
+                Some(init_list) if !init_list_is_zero(init_list) => {
+                    let mut init = String::new();
+                    write_init_list_aggregate(&mut init, init_list, lengths);
+                    init
+                }
+                _ => "zeroinit".to_string(),
+            };
+            global_array_str(id, lengths, init)
+        }
+        Definition::ConstArrayDef { id, lengths, values } => {
+            let init = match values.iter().all(|&value| value == 0) {
+                true => "zeroinit".to_string(),
+                false => aggregate_str(values, lengths),
+            };
+            global_array_str(id, lengths, init)
+        }
         _ => String::new(),
     }
 }
@@ -393,20 +507,14 @@ fn dump_function_def(
     counter: &mut Counter,
     return_void: bool,
     id: &String,
-    parameter_list: &Vec<Parameter>,
+    parameter_list: &[Parameter],
     block: &Block,
 ) -> String {
-    fn point_type_str(lengths: &[usize]) -> String {
-        lengths
-            .iter()
-            .rev()
-            .fold("i32".to_string(), |state, len| format!("[{}, {}]", state, len))
-    }
     let para_str = parameter_list
         .iter()
         .map(|parameter| match parameter {
             Parameter::Int(id) => format!("@{}: i32", id),
-            Parameter::Pointer(id, lengths) => format!("@{}: *{}", id, point_type_str(lengths)),
+            Parameter::Pointer(id, lengths) => format!("@{}: *{}", id, array_type_str(lengths)),
             _ => unreachable!(),
         })
         .reduce(|l, r| format!("{}, {}", l, r))
@@ -417,15 +525,29 @@ fn dump_function_def(
         .iter()
         .map(|parameter| match parameter {
             Parameter::Int(id) => format!("%{} = alloc i32\nstore @{}, %{}\n", id, id, id),
-            Parameter::Pointer(id, lengths) => format!("%{} = alloc *{}\n    store @{}, %{}\n", id, point_type_str(lengths), id, id),
+            Parameter::Pointer(id, lengths) => {
+                format!("%{} = alloc *{}\n    store @{}, %{}\n", id, array_type_str(lengths), id, id)
+            }
             _ => unreachable!(),
         })
         .collect();
     let return_type_str = if return_void { "" } else { ": i32" };
     let (block, _) = dump_block(counter, block, "", "");
-    format!("fun @{}({}){} {{\n{}:\n{}{}}}\n", id, para_str, return_type_str, entry_id, para_alloc, block)
+    format!(
+        "fun @{}({}){} {{\n{}:\n{}{}}}\n",
+        id, para_str, return_type_str, entry_id, para_alloc, block
+    )
 }
 
+/// 插桩插入的调用所用的运行时库函数。只有插桩后的程序调用它们，也只在被调用时声明，
+/// 未插桩的程序不依赖这些符号，也可以定义同名的函数。
+const HOOK_DECLS: [(&str, &str); 4] = [
+    ("_sysy_write_counters", "(i32, *i32)"),
+    ("_sysy_write_profile", "(i32, *i32)"),
+    ("_sysy_check_bound", "(i32, i32, i32): i32"),
+    ("_sysy_check_overflow", "(i32, i32, i32, i32): i32"),
+];
+
 pub fn dump_ir(ast: &TranslationUnit) -> String {
     let mut counter = Counter { value: 0 };
     let prelude = r"decl @getint(): i32
@@ -434,8 +556,8 @@ decl @getarray(*i32): i32
 decl @putint(i32): i32
 decl @putch(i32): i32
 decl @putarray(i32, *i32): i32
-decl @starttime(): i32
-decl @stoptime(): i32";
+decl @_sysy_starttime(i32)
+decl @_sysy_stoptime(i32)";
     let ir: String = ast
         .iter()
         .map(|p| match p.as_ref() {
@@ -476,8 +598,10 @@ decl @stoptime(): i32";
     v_2.push(format!("{}\n", v.last().unwrap()));
     let mut v_3: Vec<&str> = Vec::new();
     for i in 0..v_2.len() - 1 {
-        if !(v_2[i].starts_with("    jump") || v_2[i].starts_with("    ret") || v_2[i].starts_with("    br"))
-            && !v_2[i].ends_with("{\n")
+        if !(v_2[i].starts_with("    jump")
+            || v_2[i].starts_with("    ret")
+            || v_2[i].starts_with("    br")
+            || v_2[i].ends_with("{\n"))
             && (v_2[i + 1].ends_with("}\n") || v_2[i + 1].ends_with(":\n"))
         {
             v_3.push(&v_2[i]);
@@ -488,5 +612,116 @@ decl @stoptime(): i32";
     }
     v_3.push(v_2.last().unwrap());
     let ir: String = v_3.into_iter().collect();
-    format!("{}\n{}", prelude, ir)
+    let defined = |id: &str| {
+        ast.iter()
+            .any(|item| matches!(item.as_ref(), GlobalItem::FuncDef { id: defined, .. } if defined == id))
+    };
+    let hooks: String = HOOK_DECLS
+        .iter()
+        .filter(|(id, _)| !defined(id) && ir.contains(&format!("call @{}(", id)))
+        .map(|(id, signature)| format!("decl @{}{}\n", id, signature))
+        .collect();
+    format!("{}\n{}{}", prelude, hooks, ir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{checker::check, parser::build_ast};
+    use super::*;
+
+    fn globals(code: &str) -> Vec<String> {
+        let (ast, _) = check(vec![build_ast(code).unwrap()], &["test.sy"]).unwrap();
+        dump_ir(&ast)
+            .lines()
+            .filter(|line| line.starts_with("global"))
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn hooks_are_declared_only_when_called() {
+        let ir = |code| dump_ir(&check(vec![build_ast(code).unwrap()], &["test.sy"]).unwrap().0);
+        assert!(!ir("int main() { return 0; }").contains("_sysy_write"));
+        // 未插桩的程序可以定义同名的函数
+        let defined = ir("void _sysy_write_counters() {} int main() { _sysy_write_counters(); return 0; }");
+        assert!(!defined.contains("decl @_sysy_write_counters"), "{}", defined);
+        assert!(defined.contains("fun @_sysy_write_counters()"), "{}", defined);
+    }
+
+    #[test]
+    fn array_literal_is_a_temporary_array() {
+        let (ast, _) = check(
+            vec![build_ast("int main() { putarray(2, {getint(), 7}); return 0; }").unwrap()],
+            &["test.sy"],
+        )
+        .unwrap();
+        assert!(dump_ir(&ast).contains(
+            "    %3 = alloc [i32, 2]
+    %4 = call @getint()
+    %5 = getelemptr %3, 0
+    store %4, %5
+    %6 = getelemptr %3, 1
+    store 7, %6
+    %7 = getelemptr %3, 0
+    call @putarray(2, %7)
+"
+        ));
+    }
+
+    #[test]
+    fn timer_calls_pass_source_lines() {
+        // 行号是预处理之前的源代码中宏调用所在的行
+        let code = crate::preprocessor::preprocess(
+            "/* 计时
+ * 测试 */
+int main() {
+  int i = 0, s = 0;
+  starttime();
+  while (i < 100) {
+    s = s + i;
+    i = i + 1;
+  }
+  stoptime(); starttime();
+  putint(s);
+  stoptime();
+  return 0;
+}
+",
+        );
+        let (ast, _) = check(vec![build_ast(&code).unwrap()], &["test.sy"]).unwrap();
+        let calls: Vec<String> = dump_ir(&ast)
+            .lines()
+            .filter_map(|line| line.strip_prefix("    call @_sysy_"))
+            .map(String::from)
+            .collect();
+        assert_eq!(calls, ["starttime(5)", "stoptime(10)", "starttime(10)", "stoptime(12)"]);
+    }
+
+    #[test]
+    fn global_arrays_are_initialized() {
+        let code = "const int N = 2; int a[3]; int b[2][N] = {{1}, {}}; int z[2] = {0}; const int c[2][2][1] = {{3}, 4};
+                    int main() { return 0; }";
+        assert_eq!(
+            globals(code),
+            [
+                "global %a = alloc [i32, 3], zeroinit",
+                "global %b = alloc [[i32, 2], 2], {{1, 0}, {0, 0}}",
+                "global %z = alloc [i32, 2], zeroinit",
+                "global %c = alloc [[[i32, 1], 2], 2], {{{3}, {0}}, {{4}, {0}}}",
+            ]
+        );
+    }
+
+    #[test]
+    fn large_global_arrays_are_not_expanded() {
+        let code = "int a[1000000000]; int b[500000000] = {0, 0}; int c[3][2] = {1, 2, 3}; int main() { return 0; }";
+        assert_eq!(
+            globals(code),
+            [
+                "global %a = alloc [i32, 1000000000], zeroinit",
+                "global %b = alloc [i32, 500000000], zeroinit",
+                "global %c = alloc [[i32, 2], 3], {{1, 2}, {3, 0}, {0, 0}}",
+            ]
+        );
+    }
 }
@@ -15,215 +15,430 @@
 // You should have received a copy of the GNU General Public License
 // along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
 
-use super::super::ast::{ArithmeticOp::*, ArithmeticUnaryOp::*, ConstInitListItem, Expr, ExprInner};
+use super::super::ast::{ArithmeticOp, ArithmeticOp::*, ArithmeticUnaryOp::*, Expr, ExprInner};
 use super::super::ast::{InfixOp, InfixOp::*, LogicOp::*, OtherUnaryOp::*, SimpleType, UnaryOp, UnaryOp::*};
 use super::super::checker::*;
+use super::super::format::{arith_op_str, expr_str};
 use super::types::Type::{self, Int, Pointer};
 use crate::risk;
 
-use std::{cmp::Ordering, iter::zip};
+use std::{cmp::Ordering, iter::zip, mem::take};
 
-type ReturnType<'a> = (Type<'a>, bool, Option<i32>);
+type ReturnType<'a> = (Type<'a>, bool, Option<i64>);
 
-fn __infix_impl<'a>(lhs: &mut Expr, op: &InfixOp, rhs: &mut Expr, context: &'a SymbolTable) -> Result<ReturnType<'a>, String> {
-    let (lhs_type, lhs_left_value, lhs_value) = lhs.const_eval_wrap(context)?;
-    let (rhs_type, _, rhs_value) = rhs.const_eval_wrap(context)?;
+fn to_i32(value: i64, formatter: &dyn ErrorFormatter) -> Result<i32, String> {
+    i32::try_from(value).map_err(|_| formatter.out_of_range(value))
+}
+
+fn overflow(op: &ArithmeticOp, lhs: i64, rhs: i64, formatter: &dyn ErrorFormatter) -> String {
+    formatter.constant_overflow(&format!("{} {} {}", lhs, arith_op_str(op), rhs))
+}
+
+/// 按常量求值的规则计算 `lhs op rhs`，溢出、除以 0 或移位越界时为 None。
+fn checked_arith(op: &ArithmeticOp, lhs: i64, rhs: i64) -> Option<i64> {
     match op {
-        Assign(_) => {
-            if !lhs_left_value || !rhs_type.can_convert_to(&lhs_type) {
-                Err(format!("{0:?} 不是左值表达式，或 {1:?} 无法转换到 {0:?} 的类型", lhs, rhs))
-            } else {
-                Ok((lhs_type, true, None))
-            }
+        Multiply => lhs.checked_mul(rhs),
+        Divide => lhs.checked_div(rhs),
+        Modulus => lhs.checked_rem(rhs),
+        Add => lhs.checked_add(rhs),
+        Subtract => lhs.checked_sub(rhs),
+        BitLeftShift => u32::try_from(rhs).ok().and_then(|rhs| lhs.checked_shl(rhs)),
+        BitRightShift => u32::try_from(rhs).ok().and_then(|rhs| lhs.checked_shr(rhs)),
+        BirXor => Some(lhs ^ rhs),
+        BitAnd => Some(lhs & rhs),
+        BitOr => Some(lhs | rhs),
+        Equal => Some((lhs == rhs).into()),
+        NotEqual => Some((lhs != rhs).into()),
+        Greater => Some((lhs > rhs).into()),
+        GreaterOrEqual => Some((lhs >= rhs).into()),
+        Less => Some((lhs < rhs).into()),
+        LessOrEqual => Some((lhs <= rhs).into()),
+    }
+}
+
+/// 以常量求值的规则折叠 `lhs op rhs`，出错时的信息与常量求值相同。
+pub fn fold_arith(op: &ArithmeticOp, lhs: i64, rhs: i64, formatter: &dyn ErrorFormatter) -> Result<i32, String> {
+    let value = checked_arith(op, lhs, rhs).ok_or_else(|| overflow(op, lhs, rhs, formatter))?;
+    to_i32(value, formatter)
+}
+
+fn infix<'a>(
+    lhs: &Expr,
+    op: &InfixOp,
+    rhs: &Expr,
+    lhs_result: ReturnType<'a>,
+    rhs_result: ReturnType<'a>,
+    formatter: &dyn ErrorFormatter,
+) -> Result<ReturnType<'a>, String> {
+    let ((lhs_type, lhs_left_value, lhs_value), (rhs_type, _, rhs_value)) = (lhs_result, rhs_result);
+    // 两个操作数中不是整型的那个
+    let not_integer = || match lhs_type {
+        Int => formatter.not_integer(&expr_str(rhs)),
+        _ => formatter.not_integer(&expr_str(lhs)),
+    };
+    match op {
+        Assign(_) if !lhs_left_value => Err(formatter.not_left_value(&expr_str(lhs))),
+        Assign(_) if !rhs_type.can_convert_to(&lhs_type) => {
+            Err(formatter.type_mismatch(&lhs_type.to_string(), &rhs_type.to_string()))
         }
+        Assign(_) => Ok((lhs_type, true, None)),
         Arith(op) => match (lhs_type, lhs_value, rhs_type, rhs_value) {
             (_, Some(lhs), _, Some(rhs)) => {
-                let val = match op {
-                    Multiply => lhs * rhs,
-                    Divide => lhs / rhs,
-                    Modulus => lhs % rhs,
-                    Add => lhs + rhs,
-                    Subtract => lhs - rhs,
-                    BitLeftShift => lhs << rhs,
-                    BitRightShift => lhs >> rhs,
-                    BirXor => lhs ^ rhs,
-                    BitAnd => lhs & rhs,
-                    BitOr => lhs | rhs,
-                    Equal => (lhs == rhs).into(),
-                    NotEqual => (lhs != rhs).into(),
-                    Greater => (lhs > rhs).into(),
-                    GreaterOrEqual => (lhs >= rhs).into(),
-                    Less => (lhs < rhs).into(),
-                    LessOrEqual => (lhs <= rhs).into(),
-                };
-                Ok((Int, false, Some(val)))
+                let val = checked_arith(op, lhs, rhs);
+                match val {
+                    Some(val) => Ok((Int, false, Some(val))),
+                    None => Err(overflow(op, lhs, rhs, formatter)),
+                }
             }
             (Int, _, Int, _) => Ok((Int, false, None)),
-            _ => Err(format!("{:?} 或 {:?} 不是整数表达式", lhs, rhs)),
+            _ => Err(not_integer()),
         },
         Logic(LogicalAnd) => match (lhs_type, lhs_value, rhs_type, rhs_value) {
             (_, Some(lhs), _, Some(rhs)) => Ok((Int, false, Some((lhs != 0 && rhs != 0).into()))),
             (_, Some(0), Int, _) => Ok((Int, false, Some(0))),
             (Int, _, Int, _) => Ok((Int, false, None)),
-            _ => Err(format!("{:?} 或 {:?} 不是整数表达式", lhs, rhs)),
+            _ => Err(not_integer()),
         },
         Logic(LogicalOr) => match (lhs_type, lhs_value, rhs_type, rhs_value) {
             (_, Some(lhs), _, Some(rhs)) => Ok((Int, false, Some((lhs != 0 || rhs != 0).into()))),
             (_, Some(value), Int, _) if value != 0 => Ok((Int, false, Some(1))),
             (Int, _, Int, _) => Ok((Int, false, None)),
-            _ => Err(format!("{:?} 或 {:?} 不是整数表达式", lhs, rhs)),
+            _ => Err(not_integer()),
+        },
+    }
+}
+
+fn unary<'a>(
+    expr: &Expr,
+    op: &UnaryOp,
+    (expr_type, is_left_value, expr_value): ReturnType<'a>,
+    formatter: &dyn ErrorFormatter,
+) -> Result<ReturnType<'a>, String> {
+    match op {
+        ArithUnary(op) => match (expr_type, expr_value) {
+            (_, Some(i)) => {
+                let value = match op {
+                    LogicalNot => Some((i == 0).into()),
+                    Negative => i.checked_neg(),
+                    BitNot => Some(!i),
+                };
+                match value {
+                    Some(value) => Ok((Int, false, Some(value))),
+                    None => Err(formatter.constant_overflow(&format!("-({})", i))),
+                }
+            }
+            (Int, None) => Ok((Int, false, None)),
+            _ => Err(formatter.not_integer(&expr_str(expr))),
+        },
+        Others(PostfixSelfIncrease) | Others(PostfixSelfDecrease) => match (expr_type, is_left_value) {
+            (Int, true) => Ok((Int, false, None)),
+            _ => Err(formatter.not_left_value(&expr_str(expr))),
+        },
+        Others(PrefixSelfIncrease) | Others(PrefixSelfDecrease) => match (expr_type, is_left_value) {
+            (Int, true) => Ok((Int, true, None)),
+            _ => Err(formatter.not_left_value(&expr_str(expr))),
         },
     }
 }
 
-fn __elem_impl<'a>(subscripts: &mut [Expr], lengths: &'a [usize], context: &'a SymbolTable) -> Result<ReturnType<'a>, String> {
-    for expr in subscripts.iter_mut() {
-        if !matches!(expr.expr_type(context)?, Int) {
-            return Err(format!("{:?} 不是整型表达式", expr));
+/// 常量数组元素在 `values` 中的下标：`indices[0] * lengths[1] * ... + indices[1] * lengths[2] * ... + ...`。
+/// 任一下标越界时为 None。
+pub fn flat_index(indices: &[i64], lengths: &[usize]) -> Option<usize> {
+    zip(indices, lengths).try_fold(0, |index, (&i, &len)| match usize::try_from(i) {
+        Ok(i) if i < len => Some(index * len + i),
+        _ => None,
+    })
+}
+
+/// 对一个表达式求值的下一步。
+enum Step<'a> {
+    /// 求值下一个子表达式。`checked` 时子表达式作为实参、下标或数组字面量的元素，常量值必须在 int 的表示范围内
+    Child {
+        checked: bool,
+    },
+    Done(Result<ReturnType<'a>, String>),
+}
+
+use Step::{Child, Done};
+
+/// 已检查过的整型与 void 表达式直接复用结果；指针表达式需要重新查找维度信息。
+fn cached<'a>(expr: &Expr) -> Option<ReturnType<'a>> {
+    match (&expr.inner, expr.type_, expr.is_left_value) {
+        (ExprInner::Num(i), _, Some(_)) => Some((Int, false, Some(*i))),
+        (_, SimpleType::Int, Some(is_left_value)) => Some((Int, is_left_value, None)),
+        (_, SimpleType::Void, Some(_)) => Some((Type::Void, false, None)),
+        _ => None,
+    }
+}
+
+fn check_int<'a>(result: ReturnType<'a>, checked: bool, formatter: &dyn ErrorFormatter) -> Result<ReturnType<'a>, String> {
+    match result.2 {
+        Some(i) if checked => to_i32(i, formatter).map(|_| result),
+        _ => Ok(result),
+    }
+}
+
+/// 第 `index` 个子表达式，顺序即求值顺序。
+fn child(inner: &mut ExprInner, index: usize) -> &mut Expr {
+    match inner {
+        ExprInner::InfixExpr(lhs, _, rhs) | ExprInner::Comma(lhs, rhs) => match index {
+            0 => lhs,
+            _ => rhs,
+        },
+        ExprInner::UnaryExpr(_, expr) => expr,
+        ExprInner::FunctionCall(_, exprs, _) | ExprInner::ArrayElement(_, exprs, _) | ExprInner::ArrayInit(exprs) => {
+            &mut exprs[index]
         }
+        ExprInner::Num(_) | ExprInner::Identifier(_) => unreachable!(),
+    }
+}
+
+/// 依次求值整型的子表达式 `exprs`，`results` 是已求值的部分，最后一个不是整型时以 `error` 生成的信息报错。
+fn int_children<'a>(exprs: &[Expr], results: &[ReturnType<'a>], error: impl Fn(&str) -> String) -> Option<Step<'a>> {
+    match results.last() {
+        Some((type_, _, _)) if !matches!(type_, Int) => Some(Done(Err(error(&expr_str(&exprs[results.len() - 1]))))),
+        _ if results.len() < exprs.len() => Some(Child { checked: true }),
+        _ => None,
+    }
+}
+
+fn elem<'a>(
+    identifier: &str,
+    subscripts: &[Expr],
+    results: &[ReturnType<'a>],
+    lengths: &'a [usize],
+    formatter: &dyn ErrorFormatter,
+) -> Step<'a> {
+    if let Some(step) = int_children(subscripts, results, |expr| formatter.not_integer(expr)) {
+        return step;
     }
-    match (subscripts.len() - 1).cmp(&lengths.len()) {
+    Done(match (subscripts.len() - 1).cmp(&lengths.len()) {
         Ordering::Less => Ok((Pointer(&lengths[subscripts.len()..]), false, None)),
         Ordering::Equal => Ok((Int, true, None)),
-        Ordering::Greater => Err(format!("{:?} 错误", subscripts)),
-    }
+        Ordering::Greater => Err(formatter.subscript_count(identifier)),
+    })
 }
 
-fn __array_impl<'a>(
-    identifier: &String,
-    subscripts: &mut Vec<Expr>,
+fn array<'a>(
+    identifier: &str,
+    subscripts: &[Expr],
+    results: &[ReturnType<'a>],
     context: &'a SymbolTable,
     id_is_pointer: &mut bool,
-) -> Result<ReturnType<'a>, String> {
+) -> Step<'a> {
+    let formatter = context.formatter();
     match context.search(identifier) {
-        Some(SymbolTableItem::Array(lengths)) => __elem_impl(subscripts, &lengths[1..], context),
+        Some(SymbolTableItem::Array(lengths)) => elem(identifier, subscripts, results, &lengths[1..], formatter),
         Some(SymbolTableItem::Pointer(lengths)) => {
             *id_is_pointer = true;
-            __elem_impl(subscripts, lengths, context)
+            elem(identifier, subscripts, results, lengths, formatter)
         }
-        Some(SymbolTableItem::ConstArray(lengths, init_list)) => {
+        Some(SymbolTableItem::ConstArray(lengths, values)) => {
             if subscripts.len() != lengths.len() {
-                return Err(format!("{:?} 错误", subscripts));
+                return Done(Err(formatter.subscript_count(identifier)));
             }
-            for expr in subscripts.iter_mut() {
-                if !matches!(expr.expr_type(context)?, Int) {
-                    return Err(format!("{:?} 不是整型表达式", expr));
-                }
+            if let Some(step) = int_children(subscripts, results, |expr| formatter.not_integer(expr)) {
+                return step;
             }
             if !subscripts.iter().all(|p| matches!(p.inner, ExprInner::Num(_))) {
-                Ok((Int, false, None))
+                Done(Ok((Int, false, None)))
             } else {
-                if !zip(subscripts.iter(), lengths.iter()).all(|(l, &r)| risk!(l.inner, ExprInner::Num(i) => i as usize) < r) {
-                    return Err("下标超出范围".to_string());
+                let indices: Vec<i64> = subscripts
+                    .iter()
+                    .map(|expr| risk!(expr.inner, ExprInner::Num(i) => i))
+                    .collect();
+                match flat_index(&indices, lengths) {
+                    Some(index) => Done(Ok((Int, false, Some(values[index].into())))),
+                    None => Done(Err(formatter.subscript_out_of_range())),
                 }
-                let mut v_ref = *init_list;
-                for expr in subscripts.iter().take(subscripts.len() - 1) {
-                    let i = risk!(expr.inner, ExprInner::Num(i) => i as usize);
-                    if i >= v_ref.len() {
-                        return Ok((Int, false, Some(0)));
+            }
+        }
+        _ => Done(Err(formatter.not_subscriptable(identifier))),
+    }
+}
+
+/// 根据已求值的子表达式的结果 `results`，决定求值下一个子表达式或得到 `expr` 的结果。
+fn step<'a>(expr: &mut Expr, results: &[ReturnType<'a>], context: &'a SymbolTable) -> Step<'a> {
+    match &mut expr.inner {
+        ExprInner::InfixExpr(lhs, op, rhs) => match results {
+            [lhs_result, rhs_result] => Done(infix(lhs, op, rhs, *lhs_result, *rhs_result, context.formatter())),
+            _ => Child { checked: false },
+        },
+        ExprInner::UnaryExpr(op, expr) => match results {
+            [result] => Done(unary(expr, op, *result, context.formatter())),
+            _ => Child { checked: false },
+        },
+        ExprInner::Num(val) => Done(Ok((Int, false, Some(*val)))),
+        ExprInner::Identifier(id) => Done(match context.search(id) {
+            Some(SymbolTableItem::ConstVariable(i)) => Ok((Int, false, Some((*i).into()))),
+            Some(SymbolTableItem::Variable) => Ok((Int, true, None)),
+            Some(SymbolTableItem::Array(lengths)) => Ok((Pointer(&lengths[1..]), false, None)),
+            Some(SymbolTableItem::ConstArray(_, _)) => Err(context.formatter().const_array_as_pointer(id)),
+            Some(SymbolTableItem::Pointer(lengths)) => Ok((Type::Pointer(lengths), false, None)),
+            _ => Err(context.formatter().undefined_identifier(id)),
+        }),
+        ExprInner::FunctionCall(id, arg_list, _) => match context.search(id) {
+            Some(SymbolTableItem::Function { is_builtin: false, .. }) if id == "main" => {
+                Done(Err(context.formatter().recursive_main()))
+            }
+            Some(SymbolTableItem::Function {
+                ret: type_,
+                params: para_types,
+                ..
+            }) => {
+                if arg_list.len() != para_types.len() {
+                    return Done(Err(context.formatter().argument_count(id, para_types.len(), arg_list.len())));
+                }
+                if let Some((arg_type, _, _)) = results.last() {
+                    let expect_type = &para_types[results.len() - 1];
+                    if !arg_type.can_convert_to(expect_type) {
+                        return Done(Err(context
+                            .formatter()
+                            .argument_mismatch(&expr_str(&arg_list[results.len() - 1]), &expect_type.to_string())));
                     }
-                    v_ref = risk!(&v_ref[i], ConstInitListItem::InitList(l) => l);
                 }
-
-                let i = risk!(subscripts.last().unwrap().inner, ExprInner::Num(i) => i as usize);
-                if i >= v_ref.len() {
-                    Ok((Int, false, Some(0)))
-                } else {
-                    Ok((Int, false, Some(risk!(v_ref[i], ConstInitListItem::Num(i) => i))))
+                match results.len() < arg_list.len() {
+                    true => Child { checked: true },
+                    false => Done(Ok((*type_, false, None))),
                 }
             }
+            Some(item) => Done(Err(context.formatter().not_callable(id, item.kind()))),
+            None => Done(Err(context.formatter().undefined_function(id))),
+        },
+        ExprInner::ArrayElement(identifier, subscripts, id_is_pointer) => {
+            array(identifier, subscripts, results, context, id_is_pointer)
         }
-        _ => Err(format!("{:?} 不能使用下标运算符", identifier)),
+        // 元素各自求值，常量元素被折叠；字面量本身不是常量
+        ExprInner::ArrayInit(elements) => {
+            int_children(elements, results, |expr| context.formatter().array_literal_element(expr))
+                .unwrap_or(Done(Ok((Pointer(&[]), false, None))))
+        }
+        ExprInner::Comma(_, _) => match results {
+            [(_, _, lhs_value), (rhs_type, _, rhs_value)] => Done(Ok((*rhs_type, false, lhs_value.and(*rhs_value)))),
+            _ => Child { checked: false },
+        },
     }
 }
 
-fn __unary_impl<'a>(expr: &mut Expr, op: &UnaryOp, context: &'a SymbolTable) -> Result<ReturnType<'a>, String> {
-    let (expr_type, is_left_value, expr_value) = expr.const_eval_wrap(context)?;
-    match op {
-        ArithUnary(op) => match (expr_type, expr_value) {
-            (_, Some(i)) => {
-                let value = match op {
-                    LogicalNot => (i == 0).into(),
-                    Negative => -i,
-                    BitNot => !i,
-                };
-                Ok((Int, false, Some(value)))
+/// 求值中的表达式。子表达式求值时从父表达式中取出，求值结束后放回原处。
+struct Frame<'a> {
+    expr: Expr,
+    depth: usize,
+    checked: bool,
+    results: Vec<ReturnType<'a>>,
+    /// 某个子表达式求值出错
+    error: Option<String>,
+    trace: Option<(usize, String)>,
+}
+
+impl<'a> Frame<'a> {
+    fn new(expr: Expr, depth: usize, checked: bool, context: &SymbolTable) -> Self {
+        let trace = match context.tracer() {
+            Some(tracer) if tracer.const_eval => Some((tracer.reserve(), expr_str(&expr))),
+            _ => None,
+        };
+        Self {
+            expr,
+            depth,
+            checked,
+            results: Vec::new(),
+            error: None,
+            trace,
+        }
+    }
+
+    /// 记录跟踪并写回表达式的类型与值，返回表达式与结果。
+    fn finish(mut self, result: Result<ReturnType<'a>, String>, context: &SymbolTable) -> (Expr, Result<ReturnType<'a>, String>) {
+        if let (Some((index, text)), Some(tracer)) = (&self.trace, context.tracer()) {
+            let outcome = match &result {
+                Ok((_, _, Some(value))) => value.to_string(),
+                Ok((type_, _, None)) => format!("不是常量（{}）", type_),
+                Err(e) => format!("错误: {}", e),
+            };
+            tracer.fill(
+                *index,
+                format!("const-eval: {}{} => {}", "  ".repeat(self.depth), text, outcome),
+            );
+        }
+        if let Ok((type_, is_left_value, value)) = result {
+            self.expr.is_left_value = Some(is_left_value);
+            if let Some(i) = value {
+                self.expr.inner = ExprInner::Num(i);
             }
-            (Int, None) => Ok((Int, false, None)),
-            _ => Err(format!("{:?} 不是整数表达式", expr)),
-        },
-        Others(PostfixSelfIncrease) | Others(PostfixSelfDecrease) => match (expr_type, is_left_value) {
-            (Int, true) => Ok((Int, false, None)),
-            _ => Err(format!("{:?} 不是左值整型表达式", expr)),
-        },
-        Others(PrefixSelfIncrease) | Others(PrefixSelfDecrease) => match (expr_type, is_left_value) {
-            (Int, true) => Ok((Int, true, None)),
-            _ => Err(format!("{:?} 不是左值整型表达式", expr)),
-        },
+            self.expr.type_ = match type_ {
+                Int => SimpleType::Int,
+                Type::Void => SimpleType::Void,
+                Pointer(_) => SimpleType::Pointer,
+            };
+        }
+        let result = result.and_then(|result| check_int(result, self.checked, context.formatter()));
+        (take(&mut self.expr), result)
     }
 }
 
 impl<'a> Expr {
-    fn __const_eval_impl(&mut self, context: &'a SymbolTable) -> Result<ReturnType<'a>, String> {
-        match &mut self.inner {
-            ExprInner::InfixExpr(lhs, op, rhs) => __infix_impl(lhs, op, rhs, context),
-            ExprInner::UnaryExpr(op, expr) => __unary_impl(expr, op, context),
-            ExprInner::Num(val) => Ok((Int, false, Some(*val))),
-            ExprInner::Identifier(id) => match context.search(id) {
-                Some(SymbolTableItem::ConstVariable(i)) => Ok((Int, false, Some(*i))),
-                Some(SymbolTableItem::Variable) => Ok((Int, true, None)),
-                Some(SymbolTableItem::Array(lengths)) => Ok((Pointer(&lengths[1..]), false, None)),
-                Some(SymbolTableItem::ConstArray(_, _)) => Err(format!("常量数组 {} 不能转为指针", id)),
-                Some(SymbolTableItem::Pointer(lengths)) => Ok((Type::Pointer(lengths), false, None)),
-                _ => Err(format!("{} 不存在，或不是整型、数组或指针变量", id)),
-            },
-            ExprInner::FunctionCall(id, arg_list) => match context.search(id) {
-                Some(SymbolTableItem::Function(type_, para_types)) => {
-                    if arg_list.len() != para_types.len() {
-                        return Err("实参列表长度与函数定义不匹配".to_string());
-                    }
-                    for (expr, expect_type) in zip(arg_list.iter_mut(), para_types.iter()) {
-                        if !expr.expr_type(context)?.can_convert_to(expect_type) {
-                            return Err(format!("{:?} 无法转换到类型 {:?}", expr, expect_type));
+    /// 求值并折叠表达式。用显式的栈代替递归，深层嵌套的表达式不会栈溢出。
+    fn const_eval_wrap(&mut self, context: &'a SymbolTable, depth: usize) -> Result<ReturnType<'a>, String> {
+        if let Some(result) = cached(self) {
+            return Ok(result);
+        }
+        let mut stack = vec![Frame::new(take(self), depth, false, context)];
+        loop {
+            let frame = stack.last_mut().unwrap();
+            let result = match frame.error.take() {
+                Some(e) => Err(e),
+                None => match step(&mut frame.expr, &frame.results, context) {
+                    Child { checked } => {
+                        let expr = child(&mut frame.expr.inner, frame.results.len());
+                        match cached(expr) {
+                            Some(result) => match check_int(result, checked, context.formatter()) {
+                                Ok(result) => frame.results.push(result),
+                                Err(e) => frame.error = Some(e),
+                            },
+                            None => {
+                                let frame = Frame::new(take(expr), frame.depth + 1, checked, context);
+                                stack.push(frame);
+                            }
                         }
+                        continue;
+                    }
+                    Done(result) => result,
+                },
+            };
+            let (expr, result) = stack.pop().unwrap().finish(result, context);
+            match stack.last_mut() {
+                None => {
+                    *self = expr;
+                    return result;
+                }
+                Some(parent) => {
+                    *child(&mut parent.expr.inner, parent.results.len()) = expr;
+                    match result {
+                        Ok(result) => parent.results.push(result),
+                        Err(e) => parent.error = Some(e),
                     }
-                    Ok((*type_, false, None))
                 }
-                _ => Err(format!("{} 不存在，或不是函数", id)),
-            },
-            ExprInner::ArrayElement(identifier, subscripts, id_is_pointer) => {
-                __array_impl(identifier, subscripts, context, id_is_pointer)
             }
         }
     }
 
-    fn const_eval_wrap(&mut self, context: &'a SymbolTable) -> Result<ReturnType<'a>, String> {
-        let (type_, is_left_value, value) = self.__const_eval_impl(context)?;
-        if let Some(i) = value {
-            self.inner = ExprInner::Num(i);
-        }
-        self.type_ = match type_ {
-            Int => SimpleType::Int,
-            Type::Void => SimpleType::Void,
-            Pointer(_) => SimpleType::Pointer,
-        };
-        Ok((type_, is_left_value, value))
-    }
-
     pub fn check_expr(&mut self, context: &SymbolTable) -> Result<(), String> {
-        self.const_eval_wrap(context)?;
+        self.expr_type(context)?;
         Ok(())
     }
 
     pub fn expr_type(&mut self, context: &'a SymbolTable) -> Result<Type<'a>, String> {
-        let (type_, _, _) = self.const_eval_wrap(context)?;
+        let (type_, _, value) = self.const_eval_wrap(context, 0)?;
+        if let Some(i) = value {
+            to_i32(i, context.formatter())?;
+        }
         Ok(type_)
     }
 
     pub fn const_eval(&mut self, context: &SymbolTable) -> Result<i32, String> {
-        match self.const_eval_wrap(context)?.2 {
-            Some(i) => Ok(i),
-            None => Err(format!("{:?} 不是常量表达式", self)),
+        match self.const_eval_wrap(context, 0)?.2 {
+            Some(i) => to_i32(i, context.formatter()),
+            None => Err(context.formatter().not_constant(&expr_str(self))),
         }
     }
 }
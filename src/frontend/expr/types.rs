@@ -15,7 +15,9 @@
 // You should have received a copy of the GNU General Public License
 // along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
 
-#[derive(Debug, Clone, Copy)]
+use std::fmt::{Display, Formatter, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Type<'a> {
     Int,
     Void,
@@ -31,3 +33,47 @@ impl Type<'_> {
         }
     }
 }
+
+pub fn lengths_str(lengths: &[usize]) -> String {
+    lengths.iter().map(|len| format!("[{}]", len)).collect()
+}
+
+impl Display for Type<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Void => write!(f, "void"),
+            Type::Pointer(lengths) => write!(f, "int*{}", lengths_str(lengths)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Type::{self, *};
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashMap;
+    use std::hash::{Hash, Hasher};
+
+    fn hash(type_: Type) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        type_.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn types_as_map_keys() {
+        assert_eq!(hash(Int), hash(Int));
+        assert_eq!(hash(Pointer(&[3])), hash(Pointer(&[1, 3][1..])));
+        assert_ne!(Pointer(&[3]), Pointer(&[4]));
+        assert_ne!(Pointer(&[]), Int);
+        let mut count = HashMap::new();
+        for type_ in [Int, Void, Pointer(&[3]), Int, Pointer(&[3]), Pointer(&[4])] {
+            *count.entry(type_).or_insert(0) += 1;
+        }
+        assert_eq!(
+            (count[&Int], count[&Void], count[&Pointer(&[3])], count[&Pointer(&[4])]),
+            (2, 1, 2, 1)
+        );
+    }
+}
@@ -0,0 +1,588 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::ast::{
+    ArithmeticOp::*, ArithmeticUnaryOp::*, AssignOp::*, ExprInner::*, InfixOp::*, LogicOp::*, OtherUnaryOp::*, UnaryOp::*, *,
+};
+use super::lexer::tokenize;
+use super::parser::{build_ast, layout};
+use crate::preprocessor::preprocess;
+use std::iter::Peekable;
+
+const INDENT: &str = "    ";
+
+// 优先级与 parser.rs 中的 Pratt 解析器一致，数值越大结合越紧
+const ASSIGN: u8 = 1;
+const PREFIX: u8 = 12;
+const POSTFIX: u8 = 13;
+const PRIMARY: u8 = 14;
+
+fn infix_op(op: &InfixOp) -> (&'static str, u8) {
+    match op {
+        Assign(Assignment) => ("=", ASSIGN),
+        Assign(AddAssign) => ("+=", ASSIGN),
+        Assign(SubtractAssign) => ("-=", ASSIGN),
+        Assign(MultiplyAssign) => ("*=", ASSIGN),
+        Assign(DivideAssign) => ("/=", ASSIGN),
+        Assign(ModulusAssign) => ("%=", ASSIGN),
+        Assign(BitAndAssign) => ("&=", ASSIGN),
+        Assign(BitOrAssign) => ("|=", ASSIGN),
+        Assign(BitXorAssign) => ("^=", ASSIGN),
+        Assign(BitLeftShiftAssign) => ("<<=", ASSIGN),
+        Assign(BitRightShiftAssign) => (">>=", ASSIGN),
+        Logic(LogicalOr) => ("||", 2),
+        Logic(LogicalAnd) => ("&&", 3),
+        Arith(BitOr) => ("|", 4),
+        Arith(BirXor) => ("^", 5),
+        Arith(BitAnd) => ("&", 6),
+        Arith(Equal) => ("==", 7),
+        Arith(NotEqual) => ("!=", 7),
+        Arith(Greater) => (">", 8),
+        Arith(GreaterOrEqual) => (">=", 8),
+        Arith(Less) => ("<", 8),
+        Arith(LessOrEqual) => ("<=", 8),
+        Arith(BitLeftShift) => ("<<", 9),
+        Arith(BitRightShift) => (">>", 9),
+        Arith(Add) => ("+", 10),
+        Arith(Subtract) => ("-", 10),
+        Arith(Multiply) => ("*", 11),
+        Arith(Divide) => ("/", 11),
+        Arith(Modulus) => ("%", 11),
+    }
+}
+
+/// 优先级低于 `min` 的表达式加上括号。
+fn operand(expr: &Expr, min: u8) -> String {
+    match expr_text(expr) {
+        (text, precedence) if precedence < min => format!("({})", text),
+        (text, _) => text,
+    }
+}
+
+fn expr_list(exprs: &[Expr]) -> String {
+    exprs.iter().map(|expr| operand(expr, ASSIGN)).collect::<Vec<_>>().join(", ")
+}
+
+fn subscripts(exprs: &[Expr]) -> String {
+    exprs.iter().map(|expr| format!("[{}]", operand(expr, ASSIGN))).collect()
+}
+
+// 逗号表达式按左结合折叠，左侧的逗号表达式不需要再加括号
+fn comma_list(expr: &Expr) -> String {
+    match &expr.inner {
+        Comma(lhs, rhs) => format!("{}, {}", comma_list(lhs), operand(rhs, ASSIGN)),
+        _ => operand(expr, ASSIGN),
+    }
+}
+
+fn expr_text(expr: &Expr) -> (String, u8) {
+    match &expr.inner {
+        InfixExpr(lhs, op, rhs) => {
+            let (op, precedence) = infix_op(op);
+            let (lhs, rhs) = match precedence {
+                ASSIGN => (operand(lhs, precedence + 1), operand(rhs, precedence)),
+                _ => (operand(lhs, precedence), operand(rhs, precedence + 1)),
+            };
+            (format!("{} {} {}", lhs, op, rhs), precedence)
+        }
+        UnaryExpr(Others(op @ (PostfixSelfIncrease | PostfixSelfDecrease)), expr) => {
+            let op = if matches!(op, PostfixSelfIncrease) { "++" } else { "--" };
+            (format!("{}{}", operand(expr, POSTFIX), op), POSTFIX)
+        }
+        UnaryExpr(op, expr) => {
+            let op = match op {
+                ArithUnary(LogicalNot) => "!",
+                ArithUnary(Negative) => "-",
+                ArithUnary(BitNot) => "~",
+                Others(PrefixSelfIncrease) => "++",
+                Others(PrefixSelfDecrease) => "--",
+                Others(_) => unreachable!(),
+            };
+            // 避免 `-(-a)` 被写成 `--a`
+            let text = match operand(expr, PREFIX) {
+                text if op != "!" && op != "~" && text.starts_with(['-', '+']) => format!("({})", text),
+                text => text,
+            };
+            (format!("{}{}", op, text), PREFIX)
+        }
+        Num(i) => (i.to_string(), PRIMARY),
+        Identifier(id) => (id.clone(), PRIMARY),
+        FunctionCall(id, args, _) => (format!("{}({})", id, expr_list(args)), PRIMARY),
+        ArrayElement(id, exprs, _) => (format!("{}{}", id, subscripts(exprs)), PRIMARY),
+        Comma(..) => (format!("({})", comma_list(expr)), PRIMARY),
+        ArrayInit(elements) => (format!("{{{}}}", expr_list(elements)), PRIMARY),
+    }
+}
+
+/// 表达式的源代码形式，供跟踪输出与诊断信息使用。
+pub fn expr_str(expr: &Expr) -> String {
+    expr_text(expr).0
+}
+
+/// 运算符的源代码形式，如 `<<`。
+pub fn arith_op_str(op: &ArithmeticOp) -> &'static str {
+    infix_op(&Arith(op.clone())).0
+}
+
+/// 初始化列表的源代码形式，供诊断信息使用。
+pub fn init_list_str(init_list: &InitList) -> String {
+    init_list_text(init_list)
+}
+
+fn init_list_text(init_list: &InitList) -> String {
+    let items: Vec<_> = init_list
+        .iter()
+        .map(|item| match item {
+            InitListItem::InitList(init_list) => init_list_text(init_list),
+            InitListItem::Expr(expr) => operand(expr, ASSIGN),
+        })
+        .collect();
+    format!("{{{}}}", items.join(", "))
+}
+
+fn definition_text(definition: &Definition) -> String {
+    match definition {
+        Definition::ConstVariableDefTmp(id, expr) => format!("const int {} = {}", id, operand(expr, ASSIGN)),
+        Definition::ConstArrayDefTmp { id, lengths, init_list } => {
+            format!("const int {}{} = {}", id, subscripts(lengths), init_list_text(init_list))
+        }
+        Definition::VariableDef(id, None) => format!("int {}", id),
+        Definition::VariableDef(id, Some(expr)) => format!("int {} = {}", id, operand(expr, ASSIGN)),
+        Definition::ArrayDefTmp {
+            id,
+            lengths,
+            init_list: None,
+        } => format!("int {}{}", id, subscripts(lengths)),
+        Definition::ArrayDefTmp {
+            id,
+            lengths,
+            init_list: Some(init_list),
+        } => {
+            format!("int {}{} = {}", id, subscripts(lengths), init_list_text(init_list))
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// 源代码中的一条注释。`offset` 是起始处的字节偏移，`trailing` 表示同一行中注释之前有代码。
+struct Comment<'a> {
+    offset: usize,
+    text: &'a str,
+    trailing: bool,
+}
+
+/// 词法单元之间的注释，按出现的顺序排列。
+fn comments(code: &str) -> Vec<Comment<'_>> {
+    let mut comments = Vec::new();
+    let mut previous_end = None;
+    for token in tokenize(code) {
+        let mut start = previous_end.unwrap_or(0);
+        let mut trailing = previous_end.is_some();
+        loop {
+            let gap = &code[start..token.offset];
+            let rest = gap.trim_start();
+            trailing &= !gap[..gap.len() - rest.len()].contains('\n');
+            let len = if rest.starts_with("//") {
+                rest.find('\n').unwrap_or(rest.len())
+            } else if rest.starts_with("/*") {
+                rest.find("*/").map_or(rest.len(), |end| end + 2)
+            } else {
+                break;
+            };
+            let offset = token.offset - rest.len();
+            comments.push(Comment {
+                offset,
+                text: rest[..len].trim_end(),
+                trailing,
+            });
+            start = offset + len;
+        }
+        previous_end = Some(token.offset + token.text.len());
+    }
+    comments
+}
+
+/// 把注释换成等长的空白，保持其余代码的字节偏移不变。
+fn blank_comments(code: &str, comments: &[Comment]) -> String {
+    let mut blanked = String::with_capacity(code.len());
+    let mut end = 0;
+    for comment in comments {
+        blanked.push_str(&code[end..comment.offset]);
+        for c in comment.text.chars() {
+            match c {
+                '\n' => blanked.push('\n'),
+                _ => blanked.push_str(&" ".repeat(c.len_utf8())),
+            }
+        }
+        end = comment.offset + comment.text.len();
+    }
+    blanked.push_str(&code[end..]);
+    blanked
+}
+
+/// 逐行输出格式化的代码，并把注释放回最近的语句或定义旁: 独占一行的注释放在其后的第一个语句或定义之前
+/// （或语句块的右花括号之前），行尾的注释接在其前最近开始的语句或定义的第一行末尾。
+struct Writer<'a> {
+    out: String,
+    comments: Peekable<std::vec::IntoIter<Comment<'a>>>,
+    items: std::vec::IntoIter<usize>,
+    bodies: std::vec::IntoIter<usize>,
+    /// 最近开始的语句或定义的第一行在 `out` 中的结尾
+    anchor: Option<usize>,
+    mark_anchor: bool,
+}
+
+impl Writer<'_> {
+    fn line(&mut self, depth: usize, line: &str) {
+        self.out.push_str(&INDENT.repeat(depth));
+        self.out.push_str(line);
+        if std::mem::take(&mut self.mark_anchor) {
+            self.anchor = Some(self.out.len());
+        }
+        self.out.push('\n');
+    }
+
+    /// 输出 `end` 之前的注释。
+    fn flush(&mut self, depth: usize, end: usize) {
+        while let Some(comment) = self.comments.next_if(|comment| comment.offset < end) {
+            match self.anchor {
+                Some(anchor) if comment.trailing => {
+                    self.out.insert_str(anchor, &format!(" {}", comment.text));
+                    self.anchor = Some(anchor + 1 + comment.text.len());
+                }
+                _ => self.line(depth, comment.text),
+            }
+        }
+    }
+
+    /// 开始下一个语句或定义: 先输出它之前的注释，它的第一行成为行尾注释的位置。
+    fn begin(&mut self, depth: usize) {
+        let start = self.items.next().unwrap();
+        self.flush(depth, start);
+        self.mark_anchor = true;
+    }
+
+    fn body_end(&mut self) -> usize {
+        self.bodies.next().unwrap()
+    }
+
+    fn has_comment_before(&mut self, end: usize) -> bool {
+        self.comments.peek().is_some_and(|comment| comment.offset < end)
+    }
+
+    // `else { if ... }` 与 `else if ...` 的语法树相同，统一写成后者
+    fn write_if(&mut self, depth: usize, prefix: &str, condition: &Expr, then_block: &Block, else_block: &Block) {
+        let (then_end, else_end) = (self.body_end(), self.body_end());
+        self.line(depth, &format!("{}if ({}) {{", prefix, expr_text(condition).0));
+        self.write_block_items(depth + 1, then_block);
+        match else_block.as_slice() {
+            [] => {
+                self.flush(depth + 1, else_end);
+                self.line(depth, "}");
+            }
+            [BlockItem::Statement(statement)] if matches!(statement.as_ref(), Statement::If { .. }) => {
+                let Statement::If {
+                    condition,
+                    then_block,
+                    else_block,
+                } = statement.as_ref()
+                else {
+                    unreachable!()
+                };
+                self.flush(depth + 1, then_end);
+                self.begin(depth + 1);
+                self.write_if(depth, "} else ", condition, then_block, else_block);
+            }
+            _ => {
+                self.flush(depth + 1, then_end);
+                self.line(depth, "} else {");
+                self.write_block_items(depth + 1, else_block);
+                self.flush(depth + 1, else_end);
+                self.line(depth, "}");
+            }
+        }
+    }
+
+    fn write_statement(&mut self, depth: usize, statement: &Statement) {
+        match statement {
+            Statement::Expr(expr) => self.line(depth, &format!("{};", expr_text(expr).0)),
+            Statement::If {
+                condition,
+                then_block,
+                else_block,
+            } => self.write_if(depth, "", condition, then_block, else_block),
+            Statement::While { condition, block } => {
+                let end = self.body_end();
+                self.line(depth, &format!("while ({}) {{", expr_text(condition).0));
+                self.write_block_items(depth + 1, block);
+                self.flush(depth + 1, end);
+                self.line(depth, "}");
+            }
+            Statement::Return(None) => self.line(depth, "return;"),
+            Statement::Return(Some(expr)) => self.line(depth, &format!("return {};", expr_text(expr).0)),
+            Statement::Break => self.line(depth, "break;"),
+            Statement::Continue => self.line(depth, "continue;"),
+            Statement::Goto(label) => self.line(depth, &format!("goto {};", label)),
+            Statement::Label(label) => self.line(depth, &format!("{}:", label)),
+        }
+    }
+
+    fn write_block_items(&mut self, depth: usize, block: &Block) {
+        for item in block {
+            self.begin(depth);
+            match item {
+                BlockItem::Def(definition) => self.line(depth, &format!("{};", definition_text(definition))),
+                BlockItem::Block(block) => {
+                    let end = self.body_end();
+                    // 空的语句块中有注释时也要展开
+                    if block.is_empty() && !self.has_comment_before(end) {
+                        self.line(depth, "{}");
+                        continue;
+                    }
+                    self.line(depth, "{");
+                    self.write_block_items(depth + 1, block);
+                    self.flush(depth + 1, end);
+                    self.line(depth, "}");
+                }
+                BlockItem::Statement(statement) => self.write_statement(depth, statement),
+            }
+        }
+    }
+
+    fn write_global_item(&mut self, item: &GlobalItem) {
+        self.begin(0);
+        match item {
+            GlobalItem::Def(definition) => self.line(0, &format!("{};", definition_text(definition))),
+            GlobalItem::FuncDef {
+                return_void,
+                id,
+                parameter_list,
+                block,
+            } => {
+                let end = self.body_end();
+                let parameters: Vec<_> = parameter_list.iter().map(parameter_text).collect();
+                let return_type = if *return_void { "void" } else { "int" };
+                self.line(0, &format!("{} {}({}) {{", return_type, id, parameters.join(", ")));
+                self.write_block_items(1, block);
+                self.flush(1, end);
+                self.line(0, "}");
+            }
+        }
+    }
+}
+
+fn parameter_text(parameter: &Parameter) -> String {
+    match parameter {
+        Parameter::Int(id) => format!("int {}", id),
+        Parameter::PointerTmp(id, lengths) => format!("int {}[]{}", id, subscripts(lengths)),
+        Parameter::Pointer(..) => unreachable!(),
+    }
+}
+
+/// 文件开头的注释在 `start`（第一个定义）之前的结束处。注释与定义之间有空行时，最后一个空行之后的注释
+/// （如 `///` 文档注释）紧挨着定义；没有空行时开头的注释都是文件开头的注释。
+fn header_end(code: &str, comments: &[Comment], start: usize) -> usize {
+    let mut end = start;
+    let mut previous_end = None;
+    let comments = comments.iter().take_while(|comment| comment.offset < start);
+    for (offset, comment_end) in comments
+        .map(|comment| (comment.offset, comment.offset + comment.text.len()))
+        .chain([(start, start)])
+    {
+        // 注释之间只有空白，两个以上的换行即空行
+        if previous_end.is_some_and(|previous_end| code[previous_end..offset].matches('\n').count() > 1) {
+            end = offset;
+        }
+        previous_end = Some(comment_end);
+    }
+    end
+}
+
+/// 格式化源代码。注释按 [`Writer`] 的规则放回，文件开头的注释与第一个定义之间空一行。
+pub fn format_code(code: &str) -> Result<String, String> {
+    let comments = comments(code);
+    let blanked = preprocess(&blank_comments(code, &comments));
+    let ast = build_ast(&blanked)?;
+    let layout = layout(&blanked)?;
+    let header_end = header_end(code, &comments, layout.items[0]);
+    let mut writer = Writer {
+        out: String::new(),
+        comments: comments.into_iter().peekable(),
+        items: layout.items.into_iter(),
+        bodies: layout.bodies.into_iter(),
+        anchor: None,
+        mark_anchor: false,
+    };
+    let mut previous_is_function = None;
+    for item in &ast {
+        let is_function = matches!(item.as_ref(), GlobalItem::FuncDef { .. });
+        match previous_is_function {
+            Some(previous) if previous || is_function => writer.out.push('\n'),
+            Some(_) => (),
+            None => {
+                writer.flush(0, header_end);
+                if !writer.out.is_empty() {
+                    writer.out.push('\n');
+                }
+            }
+        }
+        writer.write_global_item(item);
+        previous_is_function = Some(is_function);
+    }
+    writer.flush(0, usize::MAX);
+    Ok(writer.out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::json::dump_ast;
+    use super::*;
+
+    const CORPUS: [&str; 5] = [
+        "// 头部注释
+/* 第二段
+   注释 */
+const int N=2,M = N*3;
+const int c[N] = {1, 2};
+int g; int a[2][2] = {{1}, {2, 3}};
+void f(int x, int p[][2]) { p[0][1] = x; }
+int main() {
+    int i = -c[1];
+    { i++; }
+    if (i < 0 && i != 1) i = (g, 1); else f(i, a);
+    while (1) { if (i) break; else continue; }
+    goto end;
+    end:
+    return i;
+}",
+        "int main(){int a=1,b=2;a=b=3;a+=-(-b);a=a- -b;a=(a+b)*(a-b)%7;a=-a++;a=(-a)++;--a;-(--a);
+if(a)if(b)a=1;else{a=2;}else if(!a)a=3;else{if(b)a=4;}
+while(a<10&&(b>1||a==0))a=a<<1>>(b&3^1);return ~a;}",
+        "int f(int a,int b){return a;} int main(){return f((1,2),3)+(1,2,3)+(1,(2,3))+f(1,2)-f(f(1,2),3);}",
+        "int main(){{} if(1); else {} while(0){} return 0;}",
+        "int s(int a[]){return a[0];} int main(){{1,2};putarray(2,{1,s({3})});return s({1+2,4});}",
+    ];
+
+    // 不同排版下函数调用所在的行号不同，比较时忽略
+    fn shape(code: &str) -> String {
+        let ast = build_ast(&preprocess(code)).unwrap();
+        dump_ast(&ast)
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("\"line\""))
+            .collect()
+    }
+
+    #[test]
+    fn formatting_preserves_the_ast() {
+        for code in CORPUS {
+            let formatted = format_code(code).unwrap();
+            assert_eq!(shape(code), shape(&formatted), "{}", formatted);
+        }
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        for code in CORPUS {
+            let formatted = format_code(code).unwrap();
+            assert_eq!(format_code(&formatted).unwrap(), formatted);
+        }
+    }
+
+    #[test]
+    fn layout() {
+        assert_eq!(
+            format_code("// x\nint a=1,b[2]={1};int main(){if(a)return a;else if(b[0])a=-(-a);else{a++;}return 0;}").unwrap(),
+            "// x
+
+int a = 1;
+int b[2] = {1};
+
+int main() {
+    if (a) {
+        return a;
+    } else if (b[0]) {
+        a = -(-a);
+    } else {
+        a++;
+    }
+    return 0;
+}
+"
+        );
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        let code = "// 头部注释
+
+/// 平方
+int sq(int x) { return x * x; } // 行尾
+int g = 1; /* 块 */ int h; // 接在 h 后
+int main() {
+    // 循环之前
+    int i = 0; // 计数
+    while (i < 3) { // 条件
+        i++;
+        // 循环体末尾
+    }
+    if (i) { /* 只有注释 */ } else if (g) g = 2; // 分支
+    else {
+        {
+            // 空的语句块
+        }
+    }
+    return sq(i /* 参数 */);
+}
+// 文件末尾
+";
+        let formatted = format_code(code).unwrap();
+        assert_eq!(
+            formatted,
+            "// 头部注释
+
+/// 平方
+int sq(int x) {
+    return x * x; // 行尾
+}
+
+int g = 1; /* 块 */
+int h; // 接在 h 后
+
+int main() {
+    // 循环之前
+    int i = 0; // 计数
+    while (i < 3) { // 条件
+        i++;
+        // 循环体末尾
+    }
+    if (i) { /* 只有注释 */
+    } else if (g) {
+        g = 2; // 分支
+    } else {
+        {
+            // 空的语句块
+        }
+    }
+    return sq(i); /* 参数 */
+}
+// 文件末尾
+"
+        );
+        assert_eq!(format_code(&formatted).unwrap(), formatted);
+        assert_eq!(shape(code), shape(&formatted));
+    }
+}
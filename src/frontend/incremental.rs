@@ -0,0 +1,391 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! 供编辑器使用的增量检查。文档按全局项（全局定义或函数）切分，每次编辑只重新分析编辑范围所在的全局项；
+//! 函数体的检查结果按它依赖的全局信息缓存，全局常量或函数签名改变时才重新检查其他函数体。
+
+use super::ast::{Block, Definition, GlobalItem, TranslationUnit};
+use super::checker::{self, BodyResult};
+use super::lexer::{self, TokenKind};
+use super::parser;
+use crate::preprocessor;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::mem::take;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// 一条诊断信息。`span` 是它所在的全局项在文本中的字节范围。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpannedDiagnostic {
+    pub span: Range<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+struct Item {
+    span: Range<usize>,
+    start: (usize, usize),
+    parsed: Result<TranslationUnit, String>,
+    // 预处理后的文本，与位置无关
+    code: String,
+}
+
+impl Item {
+    fn parse(text: &str, span: Range<usize>, (line, column): (usize, usize)) -> Self {
+        // 补上前面的换行和空格，使错误信息中的行列号与整个文本一致
+        let code = preprocessor::preprocess(&text[span.clone()]);
+        let parsed = parser::build_ast(&("\n".repeat(line - 1) + &" ".repeat(column - 1) + &code));
+        Self {
+            span,
+            start: (line, column),
+            parsed,
+            code,
+        }
+    }
+
+    /// 函数的文本中函数体之前的部分。预处理后没有注释，第一个左花括号就是函数体的开始。
+    fn signature(&self) -> &str {
+        &self.code[..self.code.find('{').unwrap_or(self.code.len())]
+    }
+}
+
+/// 按词法单元切分全局项，返回各项的字节范围与起始行列号。全局项以深度为 0 的分号结束；
+/// 紧跟在右括号后的左花括号是函数体，与它配对的右花括号结束函数。最后一项可能没有结束。
+fn split(text: &str) -> Vec<(Range<usize>, (usize, usize))> {
+    let mut items = Vec::new();
+    let mut begin = None;
+    let (mut depth, mut body, mut previous, mut end) = (0usize, false, "", 0);
+    for token in lexer::tokenize(text) {
+        if token.kind == TokenKind::Eof {
+            break;
+        }
+        let (offset, start) = *begin.get_or_insert((token.offset, token.start));
+        end = token.offset + token.text.len();
+        let punctuator = match token.kind {
+            TokenKind::Punctuator => token.text,
+            _ => "",
+        };
+        match punctuator {
+            "{" if depth == 0 && previous == ")" => {
+                body = true;
+                depth += 1;
+            }
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+        if depth == 0 && (punctuator == ";" || punctuator == "}" && body) {
+            items.push((offset..end, start));
+            (begin, body) = (None, false);
+        }
+        previous = punctuator;
+    }
+    if let Some((offset, start)) = begin {
+        items.push((offset..end, start));
+    }
+    items
+}
+
+/// 一份正在编辑的源代码及其诊断信息。
+pub struct Document {
+    text: String,
+    items: Vec<Item>,
+    // 函数体的检查结果，键是函数依赖的全局信息的散列值与函数的完整文本
+    bodies: HashMap<(u64, String), BodyResult>,
+    diagnostics: Vec<SpannedDiagnostic>,
+    verify: bool,
+}
+
+impl Document {
+    pub fn new(text: String) -> Self {
+        let items = split(&text)
+            .into_iter()
+            .map(|(span, start)| Item::parse(&text, span, start))
+            .collect();
+        let mut document = Self {
+            text,
+            items,
+            bodies: HashMap::new(),
+            diagnostics: Vec::new(),
+            verify: false,
+        };
+        document.check();
+        document
+    }
+
+    /// 开启后每次编辑都与完整的重新检查比较，结果不同时 panic。仅用于调试。
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn diagnostics(&self) -> &[SpannedDiagnostic] {
+        &self.diagnostics
+    }
+
+    /// 把字节范围 `range` 中的文本替换为 `replacement`，返回更新后的诊断信息。
+    pub fn edit(&mut self, range: Range<usize>, replacement: &str) -> Result<&[SpannedDiagnostic], String> {
+        if range.start > range.end
+            || range.end > self.text.len()
+            || !self.text.is_char_boundary(range.start)
+            || !self.text.is_char_boundary(range.end)
+        {
+            return Err(format!("编辑范围 {}..{} 不合法", range.start, range.end));
+        }
+        self.text.replace_range(range.clone(), replacement);
+        // 完全在编辑范围之外的全局项文本不变，只需平移位置
+        let mut kept: HashMap<usize, Item> = take(&mut self.items)
+            .into_iter()
+            .filter_map(|mut item| {
+                if item.span.end <= range.start {
+                    Some(item)
+                } else if item.span.start >= range.end {
+                    item.span =
+                        item.span.start - range.len() + replacement.len()..item.span.end - range.len() + replacement.len();
+                    Some(item)
+                } else {
+                    None
+                }
+            })
+            .map(|item| (item.span.start, item))
+            .collect();
+        // 词法分析很快，总是重新切分整个文本，以正确处理跨越全局项的注释
+        self.items = split(&self.text)
+            .into_iter()
+            .map(|(span, start)| match kept.remove(&span.start) {
+                // 语法错误的行列号随位置改变
+                Some(item) if item.span == span && (item.parsed.is_ok() || item.start == start) => Item { start, ..item },
+                _ => Item::parse(&self.text, span, start),
+            })
+            .collect();
+        self.check();
+        if self.verify {
+            let full = Document::new(self.text.clone());
+            assert_eq!(
+                self.diagnostics, full.diagnostics,
+                "增量检查与完整检查的结果不同，文本为:\n{}",
+                self.text
+            );
+        }
+        Ok(&self.diagnostics)
+    }
+
+    fn check(&mut self) {
+        self.diagnostics = self
+            .items
+            .iter()
+            .filter_map(|item| item.parsed.as_ref().err().map(|e| error(item.span.clone(), e.clone())))
+            .collect();
+        if !self.diagnostics.is_empty() {
+            return;
+        }
+        // 函数体依赖全局常量、所有函数签名和它之前的全局变量
+        let mut dependencies = DefaultHasher::new();
+        for item in &self.items {
+            match item.parsed.as_ref().unwrap().first().map(AsRef::as_ref) {
+                Some(GlobalItem::Def(Definition::ConstVariableDefTmp(..) | Definition::ConstArrayDefTmp { .. })) => {
+                    item.code.hash(&mut dependencies)
+                }
+                Some(GlobalItem::FuncDef { .. }) => item.signature().hash(&mut dependencies),
+                _ => (),
+            }
+        }
+        let (mut unit, mut owners, mut keys, mut selected) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        for (index, item) in self.items.iter().enumerate() {
+            for global in item.parsed.as_ref().unwrap() {
+                let (global, key) = match global.as_ref() {
+                    GlobalItem::FuncDef {
+                        return_void,
+                        id,
+                        parameter_list,
+                        ..
+                    } => {
+                        let key = (dependencies.finish(), item.code.clone());
+                        match self.bodies.contains_key(&key) {
+                            // 已有结果的函数体不再检查，只需登记签名
+                            true => (
+                                Box::new(GlobalItem::FuncDef {
+                                    return_void: *return_void,
+                                    id: id.clone(),
+                                    parameter_list: parameter_list.clone(),
+                                    block: Block::default(),
+                                }),
+                                Some(key),
+                            ),
+                            false => (global.clone(), Some(key)),
+                        }
+                    }
+                    GlobalItem::Def(_) => {
+                        item.code.hash(&mut dependencies);
+                        (global.clone(), None)
+                    }
+                };
+                selected.push(key.as_ref().is_some_and(|key| !self.bodies.contains_key(key)));
+                unit.push(global);
+                owners.push(index);
+                keys.push(key);
+            }
+        }
+        let mut results: HashMap<usize, BodyResult> = match checker::check_items(&mut unit, &selected) {
+            Ok(results) => results.into_iter().collect(),
+            Err((global, e)) => {
+                let span = global.map_or(0..0, |global| self.items[owners[global]].span.clone());
+                self.diagnostics = vec![error(span, e)];
+                return;
+            }
+        };
+        let mut bodies = HashMap::new();
+        for (global, key) in keys.into_iter().enumerate() {
+            let result = match key {
+                Some(key) => {
+                    let result = results.remove(&global).or_else(|| self.bodies.get(&key).cloned()).unwrap();
+                    bodies.insert(key, result.clone());
+                    result
+                }
+                None => match results.remove(&global) {
+                    Some(result) => result,
+                    None => continue,
+                },
+            };
+            let span = &self.items[owners[global]].span;
+            match result {
+                Ok(warnings) => self.diagnostics.extend(warnings.into_iter().map(|message| SpannedDiagnostic {
+                    span: span.clone(),
+                    severity: Severity::Warning,
+                    message,
+                })),
+                Err(e) => self.diagnostics.push(error(span.clone(), e)),
+            }
+        }
+        // 丢弃不再对应任何函数的结果
+        self.bodies = bodies;
+    }
+}
+
+fn error(span: Range<usize>, message: String) -> SpannedDiagnostic {
+    SpannedDiagnostic {
+        span,
+        severity: Severity::Error,
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "const int N = 2;\nint g;\nint f(int x) { int g = x; return g + N; }\nint main() { return f(1); }\n";
+
+    fn document(text: &str) -> Document {
+        let mut document = Document::new(text.to_string());
+        document.set_verify(true);
+        document
+    }
+
+    fn replace(document: &mut Document, old: &str, new: &str) -> Vec<SpannedDiagnostic> {
+        let start = document.text().find(old).unwrap();
+        document.edit(start..start + old.len(), new).unwrap().to_vec()
+    }
+
+    fn messages(diagnostics: &[SpannedDiagnostic]) -> Vec<&str> {
+        diagnostics.iter().map(|diagnostic| diagnostic.message.as_str()).collect()
+    }
+
+    #[test]
+    fn split_global_items() {
+        let spans: Vec<_> = split(" int a[2] = {1, 2}; int f() { if (1) {} }\n int b, c")
+            .into_iter()
+            .collect();
+        assert_eq!(spans, [(1..19, (1, 2)), (20..41, (1, 21)), (43..51, (2, 2))]);
+    }
+
+    #[test]
+    fn same_warnings_as_full_check() {
+        let document = document(CODE);
+        let (_, warnings) = checker::check(vec![parser::build_ast(CODE).unwrap()], &["a.sy"]).unwrap();
+        let warnings: Vec<&str> = warnings.iter().map(|(_, warning)| warning.as_str()).collect();
+        assert_eq!(messages(document.diagnostics()), warnings);
+        let function = CODE.find("int f").unwrap()..CODE.find("\nint main").unwrap();
+        assert!(document.diagnostics().iter().all(|diagnostic| diagnostic.span == function));
+    }
+
+    #[test]
+    fn spans_follow_edits() {
+        let mut document = document(CODE);
+        let before = document.diagnostics()[0].span.clone();
+        let diagnostics = replace(&mut document, "int g;\n", "int g;\n\n// 注释\n");
+        assert_eq!(diagnostics[0].span, before.start + 11..before.end + 11);
+    }
+
+    #[test]
+    fn errors_in_each_function() {
+        let mut document = document(CODE);
+        replace(&mut document, "return f(1);", "return y;");
+        let diagnostics = replace(&mut document, "return g + N;", "return z;");
+        assert_eq!(
+            messages(&diagnostics),
+            ["[E001] z 不存在，或不是整型、数组或指针变量", "[E001] y 不存在，或不是整型、数组或指针变量"]
+        );
+        let diagnostics = replace(&mut document, "return y;", "return 0;");
+        assert_eq!(messages(&diagnostics), ["[E001] z 不存在，或不是整型、数组或指针变量"]);
+    }
+
+    #[test]
+    fn signature_changes_invalidate_callers() {
+        let mut document = document(CODE);
+        assert!(!replace(&mut document, "int f(int x)", "int f(int x, int y)").is_empty());
+        assert!(document
+            .diagnostics()
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error));
+        replace(&mut document, "int f(int x, int y)", "int f(int x)");
+        replace(&mut document, "const int N = 2;", "const int N = 0;");
+        assert_eq!(document.bodies.len(), 2);
+    }
+
+    #[test]
+    fn syntax_errors_and_comments() {
+        let mut document = document(CODE);
+        let diagnostics = replace(&mut document, "int g;", "int g");
+        assert!(diagnostics.len() == 1 && diagnostics[0].message.starts_with("语法错误"));
+        replace(&mut document, "int g", "int g; /*");
+        replace(&mut document, "return f(1); }", "return f(1); } */ int main() { return 0; }");
+        assert!(document
+            .diagnostics()
+            .iter()
+            .all(|diagnostic| diagnostic.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn invalid_range() {
+        let mut document = document("int main() { return 0; } // 注释");
+        let middle = document.text().find('注').unwrap() + 1;
+        assert!(document.edit(middle..middle, "").is_err());
+        assert!(document.edit(0..100, "").is_err());
+        assert!(document.edit(0..0, "\n").unwrap().is_empty());
+    }
+}
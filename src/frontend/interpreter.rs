@@ -0,0 +1,810 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::ast::{ArithmeticOp::*, ArithmeticUnaryOp::*, AssignOp::*, ExprInner::*, InfixOp::*, LogicOp::*, OtherUnaryOp::*};
+use super::ast::{UnaryOp::*, *};
+use super::coverage::COUNTS_PREFIX;
+use super::overflow::OPERATORS;
+use super::profile::PROFILE_PREFIX;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::mem::{replace, take};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const MAX_CALL_DEPTH: usize = 10000;
+const MAX_MEMORY: usize = 1 << 26;
+
+/// 发生错误的函数、调用它的函数与调用所在的行（main 函数为 None）以及运行时错误。
+pub type RuntimeError<'a> = (&'a str, Option<(&'a str, usize)>, String);
+
+type Result<'a, T> = std::result::Result<T, RuntimeError<'a>>;
+
+// 数组与指针记录首地址、各维长度和所在内存块的范围。指针第一维的长度未知，不在 lengths 中
+#[derive(Clone, Copy)]
+enum Binding<'a> {
+    Const(i32),
+    Int(usize),
+    Array(usize, &'a [usize], (usize, usize)),
+    Pointer(usize, &'a [usize], (usize, usize)),
+}
+
+#[derive(Clone, Copy)]
+enum Value {
+    Int(i32),
+    Pointer(usize, (usize, usize)),
+}
+
+enum Place {
+    Element(usize),
+    SubArray(usize, (usize, usize)),
+}
+
+enum Flow<'a> {
+    Next,
+    Break,
+    Continue,
+    Return(i32),
+    Goto(&'a str),
+}
+
+fn arith(op: &ArithmeticOp, lhs: i32, rhs: i32) -> std::result::Result<i32, String> {
+    Ok(match op {
+        Multiply => lhs.wrapping_mul(rhs),
+        Divide | Modulus if rhs == 0 => return Err("除以 0".to_string()),
+        Divide => lhs.wrapping_div(rhs),
+        Modulus => lhs.wrapping_rem(rhs),
+        Add => lhs.wrapping_add(rhs),
+        Subtract => lhs.wrapping_sub(rhs),
+        // 与 RISC-V 一致，移位量只取低 5 位
+        BitLeftShift => lhs.wrapping_shl(rhs as u32),
+        BitRightShift => lhs.wrapping_shr(rhs as u32),
+        BirXor => lhs ^ rhs,
+        BitAnd => lhs & rhs,
+        BitOr => lhs | rhs,
+        Equal => (lhs == rhs).into(),
+        NotEqual => (lhs != rhs).into(),
+        Greater => (lhs > rhs).into(),
+        GreaterOrEqual => (lhs >= rhs).into(),
+        Less => (lhs < rhs).into(),
+        LessOrEqual => (lhs <= rhs).into(),
+    })
+}
+
+fn assign_op(op: &AssignOp) -> Option<ArithmeticOp> {
+    match op {
+        Assignment => None,
+        AddAssign => Some(Add),
+        SubtractAssign => Some(Subtract),
+        MultiplyAssign => Some(Multiply),
+        DivideAssign => Some(Divide),
+        ModulusAssign => Some(Modulus),
+        BitAndAssign => Some(BitAnd),
+        BitOrAssign => Some(BitOr),
+        BitXorAssign => Some(BirXor),
+        BitLeftShiftAssign => Some(BitLeftShift),
+        BitRightShiftAssign => Some(BitRightShift),
+    }
+}
+
+fn contains_label(block: &Block, label: &str) -> bool {
+    block.iter().any(|item| match item {
+        BlockItem::Def(_) => false,
+        BlockItem::Block(block) => contains_label(block, label),
+        BlockItem::Statement(statement) => statement_contains_label(statement, label),
+    })
+}
+
+fn statement_contains_label(statement: &Statement, label: &str) -> bool {
+    match statement {
+        Statement::Label(l) => l == label,
+        Statement::If {
+            then_block, else_block, ..
+        } => contains_label(then_block, label) || contains_label(else_block, label),
+        Statement::While { block, .. } => contains_label(block, label),
+        _ => false,
+    }
+}
+
+fn find_label(items: &[BlockItem], label: &str) -> Option<usize> {
+    items.iter().position(|item| match item {
+        BlockItem::Def(_) => false,
+        BlockItem::Block(block) => contains_label(block, label),
+        BlockItem::Statement(statement) => statement_contains_label(statement, label),
+    })
+}
+
+struct Interpreter<'a, 'io> {
+    functions: HashMap<&'a str, (&'a [Parameter], &'a Block)>,
+    globals: HashMap<&'a str, Binding<'a>>,
+    scopes: Vec<HashMap<&'a str, Binding<'a>>>,
+    memory: Vec<i32>,
+    input: &'io mut dyn BufRead,
+    output: &'io mut dyn Write,
+    errors: &'io mut dyn Write,
+    /// 置位后在下一次函数调用或循环迭代时停止执行
+    cancel: &'io AtomicBool,
+    function: &'a str,
+    /// 当前函数的调用者与调用所在的行
+    call_site: Option<(&'a str, usize)>,
+    depth: usize,
+}
+
+impl<'a> Interpreter<'a, '_> {
+    fn fail<T>(&self, message: String) -> Result<'a, T> {
+        Err((self.function, self.call_site, message))
+    }
+
+    fn check_cancel(&self) -> Result<'a, ()> {
+        match self.cancel.load(Ordering::Relaxed) {
+            true => self.fail("执行被取消".to_string()),
+            false => Ok(()),
+        }
+    }
+
+    fn lookup(&self, id: &str) -> Result<'a, Binding<'a>> {
+        match self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(id))
+            .or_else(|| self.globals.get(id))
+        {
+            Some(binding) => Ok(*binding),
+            None => self.fail(format!("{} 未定义", id)),
+        }
+    }
+
+    fn bind(&mut self, id: &'a str, binding: Binding<'a>) {
+        match self.scopes.last_mut() {
+            Some(scope) => scope.insert(id, binding),
+            None => self.globals.insert(id, binding),
+        };
+    }
+
+    fn alloc(&mut self, values: impl Iterator<Item = i32>) -> Result<'a, (usize, (usize, usize))> {
+        let start = self.memory.len();
+        self.memory.extend(values);
+        if self.memory.len() > MAX_MEMORY {
+            return self.fail("内存不足".to_string());
+        }
+        Ok((start, (start, self.memory.len())))
+    }
+
+    fn read_byte(&mut self, consume: bool) -> Result<'a, Option<u8>> {
+        let byte = match self.input.fill_buf() {
+            Ok(buf) => buf.first().copied(),
+            Err(e) => return self.fail(format!("读取输入失败: {}", e)),
+        };
+        if consume && byte.is_some() {
+            self.input.consume(1);
+        }
+        Ok(byte)
+    }
+
+    fn read_int(&mut self) -> Result<'a, i32> {
+        while self.read_byte(false)?.is_some_and(|c| c.is_ascii_whitespace()) {
+            self.read_byte(true)?;
+        }
+        let negative = match self.read_byte(false)? {
+            Some(c @ (b'-' | b'+')) => {
+                self.read_byte(true)?;
+                c == b'-'
+            }
+            _ => false,
+        };
+        let mut value: Option<i32> = None;
+        while let Some(c) = self.read_byte(false)?.filter(u8::is_ascii_digit) {
+            self.read_byte(true)?;
+            value = Some(value.unwrap_or(0).wrapping_mul(10).wrapping_add((c - b'0').into()));
+        }
+        match value {
+            Some(value) if negative => Ok(value.wrapping_neg()),
+            Some(value) => Ok(value),
+            None => self.fail("输入中没有可以读取的整数".to_string()),
+        }
+    }
+
+    fn write(&mut self, text: &str) -> Result<'a, ()> {
+        match self.output.write_all(text.as_bytes()) {
+            Ok(()) => Ok(()),
+            Err(e) => self.fail(format!("写入输出失败: {}", e)),
+        }
+    }
+
+    fn load(&self, addr: usize) -> i32 {
+        self.memory[addr]
+    }
+
+    fn element(&mut self, id: &'a str, subscripts: &'a [Expr]) -> Result<'a, Place> {
+        let (base, lengths, region, is_pointer) = match self.lookup(id)? {
+            Binding::Array(addr, lengths, region) => (addr, lengths, region, false),
+            Binding::Pointer(addr, lengths, region) => (addr, lengths, region, true),
+            _ => return self.fail(format!("{} 不是数组或指针", id)),
+        };
+        let dimensions = lengths.len() + usize::from(is_pointer);
+        if subscripts.len() > dimensions {
+            return self.fail(format!("{} 的下标个数过多", id));
+        }
+        let mut addr = base as i64;
+        for (i, subscript) in subscripts.iter().enumerate() {
+            let index = self.int(subscript)?;
+            let (length, stride) = match is_pointer {
+                true if i == 0 => (None, lengths.iter().product::<usize>()),
+                true => (Some(lengths[i - 1]), lengths[i..].iter().product()),
+                false => (Some(lengths[i]), lengths[i + 1..].iter().product()),
+            };
+            if length.is_some_and(|length| index < 0 || index as usize >= length) {
+                return self.fail(format!("数组 {} 的第 {} 维下标 {} 越界", id, i + 1, index));
+            }
+            addr += i64::from(index) * stride as i64;
+        }
+        // 指向子数组的指针可以指向内存块的末尾，但不能越过
+        let end = if subscripts.len() < dimensions {
+            region.1 + 1
+        } else {
+            region.1
+        };
+        if addr < region.0 as i64 || addr >= end as i64 {
+            return self.fail(format!("通过 {} 访问的地址越界", id));
+        }
+        match subscripts.len() < dimensions {
+            true => Ok(Place::SubArray(addr as usize, region)),
+            false => Ok(Place::Element(addr as usize)),
+        }
+    }
+
+    fn lvalue(&mut self, expr: &'a Expr) -> Result<'a, usize> {
+        match &expr.inner {
+            Identifier(id) => match self.lookup(id)? {
+                Binding::Int(addr) => Ok(addr),
+                _ => self.fail(format!("{} 不是变量", id)),
+            },
+            ArrayElement(id, subscripts, _) => match self.element(id, subscripts)? {
+                Place::Element(addr) => Ok(addr),
+                Place::SubArray(..) => self.fail(format!("{} 的下标个数不足", id)),
+            },
+            _ => self.fail(format!("{:?} 不是左值表达式", expr)),
+        }
+    }
+
+    fn int(&mut self, expr: &'a Expr) -> Result<'a, i32> {
+        match self.eval(expr)? {
+            Value::Int(value) => Ok(value),
+            Value::Pointer(..) => self.fail(format!("{:?} 不是整型表达式", expr)),
+        }
+    }
+
+    fn pointer(&mut self, expr: &'a Expr) -> Result<'a, (usize, (usize, usize))> {
+        match self.eval(expr)? {
+            Value::Pointer(addr, region) => Ok((addr, region)),
+            Value::Int(_) => self.fail(format!("{:?} 不是指针", expr)),
+        }
+    }
+
+    fn eval(&mut self, expr: &'a Expr) -> Result<'a, Value> {
+        let value = match &expr.inner {
+            Num(i) => *i as i32,
+            Identifier(id) => match self.lookup(id)? {
+                Binding::Const(value) => value,
+                Binding::Int(addr) => self.load(addr),
+                Binding::Array(addr, _, region) | Binding::Pointer(addr, _, region) => return Ok(Value::Pointer(addr, region)),
+            },
+            ArrayElement(id, subscripts, _) => match self.element(id, subscripts)? {
+                Place::Element(addr) => self.load(addr),
+                Place::SubArray(addr, region) => return Ok(Value::Pointer(addr, region)),
+            },
+            FunctionCall(id, args, line) => self.call(id, args, *line)?,
+            InfixExpr(lhs, Assign(op), rhs) => {
+                let value = self.int(rhs)?;
+                let addr = self.lvalue(lhs)?;
+                let value = match assign_op(op) {
+                    Some(op) => arith(&op, self.load(addr), value).or_else(|e| self.fail(e))?,
+                    None => value,
+                };
+                self.memory[addr] = value;
+                value
+            }
+            InfixExpr(lhs, Logic(LogicalAnd), rhs) => (self.int(lhs)? != 0 && self.int(rhs)? != 0).into(),
+            InfixExpr(lhs, Logic(LogicalOr), rhs) => (self.int(lhs)? != 0 || self.int(rhs)? != 0).into(),
+            InfixExpr(lhs, Arith(op), rhs) => {
+                let lhs = self.int(lhs)?;
+                let rhs = self.int(rhs)?;
+                arith(op, lhs, rhs).or_else(|e| self.fail(e))?
+            }
+            UnaryExpr(ArithUnary(op), expr) => {
+                let value = self.int(expr)?;
+                match op {
+                    LogicalNot => (value == 0).into(),
+                    Negative => value.wrapping_neg(),
+                    BitNot => !value,
+                }
+            }
+            UnaryExpr(Others(op), expr) => {
+                let addr = self.lvalue(expr)?;
+                let old = self.load(addr);
+                let new = match op {
+                    PrefixSelfIncrease | PostfixSelfIncrease => old.wrapping_add(1),
+                    PrefixSelfDecrease | PostfixSelfDecrease => old.wrapping_sub(1),
+                };
+                self.memory[addr] = new;
+                match op {
+                    PrefixSelfIncrease | PrefixSelfDecrease => new,
+                    PostfixSelfIncrease | PostfixSelfDecrease => old,
+                }
+            }
+            Comma(lhs, rhs) => {
+                self.eval(lhs)?;
+                return self.eval(rhs);
+            }
+            ArrayInit(elements) => {
+                let values = elements.iter().map(|element| self.int(element)).collect::<Result<Vec<_>>>()?;
+                let (addr, region) = self.alloc(values.into_iter())?;
+                return Ok(Value::Pointer(addr, region));
+            }
+        };
+        Ok(Value::Int(value))
+    }
+
+    fn call_builtin(&mut self, id: &str, args: &'a [Expr]) -> Result<'a, i32> {
+        match (id, args) {
+            ("getint", []) => self.read_int(),
+            ("getch", []) => Ok(self.read_byte(true)?.map_or(-1, i32::from)),
+            ("getarray", [array]) => {
+                let (addr, region) = self.pointer(array)?;
+                let n = self.read_int()?;
+                if n > 0 && addr + n as usize > region.1 {
+                    return self.fail(format!("getarray 读入的 {} 个元素超出了数组的范围", n));
+                }
+                for i in 0..n.max(0) as usize {
+                    self.memory[addr + i] = self.read_int()?;
+                }
+                Ok(n)
+            }
+            ("putint", [value]) => {
+                let value = self.int(value)?;
+                self.write(&value.to_string())?;
+                Ok(0)
+            }
+            ("putch", [value]) => {
+                let value = self.int(value)?;
+                match self.output.write_all(&[value as u8]) {
+                    Ok(()) => Ok(0),
+                    Err(e) => self.fail(format!("写入输出失败: {}", e)),
+                }
+            }
+            ("putarray", [n, array]) => {
+                let n = self.int(n)?;
+                let (addr, region) = self.pointer(array)?;
+                if n > 0 && addr + n as usize > region.1 {
+                    return self.fail(format!("putarray 输出的 {} 个元素超出了数组的范围", n));
+                }
+                let values: String = (0..n.max(0) as usize)
+                    .map(|i| format!(" {}", self.memory[addr + i]))
+                    .collect();
+                self.write(&format!("{}:{}\n", n, values))?;
+                Ok(0)
+            }
+            ("_sysy_write_counters" | "_sysy_write_profile", [n, array]) => {
+                let n = self.int(n)?;
+                let (addr, region) = self.pointer(array)?;
+                if n > 0 && addr + n as usize > region.1 {
+                    return self.fail(format!("{} 输出的 {} 个计数超出了数组的范围", id, n));
+                }
+                let counts = &self.memory[addr..addr + n.max(0) as usize];
+                // 覆盖率的计数在一行中，性能剖析每个计数器一行
+                let text: String = match id {
+                    "_sysy_write_counters" => {
+                        let counts: String = counts.iter().map(|count| format!(" {}", count)).collect();
+                        format!("{} {}{}\n", COUNTS_PREFIX, n, counts)
+                    }
+                    _ => counts
+                        .iter()
+                        .enumerate()
+                        .map(|(i, count)| format!("{} {} {}\n", PROFILE_PREFIX, i, count))
+                        .collect(),
+                };
+                match self.errors.write_all(text.as_bytes()) {
+                    Ok(()) => Ok(0),
+                    Err(e) => self.fail(format!("写入标准错误失败: {}", e)),
+                }
+            }
+            ("_sysy_check_bound", [index, length, line]) => {
+                let (index, length, line) = (self.int(index)?, self.int(length)?, self.int(line)?);
+                match 0 <= index && index < length {
+                    true => Ok(index),
+                    false => self.fail(format!("第 {} 行: 下标 {} 越界，长度为 {}", line, index, length)),
+                }
+            }
+            ("_sysy_check_overflow", [op, lhs, rhs, line]) => {
+                let (op, lhs, rhs, line) = (self.int(op)?, self.int(lhs)?, self.int(rhs)?, self.int(line)?);
+                let (wide_lhs, wide_rhs) = (i64::from(lhs), i64::from(rhs));
+                let result = match op {
+                    0 => wide_lhs + wide_rhs,
+                    1 => wide_lhs - wide_rhs,
+                    2 => wide_lhs * wide_rhs,
+                    _ if rhs == 0 => return self.fail("除以 0".to_string()),
+                    _ => wide_lhs / wide_rhs,
+                };
+                match i32::try_from(result) {
+                    Ok(result) => Ok(result),
+                    Err(_) => self.fail(format!("第 {} 行: {} {} {} 溢出", line, lhs, OPERATORS[op as usize], rhs)),
+                }
+            }
+            // 计时函数只在评测时有意义，解释执行时忽略
+            ("starttime" | "stoptime", []) => Ok(0),
+            _ => self.fail(format!("函数 {} 不存在", id)),
+        }
+    }
+
+    fn call(&mut self, id: &'a str, args: &'a [Expr], line: usize) -> Result<'a, i32> {
+        let Some(&(parameters, body)) = self.functions.get(id) else {
+            return self.call_builtin(id, args);
+        };
+        let values = args.iter().map(|arg| self.eval(arg)).collect::<Result<Vec<_>>>()?;
+        self.check_cancel()?;
+        if self.depth >= MAX_CALL_DEPTH {
+            return self.fail(format!("函数调用嵌套超过 {} 层", MAX_CALL_DEPTH));
+        }
+        let mark = self.memory.len();
+        let scopes = take(&mut self.scopes);
+        let caller = replace(&mut self.function, id);
+        // run 对 main 的调用没有调用者
+        let call_site = replace(&mut self.call_site, (self.depth > 0).then_some((caller, line)));
+        self.depth += 1;
+        self.scopes.push(HashMap::new());
+        for (parameter, value) in parameters.iter().zip(values) {
+            match (parameter, value) {
+                (Parameter::Int(id), Value::Int(value)) => {
+                    let (addr, _) = self.alloc([value].into_iter())?;
+                    self.bind(id, Binding::Int(addr));
+                }
+                (Parameter::Pointer(id, lengths), Value::Pointer(addr, region)) => {
+                    self.bind(id, Binding::Pointer(addr, lengths, region))
+                }
+                _ => return self.fail("实参与形参的类型不匹配".to_string()),
+            }
+        }
+        let value = match self.exec_block(body, None)? {
+            Flow::Return(value) => value,
+            Flow::Goto(label) => return self.fail(format!("无法跳转到标签 {}", label)),
+            // 没有 return 的函数返回 0
+            _ => 0,
+        };
+        self.depth -= 1;
+        self.function = caller;
+        self.call_site = call_site;
+        self.scopes = scopes;
+        self.memory.truncate(mark);
+        Ok(value)
+    }
+
+    fn define(&mut self, definition: &'a Definition) -> Result<'a, ()> {
+        match definition {
+            Definition::ConstVariableDef(id, value) => self.bind(id, Binding::Const(*value)),
+            Definition::ConstArrayDef { id, lengths, values } => {
+                let (addr, region) = self.alloc(values.iter().copied())?;
+                self.bind(id, Binding::Array(addr, lengths, region));
+            }
+            Definition::VariableDef(id, init) => {
+                let value = match init {
+                    Some(init) => self.int(init)?,
+                    None => 0,
+                };
+                let (addr, _) = self.alloc([value].into_iter())?;
+                self.bind(id, Binding::Int(addr));
+            }
+            Definition::ArrayDef { id, lengths, init_list } => {
+                let size = lengths.iter().product::<usize>();
+                if self.memory.len() + size > MAX_MEMORY {
+                    return self.fail("内存不足".to_string());
+                }
+                let (addr, region) = self.alloc(std::iter::repeat_n(0, size))?;
+                if let Some(init_list) = init_list {
+                    self.init_array(addr, init_list, lengths)?;
+                }
+                self.bind(id, Binding::Array(addr, lengths, region));
+            }
+            _ => return self.fail("语法树未经检查".to_string()),
+        }
+        Ok(())
+    }
+
+    // 按初始化列表的顺序求值，缺省的元素保持为 0
+    fn init_array(&mut self, addr: usize, init_list: &'a InitList, lengths: &[usize]) -> Result<'a, usize> {
+        let mut offset = 0;
+        for item in init_list {
+            offset += match item {
+                InitListItem::InitList(l) => self.init_array(addr + offset, l, &lengths[1..])?,
+                InitListItem::Expr(expr) => {
+                    self.memory[addr + offset] = self.int(expr)?;
+                    1
+                }
+            };
+        }
+        Ok(lengths.iter().product())
+    }
+
+    fn exec_block(&mut self, block: &'a Block, goto: Option<&'a str>) -> Result<'a, Flow<'a>> {
+        let mark = self.memory.len();
+        self.scopes.push(HashMap::new());
+        let flow = self.exec_items(block.as_slice(), goto);
+        self.scopes.pop();
+        self.memory.truncate(mark);
+        flow
+    }
+
+    /// `goto` 不为空时，从包含该标签的语句开始执行。
+    fn exec_items(&mut self, items: &'a [BlockItem], mut goto: Option<&'a str>) -> Result<'a, Flow<'a>> {
+        let mut i = match goto {
+            Some(label) => match find_label(items, label) {
+                Some(i) => i,
+                None => return Ok(Flow::Goto(label)),
+            },
+            None => 0,
+        };
+        while i < items.len() {
+            let flow = match &items[i] {
+                BlockItem::Def(definition) => {
+                    self.define(definition)?;
+                    Flow::Next
+                }
+                BlockItem::Block(block) => self.exec_block(block, goto.take())?,
+                BlockItem::Statement(statement) => self.exec_statement(statement, goto.take())?,
+            };
+            match flow {
+                Flow::Next => i += 1,
+                // 标签只能在 goto 之后，只需向后查找
+                Flow::Goto(label) => match find_label(&items[i + 1..], label) {
+                    Some(j) => {
+                        i += j + 1;
+                        goto = Some(label);
+                    }
+                    None => return Ok(Flow::Goto(label)),
+                },
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn exec_statement(&mut self, statement: &'a Statement, goto: Option<&'a str>) -> Result<'a, Flow<'a>> {
+        Ok(match statement {
+            Statement::Expr(expr) => {
+                self.eval(expr)?;
+                Flow::Next
+            }
+            Statement::If {
+                condition,
+                then_block,
+                else_block,
+            } => match goto {
+                Some(label) if contains_label(then_block, label) => self.exec_block(then_block, goto)?,
+                Some(_) => self.exec_block(else_block, goto)?,
+                None if self.int(condition)? != 0 => self.exec_block(then_block, None)?,
+                None => self.exec_block(else_block, None)?,
+            },
+            Statement::While { condition, block } => {
+                let mut goto = goto;
+                loop {
+                    self.check_cancel()?;
+                    if goto.is_none() && self.int(condition)? == 0 {
+                        break Flow::Next;
+                    }
+                    match self.exec_block(block, goto.take())? {
+                        Flow::Next | Flow::Continue => (),
+                        Flow::Break => break Flow::Next,
+                        flow => break flow,
+                    }
+                }
+            }
+            Statement::Return(expr) => Flow::Return(match expr {
+                Some(expr) => self.int(expr)?,
+                None => 0,
+            }),
+            Statement::Break => Flow::Break,
+            Statement::Continue => Flow::Continue,
+            Statement::Goto(label) => Flow::Goto(label),
+            Statement::Label(_) => Flow::Next,
+        })
+    }
+}
+
+/// 解释执行检查过的语法树，返回 main 函数的返回值。运行时库写到标准错误的内容（覆盖率计数）写到 `errors`。
+/// 其他线程置位 `cancel` 后，执行在下一次函数调用或循环迭代时以运行时错误结束。
+///
+/// 每层函数调用都会占用宿主的栈空间，调用者需要为深层递归准备足够大的栈。
+pub fn run<'a>(
+    ast: &'a TranslationUnit,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    errors: &mut dyn Write,
+    cancel: &AtomicBool,
+) -> Result<'a, i32> {
+    let mut interpreter = Interpreter {
+        functions: HashMap::new(),
+        globals: HashMap::new(),
+        scopes: Vec::new(),
+        memory: Vec::new(),
+        input,
+        output,
+        errors,
+        cancel,
+        function: "main",
+        call_site: None,
+        depth: 0,
+    };
+    for item in ast {
+        match item.as_ref() {
+            GlobalItem::Def(definition) => interpreter.define(definition)?,
+            GlobalItem::FuncDef {
+                id,
+                parameter_list,
+                block,
+                ..
+            } => {
+                interpreter.functions.insert(id, (parameter_list, block));
+            }
+        }
+    }
+    let value = interpreter.call("main", &[], 0)?;
+    match interpreter.output.flush() {
+        Ok(()) => Ok(value),
+        Err(e) => Err(("main", None, format!("写入输出失败: {}", e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{checker::check, parser::build_ast};
+    use super::*;
+
+    fn run_code(code: &str, input: &str) -> std::result::Result<(i32, String), String> {
+        let (ast, _) = check(vec![build_ast(code)?], &["test.sy"]).map_err(|(_, e)| e)?;
+        let mut output = Vec::new();
+        let value = run(&ast, &mut input.as_bytes(), &mut output, &mut std::io::sink(), &AtomicBool::new(false))
+            .map_err(|(function, call_site, e)| match call_site {
+                Some((caller, line)) => format!("{}（{} 第 {} 行调用）: {}", function, caller, line, e),
+                None => format!("{}: {}", function, e),
+            })?;
+        Ok((value, String::from_utf8(output).unwrap()))
+    }
+
+    #[test]
+    fn expressions_and_control_flow() {
+        let code = "int g = 3;
+            int f(int x) { g = g + x; return g; }
+            int main() {
+                int a = 7, b = -2, i = 0, s = 0;
+                while (1) {
+                    i++;
+                    if (i % 2) continue;
+                    if (i > 10) break;
+                    s += i;
+                }
+                int t = (f(1), f(2)) * 2;
+                return a / b + a % b * 10 + (a << 2) + (b >> 1) + s * 100 + t * 1000 + !a + ~b + (0 && f(100)) + (1 || f(100)) + g * 10000;
+            }";
+        assert_eq!(
+            run_code(code, ""),
+            Ok((-3 + 10 + 28 - 1 + 3000 + 12000 + 1 + 1 + 60000, String::new()))
+        );
+    }
+
+    #[test]
+    fn arrays_and_pointers() {
+        let code = "const int N = 3;
+            const int c[N] = {1, 2, 3};
+            int sum(int a[], int n) { int s = 0; while (n) { n = n - 1; s = s + a[n]; } return s; }
+            int row(int m[][N], int i) { return sum(m[i], N); }
+            int main() {
+                int m[2][N] = {{c[0]}, {4, 5, 6}};
+                int i = 2;
+                m[0][i] = c[i] * 10;
+                return row(m, 0) * 100 + row(m, 1);
+            }";
+        assert_eq!(run_code(code, ""), Ok((3115, String::new())));
+    }
+
+    #[test]
+    fn array_literals() {
+        let code = "int sum(int a[], int n) { int s = 0; while (n) { n = n - 1; s = s + a[n]; a[n] = 0; } return s; }
+            int main() {
+                int x = getint();
+                putarray(3, {x, x * 2, 3});
+                return sum({x, 10}, 2) + sum({x}, 1);
+            }";
+        assert_eq!(run_code(code, "5"), Ok((20, "3: 5 10 3\n".to_string())));
+    }
+
+    #[test]
+    fn library_functions() {
+        let code = "int main() {
+                int a[4], n = getarray(a);
+                putarray(n, a);
+                putint(getint() + getint());
+                putch(getch());
+                putch(getch());
+                starttime();
+                stoptime();
+                return getch();
+            }";
+        assert_eq!(
+            run_code(code, "3 1 -2 3\n 40 -50\nA"),
+            Ok((-1, "3: 1 -2 3\n-10\nA".to_string()))
+        );
+        assert_eq!(
+            run_code("int main() { return getint(); }", " x"),
+            Err("main: 输入中没有可以读取的整数".to_string())
+        );
+    }
+
+    #[test]
+    fn goto_into_nested_blocks() {
+        let code = "int main() {
+                int s = 0;
+                goto inner;
+                s = s + 1;
+                while (s < 100) {
+                    s = s + 10;
+                    if (s > 1000) {
+                        inner:
+                        s = s + 100;
+                    }
+                }
+                return s;
+            }";
+        assert_eq!(run_code(code, ""), Ok((100, String::new())));
+    }
+
+    #[test]
+    fn runtime_errors() {
+        assert_eq!(
+            run_code("int f(int x) { return 1 / x; } int main() { return f(0); }", ""),
+            Err("f（main 第 1 行调用）: 除以 0".to_string())
+        );
+        assert_eq!(
+            run_code("int main() { int a[2][3]; int i = 3; return a[1][i]; }", ""),
+            Err("main: 数组 a 的第 2 维下标 3 越界".to_string())
+        );
+        assert_eq!(
+            run_code(
+                "int f(int p[]) { return p[2]; } int main() { int a[2][2]; return f(a[1]); }",
+                ""
+            ),
+            Err("f（main 第 1 行调用）: 通过 p 访问的地址越界".to_string())
+        );
+        // 与命令行一致，在栈足够大的线程中运行
+        let result = std::thread::Builder::new()
+            .stack_size(1 << 30)
+            .spawn(|| run_code("int f(int n) { return f(n + 1); } int main() { return f(0); }", ""))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(result, Err("f（f 第 1 行调用）: 函数调用嵌套超过 10000 层".to_string()));
+    }
+
+    #[test]
+    fn cancelled() {
+        let (ast, _) = check(vec![build_ast("int main() { while (1) {} return 0; }").unwrap()], &["test.sy"]).unwrap();
+        let cancel = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            scope.spawn(|| cancel.store(true, Ordering::Relaxed));
+            let result = run(&ast, &mut "".as_bytes(), &mut Vec::new(), &mut std::io::sink(), &cancel);
+            assert_eq!(result, Err(("main", None, "执行被取消".to_string())));
+        });
+    }
+}
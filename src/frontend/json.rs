@@ -0,0 +1,449 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::ast::*;
+use std::fmt::Write;
+
+// 输出格式有不兼容的改动时递增
+const VERSION: i64 = 1;
+// 更深的结点不再增加缩进，否则输出的长度随嵌套深度平方增长
+const MAX_INDENT: usize = 64;
+
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_json(out: &mut String, json: &Json, indent: usize) {
+    let pad = "  ".repeat(indent.min(MAX_INDENT) + 1);
+    match json {
+        Json::Null => out.push_str("null"),
+        Json::Bool(b) => write!(out, "{}", b).unwrap(),
+        Json::Number(i) => write!(out, "{}", i).unwrap(),
+        Json::String(s) => write_string(out, s),
+        Json::Array(items) if items.is_empty() => out.push_str("[]"),
+        Json::Array(items) => {
+            out.push_str("[\n");
+            for (i, item) in items.iter().enumerate() {
+                out.push_str(&pad);
+                write_json(out, item, indent + 1);
+                out.push_str(if i + 1 == items.len() { "\n" } else { ",\n" });
+            }
+            write!(out, "{}]", "  ".repeat(indent.min(MAX_INDENT))).unwrap();
+        }
+        Json::Object(fields) if fields.is_empty() => out.push_str("{}"),
+        Json::Object(fields) => {
+            out.push_str("{\n");
+            for (i, (key, value)) in fields.iter().enumerate() {
+                out.push_str(&pad);
+                write_string(out, key);
+                out.push_str(": ");
+                write_json(out, value, indent + 1);
+                out.push_str(if i + 1 == fields.len() { "\n" } else { ",\n" });
+            }
+            write!(out, "{}}}", "  ".repeat(indent.min(MAX_INDENT))).unwrap();
+        }
+    }
+}
+
+/// 带缩进的 JSON 文本，以换行结尾。
+pub fn json_text(json: &Json) -> String {
+    let mut out = String::new();
+    write_json(&mut out, json, 0);
+    out.push('\n');
+    out
+}
+
+fn kind(kind: &str, mut fields: Vec<(&'static str, Json)>) -> Json {
+    fields.insert(0, ("kind", Json::String(kind.to_string())));
+    Json::Object(fields)
+}
+
+fn string(s: &str) -> Json {
+    Json::String(s.to_string())
+}
+
+fn numbers<T: Copy + Into<i64>>(values: &[T]) -> Json {
+    Json::Array(values.iter().map(|&i| Json::Number(i.into())).collect())
+}
+
+fn lengths_json(lengths: &[usize]) -> Json {
+    Json::Array(lengths.iter().map(|&i| Json::Number(i as i64)).collect())
+}
+
+fn exprs_json(exprs: &[Expr]) -> Json {
+    Json::Array(exprs.iter().map(expr_json).collect())
+}
+
+fn infix_op_str(op: &InfixOp) -> &'static str {
+    match op {
+        InfixOp::Assign(op) => match op {
+            AssignOp::Assignment => "=",
+            AssignOp::AddAssign => "+=",
+            AssignOp::SubtractAssign => "-=",
+            AssignOp::MultiplyAssign => "*=",
+            AssignOp::DivideAssign => "/=",
+            AssignOp::ModulusAssign => "%=",
+            AssignOp::BitAndAssign => "&=",
+            AssignOp::BitOrAssign => "|=",
+            AssignOp::BitXorAssign => "^=",
+            AssignOp::BitLeftShiftAssign => "<<=",
+            AssignOp::BitRightShiftAssign => ">>=",
+        },
+        InfixOp::Arith(op) => match op {
+            ArithmeticOp::Multiply => "*",
+            ArithmeticOp::Divide => "/",
+            ArithmeticOp::Modulus => "%",
+            ArithmeticOp::Add => "+",
+            ArithmeticOp::Subtract => "-",
+            ArithmeticOp::BitLeftShift => "<<",
+            ArithmeticOp::BitRightShift => ">>",
+            ArithmeticOp::BirXor => "^",
+            ArithmeticOp::BitAnd => "&",
+            ArithmeticOp::BitOr => "|",
+            ArithmeticOp::Equal => "==",
+            ArithmeticOp::NotEqual => "!=",
+            ArithmeticOp::Greater => ">",
+            ArithmeticOp::GreaterOrEqual => ">=",
+            ArithmeticOp::Less => "<",
+            ArithmeticOp::LessOrEqual => "<=",
+        },
+        InfixOp::Logic(LogicOp::LogicalAnd) => "&&",
+        InfixOp::Logic(LogicOp::LogicalOr) => "||",
+    }
+}
+
+fn unary_op_str(op: &UnaryOp) -> &'static str {
+    match op {
+        UnaryOp::ArithUnary(ArithmeticUnaryOp::LogicalNot) => "!",
+        UnaryOp::ArithUnary(ArithmeticUnaryOp::Negative) => "-",
+        UnaryOp::ArithUnary(ArithmeticUnaryOp::BitNot) => "~",
+        UnaryOp::Others(OtherUnaryOp::PostfixSelfIncrease) => "postfix ++",
+        UnaryOp::Others(OtherUnaryOp::PostfixSelfDecrease) => "postfix --",
+        UnaryOp::Others(OtherUnaryOp::PrefixSelfIncrease) => "prefix ++",
+        UnaryOp::Others(OtherUnaryOp::PrefixSelfDecrease) => "prefix --",
+    }
+}
+
+fn expr_json(expr: &Expr) -> Json {
+    let mut json = match &expr.inner {
+        ExprInner::InfixExpr(lhs, op, rhs) => kind(
+            "Infix",
+            vec![
+                ("op", string(infix_op_str(op))),
+                ("lhs", expr_json(lhs)),
+                ("rhs", expr_json(rhs)),
+            ],
+        ),
+        ExprInner::UnaryExpr(op, operand) => kind(
+            "Unary",
+            vec![("op", string(unary_op_str(op))), ("operand", expr_json(operand))],
+        ),
+        ExprInner::Num(i) => kind("Num", vec![("value", Json::Number(*i))]),
+        ExprInner::Identifier(id) => kind("Identifier", vec![("id", string(id))]),
+        ExprInner::FunctionCall(id, args, line) => kind(
+            "Call",
+            vec![
+                ("callee", string(id)),
+                ("args", exprs_json(args)),
+                ("line", Json::Number(*line as i64)),
+            ],
+        ),
+        ExprInner::ArrayElement(id, subscripts, _) => kind(
+            "ArrayElement",
+            vec![("id", string(id)), ("subscripts", exprs_json(subscripts))],
+        ),
+        ExprInner::Comma(lhs, rhs) => kind("Comma", vec![("lhs", expr_json(lhs)), ("rhs", expr_json(rhs))]),
+        ExprInner::ArrayInit(elements) => kind("ArrayInit", vec![("elements", exprs_json(elements))]),
+    };
+    // 检查过的表达式带有缓存的类型与左值信息
+    if let (Json::Object(fields), Some(is_left_value)) = (&mut json, expr.is_left_value) {
+        let type_ = match expr.type_ {
+            SimpleType::Int => "int",
+            SimpleType::Pointer => "pointer",
+            SimpleType::Void => "void",
+        };
+        fields.push(("type", string(type_)));
+        fields.push(("lvalue", Json::Bool(is_left_value)));
+    }
+    json
+}
+
+fn init_list_json(init_list: &InitList) -> Json {
+    Json::Array(
+        init_list
+            .iter()
+            .map(|item| match item {
+                InitListItem::InitList(init_list) => init_list_json(init_list),
+                InitListItem::Expr(expr) => expr_json(expr),
+            })
+            .collect(),
+    )
+}
+
+fn definition_json(definition: &Definition) -> Json {
+    match definition {
+        Definition::ConstVariableDefTmp(id, init) => {
+            kind("ConstVariableDef", vec![("id", string(id)), ("init", expr_json(init))])
+        }
+        Definition::ConstVariableDef(id, value) => kind(
+            "ConstVariableDef",
+            vec![("id", string(id)), ("value", Json::Number((*value).into()))],
+        ),
+        Definition::ConstArrayDefTmp { id, lengths, init_list } => kind(
+            "ConstArrayDef",
+            vec![
+                ("id", string(id)),
+                ("lengths", exprs_json(lengths)),
+                ("init", init_list_json(init_list)),
+            ],
+        ),
+        Definition::ConstArrayDef { id, lengths, values } => kind(
+            "ConstArrayDef",
+            vec![
+                ("id", string(id)),
+                ("lengths", lengths_json(lengths)),
+                ("values", numbers(values)),
+            ],
+        ),
+        Definition::VariableDef(id, init) => kind(
+            "VariableDef",
+            vec![("id", string(id)), ("init", init.as_ref().map_or(Json::Null, expr_json))],
+        ),
+        Definition::ArrayDefTmp { id, lengths, init_list } => kind(
+            "ArrayDef",
+            vec![
+                ("id", string(id)),
+                ("lengths", exprs_json(lengths)),
+                ("init", init_list.as_ref().map_or(Json::Null, init_list_json)),
+            ],
+        ),
+        Definition::ArrayDef { id, lengths, init_list } => kind(
+            "ArrayDef",
+            vec![
+                ("id", string(id)),
+                ("lengths", lengths_json(lengths)),
+                ("init", init_list.as_ref().map_or(Json::Null, init_list_json)),
+            ],
+        ),
+    }
+}
+
+fn statement_json(statement: &Statement) -> Json {
+    match statement {
+        Statement::Expr(expr) => kind("ExprStatement", vec![("expr", expr_json(expr))]),
+        Statement::If {
+            condition,
+            then_block,
+            else_block,
+        } => kind(
+            "If",
+            vec![
+                ("condition", expr_json(condition)),
+                ("then", block_json(then_block)),
+                ("else", block_json(else_block)),
+            ],
+        ),
+        Statement::While { condition, block } => kind(
+            "While",
+            vec![("condition", expr_json(condition)), ("body", block_json(block))],
+        ),
+        Statement::Return(value) => kind("Return", vec![("value", value.as_ref().map_or(Json::Null, expr_json))]),
+        Statement::Break => kind("Break", Vec::new()),
+        Statement::Continue => kind("Continue", Vec::new()),
+        Statement::Goto(label) => kind("Goto", vec![("label", string(label))]),
+        Statement::Label(label) => kind("Label", vec![("label", string(label))]),
+    }
+}
+
+fn block_json(block: &Block) -> Json {
+    Json::Array(
+        block
+            .iter()
+            .map(|item| match item {
+                BlockItem::Def(definition) => definition_json(definition),
+                BlockItem::Block(block) => kind("Block", vec![("items", block_json(block))]),
+                BlockItem::Statement(statement) => statement_json(statement),
+            })
+            .collect(),
+    )
+}
+
+fn parameter_json(parameter: &Parameter) -> Json {
+    match parameter {
+        Parameter::Int(id) => kind("IntParameter", vec![("id", string(id))]),
+        Parameter::PointerTmp(id, lengths) => {
+            kind("PointerParameter", vec![("id", string(id)), ("lengths", exprs_json(lengths))])
+        }
+        Parameter::Pointer(id, lengths) => kind(
+            "PointerParameter",
+            vec![("id", string(id)), ("lengths", lengths_json(lengths))],
+        ),
+    }
+}
+
+fn global_item_json(item: &GlobalItem) -> Json {
+    match item {
+        GlobalItem::Def(definition) => definition_json(definition),
+        GlobalItem::FuncDef {
+            return_void,
+            id,
+            parameter_list,
+            block,
+        } => kind(
+            "FuncDef",
+            vec![
+                ("return_type", string(if *return_void { "void" } else { "int" })),
+                ("id", string(id)),
+                ("parameters", Json::Array(parameter_list.iter().map(parameter_json).collect())),
+                ("body", block_json(block)),
+            ],
+        ),
+    }
+}
+
+pub fn dump_ast(ast: &TranslationUnit) -> String {
+    let json = Json::Object(vec![
+        ("version", Json::Number(VERSION)),
+        ("items", Json::Array(ast.iter().map(|item| global_item_json(item)).collect())),
+    ]);
+    json_text(&json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{checker::check, parser::build_ast};
+    use super::*;
+
+    fn checked_json(code: &str) -> String {
+        let (ast, _) = check(vec![build_ast(code).unwrap()], &["test.sy"]).unwrap();
+        dump_ast(&ast)
+    }
+
+    #[test]
+    fn every_node_kind() {
+        let json = checked_json(
+            "const int N = 2;
+            const int c[N] = {1, 2};
+            int g;
+            int a[2][2] = {{1}, {2, 3}};
+            void f(int x, int p[][2]) { p[0][1] = x; }
+            int main() {
+                int i = -c[1];
+                {
+                    i++;
+                }
+                if (i < 0 && i != 1) i = (g, 1); else f(i, a);
+                putarray(2, {i, 1});
+                while (1) { if (i) break; else continue; }
+                goto end;
+                end:
+                return i;
+            }",
+        );
+        for kind in [
+            "ConstVariableDef",
+            "ConstArrayDef",
+            "VariableDef",
+            "ArrayDef",
+            "FuncDef",
+            "IntParameter",
+            "PointerParameter",
+            "Block",
+            "ExprStatement",
+            "If",
+            "While",
+            "Return",
+            "Break",
+            "Continue",
+            "Goto",
+            "Label",
+            "Infix",
+            "Unary",
+            "Num",
+            "Identifier",
+            "Call",
+            "ArrayElement",
+            "Comma",
+            "ArrayInit",
+        ] {
+            assert!(json.contains(&format!("\"kind\": \"{}\"", kind)), "{}", kind);
+        }
+    }
+
+    #[test]
+    fn snapshot() {
+        assert_eq!(
+            checked_json("int main() { return 1 + getint(); }"),
+            r#"{
+  "version": 1,
+  "items": [
+    {
+      "kind": "FuncDef",
+      "return_type": "int",
+      "id": "main",
+      "parameters": [],
+      "body": [
+        {
+          "kind": "Return",
+          "value": {
+            "kind": "Infix",
+            "op": "+",
+            "lhs": {
+              "kind": "Num",
+              "value": 1,
+              "type": "int",
+              "lvalue": false
+            },
+            "rhs": {
+              "kind": "Call",
+              "callee": "getint",
+              "args": [],
+              "line": 1,
+              "type": "int",
+              "lvalue": false
+            },
+            "type": "int",
+            "lvalue": false
+          }
+        }
+      ]
+    }
+  ]
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,291 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt::{self, Display, Formatter};
+
+// 独立于语法分析，仅用于调试时输出词法单元
+const KEYWORDS: [&str; 10] = [
+    "int", "const", "void", "return", "continue", "break", "goto", "if", "else", "while",
+];
+
+// 按长度从长到短排列，保证最长匹配
+const OPERATORS: [&str; 33] = [
+    "<<=", ">>=", "&&", "||", "<<", ">>", "==", "!=", ">=", "<=", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "++", "--",
+    "*", "/", "%", "+", "-", "^", "&", "|", ">", "<", "=", "!", "~",
+];
+
+const PUNCTUATORS: [char; 9] = ['(', ')', '[', ']', '{', '}', ',', ';', ':'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    Identifier,
+    IntegerDec,
+    IntegerOct,
+    IntegerHex,
+    IntegerBin,
+    Operator,
+    CustomOperator,
+    Punctuator,
+    Error,
+    Eof,
+}
+
+/// 位置均为 (行, 列)，从 1 开始计数；`end` 指向词法单元之后的位置。`offset` 是起始位置的字节偏移。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub offset: usize,
+}
+
+impl Display for Token<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let ((line, column), (end_line, end_column)) = (self.start, self.end);
+        write!(f, "{}:{}-{}:{} {:?}", line, column, end_line, end_column, self.kind)?;
+        match self.kind {
+            TokenKind::Eof => Ok(()),
+            _ => write!(f, " {}", self.text),
+        }
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn integer_kind(text: &str) -> TokenKind {
+    let digits_are = |digits: &str, radix| !digits.is_empty() && digits.chars().all(|c| c.is_digit(radix));
+    match text.get(..2) {
+        Some("0x" | "0X") if digits_are(&text[2..], 16) => TokenKind::IntegerHex,
+        Some("0b" | "0B") if digits_are(&text[2..], 2) => TokenKind::IntegerBin,
+        _ if text.starts_with('0') && digits_are(text, 8) => TokenKind::IntegerOct,
+        _ if !text.starts_with('0') && digits_are(text, 10) => TokenKind::IntegerDec,
+        _ => TokenKind::Error,
+    }
+}
+
+struct Lexer<'a> {
+    code: &'a str,
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn rest(&self) -> &'a str {
+        &self.code[self.offset..]
+    }
+
+    fn advance(&mut self, len: usize) {
+        for c in self.code[self.offset..self.offset + len].chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        self.offset += len;
+    }
+
+    /// 跳过空白和注释。未闭合的块注释作为错误单元的长度返回。
+    fn skip_blank(&mut self) -> Option<usize> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.advance(rest.len() - trimmed.len());
+            if trimmed.starts_with("//") {
+                self.advance(trimmed.find('\n').unwrap_or(trimmed.len()));
+            } else if trimmed.starts_with("/*") {
+                match trimmed.find("*/") {
+                    Some(end) => self.advance(end + 2),
+                    None => return Some(trimmed.len()),
+                }
+            } else {
+                return None;
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Token<'a> {
+        let unterminated_comment = self.skip_blank();
+        let rest = self.rest();
+        let (kind, len) = match unterminated_comment {
+            Some(len) => (TokenKind::Error, len),
+            None => match rest.chars().next() {
+                None => (TokenKind::Eof, 0),
+                Some(c) if c.is_ascii_digit() => {
+                    let len = rest.find(|c| !is_identifier_char(c)).unwrap_or(rest.len());
+                    (integer_kind(&rest[..len]), len)
+                }
+                Some(c) if is_identifier_char(c) => {
+                    let len = rest.find(|c| !is_identifier_char(c)).unwrap_or(rest.len());
+                    match KEYWORDS.contains(&&rest[..len]) {
+                        true => (TokenKind::Keyword, len),
+                        false => (TokenKind::Identifier, len),
+                    }
+                }
+                Some('`') => {
+                    let len = rest[1..].find(|c| !is_identifier_char(c)).map_or(rest.len(), |len| len + 1);
+                    match rest[len..].starts_with('`') && len > 1 {
+                        true => (TokenKind::CustomOperator, len + 1),
+                        false => (TokenKind::Error, len),
+                    }
+                }
+                Some(c) if PUNCTUATORS.contains(&c) => (TokenKind::Punctuator, 1),
+                Some(c) => match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                    Some(op) => (TokenKind::Operator, op.len()),
+                    None => (TokenKind::Error, c.len_utf8()),
+                },
+            },
+        };
+        let (start, offset) = ((self.line, self.column), self.offset);
+        self.advance(len);
+        Token {
+            kind,
+            text: &rest[..len],
+            start,
+            end: (self.line, self.column),
+            offset,
+        }
+    }
+}
+
+/// 切分词法单元，以 `Eof` 结尾。遇到无法识别的字符时产生 `Error` 单元并继续。
+pub fn tokenize(code: &str) -> Vec<Token<'_>> {
+    let mut lexer = Lexer {
+        code,
+        offset: 0,
+        line: 1,
+        column: 1,
+    };
+    let mut tokens = Vec::new();
+    loop {
+        let token = lexer.next_token();
+        tokens.push(token);
+        if token.kind == TokenKind::Eof {
+            return tokens;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TokenKind::*;
+    use super::*;
+
+    fn kinds(code: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(code).iter().map(|token| (token.kind, token.text)).collect()
+    }
+
+    #[test]
+    fn integer_literals() {
+        assert_eq!(
+            kinds("0x1F 0XaB 017 0 0b101 42 08 0x 12ab"),
+            [
+                (IntegerHex, "0x1F"),
+                (IntegerHex, "0XaB"),
+                (IntegerOct, "017"),
+                (IntegerOct, "0"),
+                (IntegerBin, "0b101"),
+                (IntegerDec, "42"),
+                (Error, "08"),
+                (Error, "0x"),
+                (Error, "12ab"),
+                (Eof, ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        let tokens = tokenize("a // b\n/* c\n d */ e /* f");
+        assert_eq!(tokens[0].text, "a");
+        assert_eq!((tokens[1].text, tokens[1].start), ("e", (3, 7)));
+        assert_eq!((tokens[2].kind, tokens[2].text), (Error, "/* f"));
+        assert_eq!(tokens[3].kind, Eof);
+    }
+
+    #[test]
+    fn errors_do_not_stop_the_lexer() {
+        assert_eq!(
+            kinds("a @ b `c d"),
+            [
+                (Identifier, "a"),
+                (Error, "@"),
+                (Identifier, "b"),
+                (Error, "`c"),
+                (Identifier, "d"),
+                (Eof, "")
+            ]
+        );
+    }
+
+    #[test]
+    fn snapshot() {
+        let dump: String =
+            tokenize("const int x[2] = {0x1, 07};\nint main() {\n  x[0] <<= 0b1 `max` 3;\n  return -x[1]++ $;\n}\n")
+                .iter()
+                .map(|token| format!("{}\n", token))
+                .collect();
+        assert_eq!(
+            dump,
+            "1:1-1:6 Keyword const
+1:7-1:10 Keyword int
+1:11-1:12 Identifier x
+1:12-1:13 Punctuator [
+1:13-1:14 IntegerDec 2
+1:14-1:15 Punctuator ]
+1:16-1:17 Operator =
+1:18-1:19 Punctuator {
+1:19-1:22 IntegerHex 0x1
+1:22-1:23 Punctuator ,
+1:24-1:26 IntegerOct 07
+1:26-1:27 Punctuator }
+1:27-1:28 Punctuator ;
+2:1-2:4 Keyword int
+2:5-2:9 Identifier main
+2:9-2:10 Punctuator (
+2:10-2:11 Punctuator )
+2:12-2:13 Punctuator {
+3:3-3:4 Identifier x
+3:4-3:5 Punctuator [
+3:5-3:6 IntegerOct 0
+3:6-3:7 Punctuator ]
+3:8-3:11 Operator <<=
+3:12-3:15 IntegerBin 0b1
+3:16-3:21 CustomOperator `max`
+3:22-3:23 IntegerDec 3
+3:23-3:24 Punctuator ;
+4:3-4:9 Keyword return
+4:10-4:11 Operator -
+4:11-4:12 Identifier x
+4:12-4:13 Punctuator [
+4:13-4:14 IntegerDec 1
+4:14-4:15 Punctuator ]
+4:15-4:17 Operator ++
+4:18-4:19 Error $
+4:19-4:20 Punctuator ;
+5:1-5:2 Punctuator }
+6:1-6:1 Eof
+"
+        );
+    }
+}
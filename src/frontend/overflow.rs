@@ -0,0 +1,315 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! 溢出检查: --sanitize=overflow 把函数体中的 `a op b`（op 为 `+`、`-`、`*`、`/`）改写为
+//! `_sysy_check_overflow(运算编号, a, b, 行号)`，运行时库在有符号整数溢出时输出行号、运算与操作数，
+//! 然后以非零退出码结束程序。
+//!
+//! 复合赋值 `x op= e` 改写为 `x = _sysy_check_overflow(op, x, e, 行号)`，`++x` 与 `x++` 分别改写为
+//! `x = x + 1` 与 `(x = x + 1) - 1`，左值因此求值两次，下标有副作用时不改写。取负不检查。
+//!
+//! 检查之前用 [`guard`] 改写，检查之后由 [`resolve`] 按常量求值的规则折叠两个操作数都是常量的运算，
+//! 与不检查时一样，常量运算溢出是编译错误。插桩只发生在生成 IR 之前，改写出的调用不会被删除。
+
+use super::ast::{ArithmeticOp::*, AssignOp::*, OtherUnaryOp::*, *};
+use super::bounds::CHECK_BOUND;
+use super::checker::ChineseFormatter;
+use super::expr::const_eval::fold_arith;
+use super::walk;
+use std::mem::take;
+
+pub const CHECK_OVERFLOW: &str = "_sysy_check_overflow";
+/// 运行时库输出的运算符，下标即运算编号。
+pub const OPERATORS: [&str; 4] = ["+", "-", "*", "/"];
+const OPERATIONS: [ArithmeticOp; 4] = [Add, Subtract, Multiply, Divide];
+
+fn code(op: &ArithmeticOp) -> Option<i64> {
+    match op {
+        Add => Some(0),
+        Subtract => Some(1),
+        Multiply => Some(2),
+        Divide => Some(3),
+        _ => None,
+    }
+}
+
+fn check(code: i64, lhs: Expr, rhs: Expr, line: usize) -> ExprInner {
+    let arguments = vec![ExprInner::Num(code).into(), lhs, rhs, ExprInner::Num(line as i64).into()];
+    ExprInner::FunctionCall(CHECK_OVERFLOW.to_string(), arguments, line)
+}
+
+/// 重复求值不改变结果的左值：下标中没有赋值、自增自减与函数调用（插桩的检查除外）。
+fn repeatable(expr: &Expr) -> bool {
+    match &expr.inner {
+        ExprInner::Num(_) | ExprInner::Identifier(_) => true,
+        ExprInner::InfixExpr(_, InfixOp::Assign(_), _) | ExprInner::UnaryExpr(UnaryOp::Others(_), _) => false,
+        ExprInner::InfixExpr(lhs, _, rhs) | ExprInner::Comma(lhs, rhs) => repeatable(lhs) && repeatable(rhs),
+        ExprInner::UnaryExpr(_, operand) => repeatable(operand),
+        ExprInner::ArrayElement(_, exprs, _) => exprs.iter().all(repeatable),
+        ExprInner::FunctionCall(id, exprs, _) => (id == CHECK_OVERFLOW || id == CHECK_BOUND) && exprs.iter().all(repeatable),
+        ExprInner::ArrayInit(_) => false,
+    }
+}
+
+fn rewrite(expr: &mut Expr, line: usize) {
+    let checked = match &mut expr.inner {
+        ExprInner::InfixExpr(lhs, op, rhs) => {
+            rewrite(lhs, line);
+            rewrite(rhs, line);
+            match op {
+                InfixOp::Arith(op) => code(op).map(|code| check(code, take(lhs), take(rhs), line)),
+                InfixOp::Assign(assign) if repeatable(lhs) => {
+                    let op = match assign {
+                        AddAssign => Add,
+                        SubtractAssign => Subtract,
+                        MultiplyAssign => Multiply,
+                        DivideAssign => Divide,
+                        _ => return,
+                    };
+                    **rhs = check(code(&op).unwrap(), (**lhs).clone(), take(rhs), line).into();
+                    *assign = Assignment;
+                    None
+                }
+                _ => None,
+            }
+        }
+        ExprInner::UnaryExpr(UnaryOp::Others(op), operand) => {
+            rewrite(operand, line);
+            if !repeatable(operand) {
+                return;
+            }
+            let (code, undo) = match op {
+                PrefixSelfIncrease => (0, None),
+                PrefixSelfDecrease => (1, None),
+                PostfixSelfIncrease => (0, Some(Subtract)),
+                PostfixSelfDecrease => (1, Some(Add)),
+            };
+            let increased = check(code, (**operand).clone(), ExprInner::Num(1).into(), line);
+            let assign = ExprInner::InfixExpr(take(operand), InfixOp::Assign(Assignment), Box::new(increased.into()));
+            // 后缀形式的值是原来的值，加一没有溢出时减一也不会溢出
+            Some(match undo {
+                Some(undo) => ExprInner::InfixExpr(
+                    Box::new(assign.into()),
+                    InfixOp::Arith(undo),
+                    Box::new(ExprInner::Num(1).into()),
+                ),
+                None => assign,
+            })
+        }
+        ExprInner::UnaryExpr(_, operand) => {
+            rewrite(operand, line);
+            None
+        }
+        ExprInner::Comma(lhs, rhs) => {
+            rewrite(lhs, line);
+            rewrite(rhs, line);
+            None
+        }
+        ExprInner::FunctionCall(_, exprs, _) | ExprInner::ArrayElement(_, exprs, _) | ExprInner::ArrayInit(exprs) => {
+            exprs.iter_mut().for_each(|expr| rewrite(expr, line));
+            None
+        }
+        ExprInner::Num(_) | ExprInner::Identifier(_) => None,
+    };
+    if let Some(checked) = checked {
+        expr.inner = checked;
+    }
+}
+
+/// 改写检查之前的语法树中函数体里的算术运算。`lines[i]` 是第 i 个文件中各语句与定义的行号，见 `parser::statement_lines`。
+pub fn guard(units: &mut [TranslationUnit], lines: &[Vec<usize>]) {
+    walk::function_exprs(units, lines, rewrite);
+}
+
+fn fold(expr: &mut Expr) -> Result<(), String> {
+    let value = match &mut expr.inner {
+        ExprInner::InfixExpr(lhs, _, rhs) | ExprInner::Comma(lhs, rhs) => {
+            fold(lhs)?;
+            fold(rhs)?;
+            None
+        }
+        ExprInner::UnaryExpr(_, operand) => {
+            fold(operand)?;
+            None
+        }
+        ExprInner::ArrayElement(_, exprs, _) | ExprInner::ArrayInit(exprs) => {
+            exprs.iter_mut().try_for_each(fold)?;
+            None
+        }
+        ExprInner::FunctionCall(id, exprs, _) => {
+            exprs.iter_mut().try_for_each(fold)?;
+            match (id == CHECK_OVERFLOW, exprs.as_slice()) {
+                (true, [op, lhs, rhs, _]) => match (&op.inner, &lhs.inner, &rhs.inner) {
+                    (ExprInner::Num(op), ExprInner::Num(lhs), ExprInner::Num(rhs)) => {
+                        Some(fold_arith(&OPERATIONS[*op as usize], *lhs, *rhs, &ChineseFormatter)?)
+                    }
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+        ExprInner::Num(_) | ExprInner::Identifier(_) => None,
+    };
+    if let Some(value) = value {
+        expr.inner = ExprInner::Num(value.into());
+    }
+    Ok(())
+}
+
+fn fold_init_list(init_list: &mut InitList) -> Result<(), String> {
+    init_list.iter_mut().try_for_each(|item| match item {
+        InitListItem::InitList(init_list) => fold_init_list(init_list),
+        InitListItem::Expr(expr) => fold(expr),
+    })
+}
+
+fn fold_block(block: &mut Block) -> Result<(), String> {
+    block.iter_mut().try_for_each(|item| match item {
+        BlockItem::Def(definition) => match definition.as_mut() {
+            Definition::VariableDef(_, Some(init)) => fold(init),
+            Definition::ArrayDef {
+                init_list: Some(init_list),
+                ..
+            } => fold_init_list(init_list),
+            _ => Ok(()),
+        },
+        BlockItem::Block(block) => fold_block(block),
+        BlockItem::Statement(statement) => match statement.as_mut() {
+            Statement::Expr(expr) | Statement::Return(Some(expr)) => fold(expr),
+            Statement::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                fold(condition)?;
+                fold_block(then_block)?;
+                fold_block(else_block)
+            }
+            Statement::While { condition, block } => {
+                fold(condition)?;
+                fold_block(block)
+            }
+            _ => Ok(()),
+        },
+    })
+}
+
+/// 折叠检查过的语法树中两个操作数都是常量的运算。出错时返回所在的函数与错误信息。
+pub fn resolve(ast: &mut TranslationUnit) -> Result<(), (String, String)> {
+    for item in ast.iter_mut() {
+        if let GlobalItem::FuncDef { id, block, .. } = item.as_mut() {
+            fold_block(block).map_err(|e| (id.clone(), e))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{bounds, checker::check_instrumented, dump::dump_ir, interpreter, parser};
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    fn guarded(code: &str) -> Result<TranslationUnit, String> {
+        let mut units = vec![parser::build_ast(code).unwrap()];
+        let lines = [parser::statement_lines(code).unwrap()];
+        bounds::guard(&mut units, &lines);
+        guard(&mut units, &lines);
+        let (mut ast, _) = check_instrumented(units, &["test.sy"], &[CHECK_BOUND, CHECK_OVERFLOW]).map_err(|(_, e)| e)?;
+        resolve(&mut ast).map_err(|(_, e)| e)?;
+        bounds::resolve(&mut ast).map_err(|(_, e)| e)?;
+        Ok(ast)
+    }
+
+    fn run(code: &str) -> (String, Result<i32, String>) {
+        let ast = guarded(code).unwrap();
+        let mut output = Vec::new();
+        let result = interpreter::run(&ast, &mut "".as_bytes(), &mut output, &mut std::io::sink(), &AtomicBool::new(false)).map_err(|(_, _, e)| e);
+        (String::from_utf8(output).unwrap(), result)
+    }
+
+    #[test]
+    fn overflow_reports_line() {
+        let code = "int main() {
+  int x = 1, i = 0;
+  while (i < 40) {
+    putint(i);
+    putch(32);
+    x = x * 2;
+    i = i + 1;
+  }
+  return x;
+}
+";
+        let (output, result) = run(code);
+        assert!(output.ends_with("29 30 "), "{}", output);
+        assert_eq!(result, Err("第 6 行: 1073741824 * 2 溢出".to_string()));
+    }
+
+    #[test]
+    fn definitions_report_their_own_line() {
+        let code = "int main() {
+  int x = 2147483647;
+  if (x > 0)
+    x = x - 1;
+  int y = 0,
+    z = x * 2;
+  return y + z;
+}
+";
+        assert_eq!(run(code).1, Err("第 6 行: 2147483646 * 2 溢出".to_string()));
+        let code = "int main() {\n  int x = getint();\n  int y = x + 2147483647;\n  return y;\n}\n";
+        let ast = guarded(code).unwrap();
+        let result = interpreter::run(&ast, &mut "1".as_bytes(), &mut Vec::new(), &mut std::io::sink(), &AtomicBool::new(false)).map_err(|(_, _, e)| e);
+        assert_eq!(result, Err("第 3 行: 1 + 2147483647 溢出".to_string()));
+    }
+
+    #[test]
+    fn compound_assignment_and_increment() {
+        let trap = |statement: &str| {
+            let code = format!(
+                "int main() {{\n  int a[2] = {{}}, i = 0, m = -2147483647;\n  a[1] = 2147483647;\n  {};\n  return 0;\n}}\n",
+                statement
+            );
+            run(&code).1.unwrap_err()
+        };
+        assert_eq!(trap("a[i + 1] += 1"), "第 4 行: 2147483647 + 1 溢出");
+        assert_eq!(trap("a[1]++"), "第 4 行: 2147483647 + 1 溢出");
+        assert_eq!(trap("--m; m--"), "第 4 行: -2147483648 - 1 溢出");
+        assert_eq!(trap("m = (m - 1) / -1"), "第 4 行: -2147483648 / -1 溢出");
+        let code = "int main() { int i = 0, j = 5; j -= i++; j *= ++i; return j * 10 + i; }";
+        assert_eq!(run(code), (String::new(), Ok(102)));
+    }
+
+    #[test]
+    fn constants_are_folded() {
+        let ast = guarded("const int N = 3; int main() { int x = getint(); return N * 2 + x + (1 - 1); }").unwrap();
+        assert_eq!(dump_ir(&ast).matches("call @_sysy_check_overflow").count(), 2);
+        assert_eq!(
+            guarded("int main() { int x = 2147483647 + 1; return x; }").unwrap_err(),
+            "[E007] 2147483648 超出了 int 的表示范围"
+        );
+        assert!(guarded("int main() { int x; x = 2147483647 + 1; return x; }").is_err());
+    }
+
+    #[test]
+    fn side_effects_in_subscripts() {
+        // 下标有副作用时不改写，下标只求值一次
+        let code = "int a[3]; int main() { int i = 0; a[i++] += 5; a[i] += i; return a[0] * 10 + a[1] + i * 100; }";
+        assert_eq!(run(code), (String::new(), Ok(151)));
+    }
+}
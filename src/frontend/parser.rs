@@ -41,7 +41,7 @@ fn new_expr_parser() -> PrattParser<Rule> {
             | Op::infix(Rule::bit_right_shift_assignment, Right))
         .op(Op::infix(Rule::logical_or, Left))
         .op(Op::infix(Rule::logical_and, Left))
-        .op(Op::infix(Rule::bit_xor, Left))
+        .op(Op::infix(Rule::bit_or, Left))
         .op(Op::infix(Rule::bit_xor, Left))
         .op(Op::infix(Rule::bit_and, Left))
         .op(Op::infix(Rule::equal, Left) | Op::infix(Rule::not_equal, Left))
@@ -62,20 +62,37 @@ fn new_expr_parser() -> PrattParser<Rule> {
         .op(Op::postfix(Rule::postfix_self_increase) | Op::postfix(Rule::postfix_self_decrease))
 }
 
+fn integer_value(pair: &Pair<Rule>) -> Option<i64> {
+    match pair.as_rule() {
+        Rule::integer_bin => i64::from_str_radix(&pair.as_str()[2..], 2).ok(),
+        Rule::integer_oct => i64::from_str_radix(pair.as_str(), 8).ok(),
+        Rule::integer_dec => pair.as_str().parse().ok(),
+        Rule::integer_hex => i64::from_str_radix(&pair.as_str()[2..], 16).ok(),
+        _ => unreachable!(),
+    }
+}
+
 fn parse_expr(expr_parser: &PrattParser<Rule>, pair: Pair<Rule>) -> Expr {
     expr_parser
         .map_primary(|pair| match pair.as_rule() {
             Rule::expression => parse_expr(expr_parser, pair),
-            Rule::integer_bin => Num(i32::from_str_radix(&pair.as_str()[2..], 2).unwrap()).into(),
-            Rule::integer_oct => Num(i32::from_str_radix(pair.as_str(), 8).unwrap()).into(),
-            Rule::integer_dec => Num(i32::from_str_radix(pair.as_str(), 10).unwrap()).into(),
-            Rule::integer_hex => Num(i32::from_str_radix(&pair.as_str()[2..], 16).unwrap()).into(),
+            // 括号内只有一个表达式时就是该表达式本身，两个及以上才构成逗号表达式
+            Rule::comma_expression => pair
+                .into_inner()
+                .map(|p| parse_expr(expr_parser, p))
+                .reduce(|lhs, rhs| Comma(Box::new(lhs), Box::new(rhs)).into())
+                .unwrap(),
+            // 字面量的范围已在 build_ast 中检查过
+            Rule::integer_bin | Rule::integer_oct | Rule::integer_dec | Rule::integer_hex => {
+                Num(integer_value(&pair).unwrap()).into()
+            }
             Rule::identifier => Identifier(pair.as_str().to_string()).into(),
             Rule::function_call => {
+                let line = pair.line_col().0;
                 let mut iter = pair.into_inner();
                 let id = iter.next().unwrap().as_str().to_string();
                 let arg_list = iter.map(|p| parse_expr(expr_parser, p)).collect();
-                FunctionCall(id, arg_list).into()
+                FunctionCall(id, arg_list, line).into()
             }
             Rule::array_element => {
                 let mut iter = pair.into_inner();
@@ -88,10 +105,14 @@ fn parse_expr(expr_parser: &PrattParser<Rule>, pair: Pair<Rule>) -> Expr {
                     .collect();
                 ArrayElement(id, subscripts, false).into()
             }
+            Rule::array_literal => ArrayInit(pair.into_inner().map(|p| parse_expr(expr_parser, p)).collect()).into(),
             _ => unreachable!(),
         })
         .map_infix(|lhs, op, rhs| match op.as_rule() {
-            Rule::custom_operator => FunctionCall(op.into_inner().as_str().to_string(), vec![lhs, rhs]).into(),
+            Rule::custom_operator => {
+                let line = op.line_col().0;
+                FunctionCall(op.into_inner().as_str().to_string(), vec![lhs, rhs], line).into()
+            }
             Rule::multiply => InfixExpr(Box::new(lhs), Arith(Multiply), Box::new(rhs)).into(),
             Rule::divide => InfixExpr(Box::new(lhs), Arith(Divide), Box::new(rhs)).into(),
             Rule::modulus => InfixExpr(Box::new(lhs), Arith(Modulus), Box::new(rhs)).into(),
@@ -118,6 +139,8 @@ fn parse_expr(expr_parser: &PrattParser<Rule>, pair: Pair<Rule>) -> Expr {
             Rule::add_assignment => InfixExpr(Box::new(lhs), Assign(AddAssign), Box::new(rhs)).into(),
             Rule::subtract_assignment => InfixExpr(Box::new(lhs), Assign(SubtractAssign), Box::new(rhs)).into(),
             Rule::multiply_assignment => InfixExpr(Box::new(lhs), Assign(MultiplyAssign), Box::new(rhs)).into(),
+            Rule::divide_assignment => InfixExpr(Box::new(lhs), Assign(DivideAssign), Box::new(rhs)).into(),
+            Rule::modulus_assignment => InfixExpr(Box::new(lhs), Assign(ModulusAssign), Box::new(rhs)).into(),
             Rule::bit_and_assignment => InfixExpr(Box::new(lhs), Assign(BitAndAssign), Box::new(rhs)).into(),
             Rule::bit_or_assignment => InfixExpr(Box::new(lhs), Assign(BitOrAssign), Box::new(rhs)).into(),
             Rule::bit_xor_assignment => InfixExpr(Box::new(lhs), Assign(BitXorAssign), Box::new(rhs)).into(),
@@ -160,11 +183,17 @@ fn parse_definition(expr_parser: &PrattParser<Rule>, pair: Pair<Rule>) -> Defini
     match pair.as_rule() {
         Rule::const_variable_definition => {
             let mut iter = pair.into_inner();
-            Definition::ConstVariableDefTmp(iter.next().unwrap().as_str().to_string(), parse_expr(expr_parser, iter.next().unwrap()))
+            Definition::ConstVariableDefTmp(
+                iter.next().unwrap().as_str().to_string(),
+                parse_expr(expr_parser, iter.next().unwrap()),
+            )
         }
         Rule::variable_definition => {
             let mut iter = pair.into_inner();
-            Definition::VariableDef(iter.next().unwrap().as_str().to_string(), iter.next().map(|expr| parse_expr(expr_parser, expr)))
+            Definition::VariableDef(
+                iter.next().unwrap().as_str().to_string(),
+                iter.next().map(|expr| parse_expr(expr_parser, expr)),
+            )
         }
         Rule::const_array_definition => {
             let mut iter = pair.into_inner();
@@ -204,8 +233,10 @@ fn parse_if_while_helper(expr_parser: &PrattParser<Rule>, pair: Pair<Rule>) -> B
         | Rule::if_statement
         | Rule::while_statement
         | Rule::break_keyword
-        | Rule::continue_keyword => vec![BlockItem::Statement(Box::new(parse_statement(expr_parser, pair)))],
-        Rule::empty_statement => Vec::new(),
+        | Rule::continue_keyword
+        | Rule::goto_statement
+        | Rule::label_statement => Block::from_iter([BlockItem::Statement(Box::new(parse_statement(expr_parser, pair)))]),
+        Rule::empty_statement => Block::default(),
         Rule::definitions_in_if_or_while_non_block => pair
             .into_inner()
             .skip(1)
@@ -247,6 +278,8 @@ fn parse_statement(expr_parser: &PrattParser<Rule>, iter: Pair<Rule>) -> Stateme
         Rule::while_statement => parse_while(expr_parser, iter),
         Rule::break_keyword => Statement::Break,
         Rule::continue_keyword => Statement::Continue,
+        Rule::goto_statement => Statement::Goto(iter.into_inner().nth(1).unwrap().as_str().to_string()),
+        Rule::label_statement => Statement::Label(iter.into_inner().next().unwrap().as_str().to_string()),
         _ => unreachable!(),
     }
 }
@@ -261,10 +294,13 @@ fn parse_block(expr_parser: &PrattParser<Rule>, pair: Pair<Rule>) -> Block {
             | Rule::if_statement
             | Rule::while_statement
             | Rule::break_keyword
-            | Rule::continue_keyword => BlockItem::Statement(Box::new(parse_statement(expr_parser, pair))),
-            Rule::variable_definition | Rule::array_definition | Rule::const_variable_definition | Rule::const_array_definition => {
-                BlockItem::Def(Box::new(parse_definition(expr_parser, pair)))
-            }
+            | Rule::continue_keyword
+            | Rule::goto_statement
+            | Rule::label_statement => BlockItem::Statement(Box::new(parse_statement(expr_parser, pair))),
+            Rule::variable_definition
+            | Rule::array_definition
+            | Rule::const_variable_definition
+            | Rule::const_array_definition => BlockItem::Def(Box::new(parse_definition(expr_parser, pair))),
             _ => unreachable!(),
         })
         .collect()
@@ -316,11 +352,190 @@ fn parse_global_item(expr_parser: &PrattParser<Rule>, pair: Pair<Rule>) -> Globa
     }
 }
 
-pub fn build_ast(code: &str) -> TranslationUnit {
+pub fn build_ast(code: &str) -> Result<TranslationUnit, String> {
     let expr_parser = new_expr_parser();
-    let translation_unit = SysYParser::parse(Rule::translation_unit, code).unwrap();
-    translation_unit
+    let translation_unit = SysYParser::parse(Rule::translation_unit, code).map_err(|e| format!("语法错误:\n{}", e))?;
+    for pair in translation_unit.clone().flatten() {
+        if matches!(
+            pair.as_rule(),
+            Rule::integer_bin | Rule::integer_oct | Rule::integer_dec | Rule::integer_hex
+        ) && integer_value(&pair).is_none()
+        {
+            return Err(format!("第 {} 行的整数字面量 {} 过大", pair.line_col().0, pair.as_str()));
+        }
+    }
+    Ok(translation_unit
         .filter(|pair| !matches!(pair.as_rule(), Rule::EOI | Rule::int_keyword | Rule::const_keyword))
         .map(|p| Box::new(parse_global_item(&expr_parser, p)))
-        .collect()
+        .collect())
+}
+
+fn collect_statement_lines(pair: Pair<Rule>, lines: &mut Vec<usize>) {
+    match pair.as_rule() {
+        Rule::block | Rule::function_definition | Rule::definitions_in_if_or_while_non_block => {
+            pair.into_inner().for_each(|pair| collect_statement_lines(pair, lines))
+        }
+        Rule::expression
+        | Rule::return_statement
+        | Rule::break_keyword
+        | Rule::continue_keyword
+        | Rule::goto_statement
+        | Rule::label_statement
+        | Rule::variable_definition
+        | Rule::array_definition
+        | Rule::const_variable_definition
+        | Rule::const_array_definition => lines.push(pair.line_col().0),
+        // 跳过条件，只遍历分支与循环体
+        Rule::if_statement | Rule::while_statement => {
+            lines.push(pair.line_col().0);
+            pair.into_inner()
+                .skip(1)
+                .for_each(|pair| collect_statement_lines(pair, lines));
+        }
+        _ => (),
+    }
+}
+
+/// 函数体中每条语句与每个定义起始处的行号，顺序与 build_ast 生成的语法树中语句与定义（`BlockItem::Statement`
+/// 与 `BlockItem::Def`）的先序遍历相同。
+pub fn statement_lines(code: &str) -> Result<Vec<usize>, String> {
+    let translation_unit = SysYParser::parse(Rule::translation_unit, code).map_err(|e| format!("语法错误:\n{}", e))?;
+    let mut lines = Vec::new();
+    translation_unit
+        .filter(|pair| pair.as_rule() == Rule::function_definition)
+        .for_each(|pair| collect_statement_lines(pair, &mut lines));
+    Ok(lines)
+}
+
+/// 格式化时放回注释所用的位置，都是字节偏移，按 build_ast 生成的语法树的先序排列。`items` 是每个全局定义、
+/// 函数、定义、语句与嵌套的语句块的起始处；`bodies` 是函数体、循环体、嵌套的语句块与 if 的两个分支的结束处，
+/// 没有 else 分支时 else 分支的结束处与 then 分支相同。
+#[derive(Default)]
+pub struct Layout {
+    pub items: Vec<usize>,
+    pub bodies: Vec<usize>,
+}
+
+fn collect_body(pair: Pair<Rule>, layout: &mut Layout) {
+    match pair.as_rule() {
+        Rule::block => pair.into_inner().for_each(|pair| collect_layout(pair, layout)),
+        _ => collect_layout(pair, layout),
+    }
+}
+
+fn collect_layout(pair: Pair<Rule>, layout: &mut Layout) {
+    let span = pair.as_span();
+    match pair.as_rule() {
+        Rule::definitions_in_if_or_while_non_block => pair.into_inner().for_each(|pair| collect_layout(pair, layout)),
+        Rule::block => {
+            layout.items.push(span.start());
+            layout.bodies.push(span.end());
+            collect_body(pair, layout);
+        }
+        // 函数的签名与循环的条件之后是函数体与循环体
+        Rule::function_definition | Rule::while_statement => {
+            layout.items.push(span.start());
+            let body = pair.into_inner().nth(1).unwrap();
+            layout.bodies.push(body.as_span().end());
+            collect_body(body, layout);
+        }
+        Rule::if_statement => {
+            layout.items.push(span.start());
+            let mut iter = pair.into_inner().skip(1);
+            let then_body = iter.next().unwrap();
+            let else_body = iter.next();
+            layout.bodies.push(then_body.as_span().end());
+            layout.bodies.push(else_body.as_ref().unwrap_or(&then_body).as_span().end());
+            collect_body(then_body, layout);
+            if let Some(else_body) = else_body {
+                collect_body(else_body, layout);
+            }
+        }
+        Rule::expression
+        | Rule::return_statement
+        | Rule::break_keyword
+        | Rule::continue_keyword
+        | Rule::goto_statement
+        | Rule::label_statement
+        | Rule::variable_definition
+        | Rule::array_definition
+        | Rule::const_variable_definition
+        | Rule::const_array_definition => layout.items.push(span.start()),
+        _ => (),
+    }
+}
+
+/// 见 [`Layout`]。
+pub fn layout(code: &str) -> Result<Layout, String> {
+    let translation_unit = SysYParser::parse(Rule::translation_unit, code).map_err(|e| format!("语法错误:\n{}", e))?;
+    let mut layout = Layout::default();
+    translation_unit.for_each(|pair| collect_layout(pair, &mut layout));
+    Ok(layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn global_init(code: &str) -> Option<i64> {
+        match build_ast(code).ok()?[0].as_ref() {
+            GlobalItem::Def(Definition::VariableDef(_, Some(Expr { inner: Num(i), .. }))) => Some(*i),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn zero_and_octal_literals() {
+        assert_eq!(global_init("int x = 0;"), Some(0));
+        assert_eq!(global_init("int y = 00;"), Some(0));
+        assert_eq!(global_init("int z = 017;"), Some(15));
+        assert_eq!(global_init("int w = 0x1F;"), Some(31));
+        assert!(build_ast("int z = 08;").is_err());
+        assert_eq!(
+            build_ast("int a;\nint b = 0x8000000000000000;").unwrap_err(),
+            "第 2 行的整数字面量 0x8000000000000000 过大"
+        );
+    }
+
+    #[test]
+    fn function_call_line() {
+        let code = crate::preprocessor::preprocess("/* a\n b */\nint main() {\n    return f(1);\n}\n");
+        let ast = build_ast(&code).unwrap();
+        let block = match ast[0].as_ref() {
+            GlobalItem::FuncDef { block, .. } => block,
+            _ => unreachable!(),
+        };
+        assert!(matches!(
+            &block.as_slice()[0],
+            BlockItem::Statement(statement)
+                if matches!(statement.as_ref(), Statement::Return(Some(Expr { inner: FunctionCall(_, _, 4), .. })))
+        ));
+    }
+
+    #[test]
+    fn operators() {
+        let ast = build_ast("int x = 1 | 2 ^ 3 & 4; int y = (x /= 2, x %= 3);").unwrap();
+        let (x, y) = match (ast[0].as_ref(), ast[1].as_ref()) {
+            (GlobalItem::Def(Definition::VariableDef(_, Some(x))), GlobalItem::Def(Definition::VariableDef(_, Some(y)))) => {
+                (x, y)
+            }
+            _ => unreachable!(),
+        };
+        assert!(matches!(&x.inner, InfixExpr(_, Arith(BitOr), rhs) if matches!(&rhs.inner, InfixExpr(_, Arith(BirXor), _))));
+        assert!(matches!(
+            &y.inner,
+            Comma(lhs, rhs) if matches!(lhs.inner, InfixExpr(_, Assign(DivideAssign), _))
+                && matches!(rhs.inner, InfixExpr(_, Assign(ModulusAssign), _))
+        ));
+    }
+
+    #[test]
+    fn nested_parentheses() {
+        // 每层括号只尝试一次，解析时间随嵌套深度线性增长
+        let depth = 200;
+        let code = format!("int x = {}1{};", "(".repeat(depth), ")".repeat(depth));
+        let start = std::time::Instant::now();
+        assert_eq!(global_init(&code), Some(1));
+        assert!(start.elapsed() < std::time::Duration::from_secs(2), "{:?}", start.elapsed());
+    }
 }
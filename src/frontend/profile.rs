@@ -0,0 +1,220 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! 性能剖析: --profile-generate 的插桩与映射文件。
+//!
+//! 每个函数的入口与每个 while 循环体的开头各有一个计数器，循环体的计数即循环的迭代次数。
+//! `blocks` 时另外在 if 的两个分支、if 与 while 之后以及每个标签之后各加一个，覆盖所有基本块的开头。
+//! 计数器按函数和语句的先序编号，名字形如 `main`、`main:while@9`、`f:then@3`、`f:label L@12`，数字为行号。
+//! main 返回之前调用运行时库的 `_sysy_write_profile`，向标准错误每个计数器输出一行
+//! `xenon-profile: <编号> <计数>`，不影响程序的标准输出。
+
+use super::ast::*;
+use super::coverage::{finish, increase};
+use std::iter::once;
+use std::mem::take;
+
+const COUNTERS: &str = "__xenon_profile";
+pub const WRITE_PROFILE: &str = "_sysy_write_profile";
+/// 运行时输出的计数行的开头，其后是计数器编号与计数。
+pub const PROFILE_PREFIX: &str = "xenon-profile:";
+
+struct Instrumenter<'l> {
+    function: String,
+    lines: std::slice::Iter<'l, usize>,
+    blocks: bool,
+    names: Vec<String>,
+}
+
+impl Instrumenter<'_> {
+    fn counter(&mut self, location: String) -> BlockItem {
+        self.names.push(location);
+        increase(COUNTERS, self.names.len() - 1)
+    }
+
+    /// 在语句块的开头加一个计数器，再插桩语句块中的语句。
+    fn counted_block(&mut self, block: &mut Block, location: String) {
+        let counter = self.counter(location);
+        self.block(block);
+        *block = once(counter).chain(take(block)).collect();
+    }
+
+    fn block(&mut self, block: &mut Block) {
+        *block = take(block).into_iter().flat_map(|item| self.item(item)).collect();
+    }
+
+    fn item(&mut self, item: BlockItem) -> Vec<BlockItem> {
+        let mut statement = match item {
+            BlockItem::Def(_) => {
+                // 定义也占一个行号，不计数
+                self.lines.next();
+                return vec![item];
+            }
+            BlockItem::Block(mut block) => {
+                self.block(&mut block);
+                return vec![BlockItem::Block(block)];
+            }
+            BlockItem::Statement(statement) => statement,
+        };
+        // 与 coverage 相同，每条语句（包括标签）按先序对应一个行号
+        let line = self.lines.next().copied().unwrap_or(0);
+        let after = match statement.as_mut() {
+            Statement::While { block, .. } => {
+                self.counted_block(block, format!("{}:while@{}", self.function, line));
+                self.blocks.then(|| format!("{}:after@{}", self.function, line))
+            }
+            Statement::If {
+                then_block, else_block, ..
+            } if self.blocks => {
+                self.counted_block(then_block, format!("{}:then@{}", self.function, line));
+                self.counted_block(else_block, format!("{}:else@{}", self.function, line));
+                Some(format!("{}:after@{}", self.function, line))
+            }
+            Statement::If {
+                then_block, else_block, ..
+            } => {
+                self.block(then_block);
+                self.block(else_block);
+                None
+            }
+            // goto 跳转到标签时也会计数
+            Statement::Label(label) if self.blocks => Some(format!("{}:label {}@{}", self.function, label, line)),
+            _ => None,
+        };
+        let after = after.map(|location| self.counter(location));
+        once(BlockItem::Statement(statement)).chain(after).collect()
+    }
+}
+
+/// 插桩检查之前的语法树。`lines[i]` 是第 i 个文件中各语句与定义的行号，见 `parser::statement_lines`。
+/// 返回每个计数器的名字。
+pub fn instrument(units: &mut [TranslationUnit], lines: &[Vec<usize>], blocks: bool) -> Vec<String> {
+    let mut names = Vec::new();
+    for (file, unit) in units.iter_mut().enumerate() {
+        let mut instrumenter = Instrumenter {
+            function: String::new(),
+            lines: lines[file].iter(),
+            blocks,
+            names: take(&mut names),
+        };
+        for item in unit.iter_mut() {
+            if let GlobalItem::FuncDef { id, block, .. } = item.as_mut() {
+                instrumenter.function = id.clone();
+                instrumenter.counted_block(block, id.clone());
+            }
+        }
+        names = instrumenter.names;
+    }
+    finish(units, COUNTERS, WRITE_PROFILE, names.len());
+    names
+}
+
+/// 映射文件: 注释行之后每个计数器一行 `<编号> <名字>`。
+pub fn map_text(names: &[String]) -> String {
+    let mut text = String::from("# xenon --profile-generate 映射文件: 计数器编号 名字\n");
+    for (id, name) in names.iter().enumerate() {
+        text += &format!("{} {}\n", id, name);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{checker::check_instrumented, interpreter, parser};
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    /// 运行插桩后的程序，返回标准输出与按编号排列的计数器名字和计数。
+    fn profile(code: &str, blocks: bool) -> (String, Vec<(String, i32)>) {
+        let mut units = vec![parser::build_ast(code).unwrap()];
+        let names = instrument(&mut units, &[parser::statement_lines(code).unwrap()], blocks);
+        let (ast, _) = check_instrumented(units, &["test.sy"], &[WRITE_PROFILE]).unwrap();
+        let (mut output, mut errors) = (Vec::new(), Vec::new());
+        interpreter::run(&ast, &mut "".as_bytes(), &mut output, &mut errors, &AtomicBool::new(false)).unwrap();
+        let counts = String::from_utf8(errors)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let mut fields = line.strip_prefix(PROFILE_PREFIX).unwrap().split_whitespace();
+                let id: usize = fields.next().unwrap().parse().unwrap();
+                (names[id].clone(), fields.next().unwrap().parse().unwrap())
+            })
+            .collect();
+        (String::from_utf8(output).unwrap(), counts)
+    }
+
+    #[test]
+    fn loop_header_counts_trips() {
+        let code = "int square(int x) { return x * x; }
+int main() {
+  int i = 0, s = 0;
+  while (i < 7) {
+    i = i + 1;
+    if (i == 3) continue;
+    s = s + square(i);
+  }
+  putint(s);
+  return s;
+}
+";
+        let (output, counts) = profile(code, false);
+        assert_eq!(output, "131");
+        assert_eq!(
+            counts,
+            [
+                ("square".to_string(), 6),
+                ("main".to_string(), 1),
+                ("main:while@4".to_string(), 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn basic_blocks() {
+        let code = "int main() {
+  int i = 0;
+  while (i < 3) {
+    if (i == 1) {
+      i = 5;
+      goto done;
+    } else i = i + 1;
+  }
+done:
+  return i;
+}
+";
+        let (_, counts) = profile(code, true);
+        let counts: Vec<_> = counts.iter().map(|(name, count)| format!("{} {}", name, count)).collect();
+        // 跳出循环的 goto 绕过了循环之后的计数器
+        assert_eq!(
+            counts,
+            [
+                "main 1",
+                "main:while@3 2",
+                "main:then@4 1",
+                "main:else@4 1",
+                "main:after@4 1",
+                "main:after@3 0",
+                "main:label done@9 1"
+            ]
+        );
+        assert_eq!(
+            map_text(&["main".to_string()]),
+            "# xenon --profile-generate 映射文件: 计数器编号 名字\n0 main\n"
+        );
+    }
+}
@@ -0,0 +1,371 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! 检查后的代码度量，供 --stats 与 --stats-json 输出。
+
+use super::ast::*;
+use super::json::{json_text, Json};
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FunctionStats {
+    pub name: String,
+    pub parameters: usize,
+    /// 定义、语句、语句块与表达式结点的总数
+    pub nodes: usize,
+    pub statements: usize,
+    /// 语句块的最大嵌套层数，函数体为第 1 层，if 与 while 的分支各算一层
+    pub max_depth: usize,
+    /// 1 + if 语句数 + while 语句数
+    pub complexity: usize,
+    pub calls: usize,
+    /// 局部数组定义占用的字节数，不含数组字面量产生的临时数组
+    pub array_bytes: usize,
+    /// 生成的 Koopa IR 中的指令数，不含基本块标号
+    pub ir_instructions: usize,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ProgramStats {
+    pub functions: Vec<FunctionStats>,
+    pub const_variables: usize,
+    pub variables: usize,
+    pub const_arrays: usize,
+    pub arrays: usize,
+}
+
+fn array_bytes(lengths: &[usize]) -> usize {
+    lengths.iter().product::<usize>() * 4
+}
+
+impl FunctionStats {
+    fn expr(&mut self, expr: &Expr) {
+        self.nodes += 1;
+        match &expr.inner {
+            ExprInner::InfixExpr(lhs, _, rhs) | ExprInner::Comma(lhs, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ExprInner::UnaryExpr(_, expr) => self.expr(expr),
+            ExprInner::Num(_) | ExprInner::Identifier(_) => (),
+            ExprInner::FunctionCall(_, exprs, _) => {
+                self.calls += 1;
+                exprs.iter().for_each(|expr| self.expr(expr));
+            }
+            ExprInner::ArrayElement(_, exprs, _) | ExprInner::ArrayInit(exprs) => exprs.iter().for_each(|expr| self.expr(expr)),
+        }
+    }
+
+    fn init_list(&mut self, init_list: &InitList) {
+        for item in init_list {
+            match item {
+                InitListItem::InitList(init_list) => self.init_list(init_list),
+                InitListItem::Expr(expr) => self.expr(expr),
+            }
+        }
+    }
+
+    fn definition(&mut self, definition: &Definition) {
+        self.nodes += 1;
+        match definition {
+            Definition::ConstArrayDef { lengths, .. } => self.array_bytes += array_bytes(lengths),
+            Definition::ArrayDef { lengths, init_list, .. } => {
+                self.array_bytes += array_bytes(lengths);
+                init_list.iter().for_each(|init_list| self.init_list(init_list));
+            }
+            Definition::VariableDef(_, init) => init.iter().for_each(|expr| self.expr(expr)),
+            _ => (),
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement, depth: usize) {
+        self.nodes += 1;
+        self.statements += 1;
+        match statement {
+            Statement::Expr(expr) | Statement::Return(Some(expr)) => self.expr(expr),
+            Statement::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                self.complexity += 1;
+                self.expr(condition);
+                self.block(then_block, depth + 1);
+                if !else_block.is_empty() {
+                    self.block(else_block, depth + 1);
+                }
+            }
+            Statement::While { condition, block } => {
+                self.complexity += 1;
+                self.expr(condition);
+                self.block(block, depth + 1);
+            }
+            _ => (),
+        }
+    }
+
+    fn block(&mut self, block: &Block, depth: usize) {
+        self.max_depth = self.max_depth.max(depth);
+        for item in block {
+            match item {
+                BlockItem::Def(definition) => self.definition(definition),
+                BlockItem::Block(block) => {
+                    self.nodes += 1;
+                    self.block(block, depth + 1);
+                }
+                BlockItem::Statement(statement) => self.statement(statement, depth),
+            }
+        }
+    }
+}
+
+/// 每个函数在 Koopa IR 中的指令数：函数体中除标号外的非空行。
+fn ir_instructions(ir: &str) -> Vec<(&str, usize)> {
+    let mut counts = Vec::new();
+    for line in ir.lines() {
+        if let Some(header) = line.strip_prefix("fun @") {
+            counts.push((&header[..header.find('(').unwrap()], 0));
+        } else if let Some((_, count)) = counts.last_mut() {
+            if !line.is_empty() && !line.ends_with(':') && line != "}" {
+                *count += 1;
+            }
+        }
+    }
+    counts
+}
+
+/// 按源代码中的顺序统计。`ir` 是同一语法树生成的 Koopa IR。
+pub fn collect(ast: &TranslationUnit, ir: &str) -> ProgramStats {
+    let mut stats = ProgramStats::default();
+    let ir_counts = ir_instructions(ir);
+    for item in ast {
+        match item.as_ref() {
+            GlobalItem::Def(Definition::ConstVariableDef(..)) => stats.const_variables += 1,
+            GlobalItem::Def(Definition::VariableDef(..)) => stats.variables += 1,
+            GlobalItem::Def(Definition::ConstArrayDef { .. }) => stats.const_arrays += 1,
+            GlobalItem::Def(Definition::ArrayDef { .. }) => stats.arrays += 1,
+            GlobalItem::Def(_) => unreachable!(),
+            GlobalItem::FuncDef {
+                id,
+                parameter_list,
+                block,
+                ..
+            } => {
+                let mut function = FunctionStats {
+                    name: id.clone(),
+                    parameters: parameter_list.len(),
+                    complexity: 1,
+                    ..Default::default()
+                };
+                function.block(block, 1);
+                function.ir_instructions = ir_counts.iter().find(|(name, _)| name == id).map_or(0, |&(_, count)| count);
+                stats.functions.push(function);
+            }
+        }
+    }
+    stats
+}
+
+const HEADERS: [&str; 9] = [
+    "函数",
+    "参数",
+    "结点",
+    "语句",
+    "嵌套",
+    "圈复杂度",
+    "调用",
+    "局部数组字节",
+    "IR 指令",
+];
+
+fn row(function: &FunctionStats) -> [String; 9] {
+    [
+        function.name.clone(),
+        function.parameters.to_string(),
+        function.nodes.to_string(),
+        function.statements.to_string(),
+        function.max_depth.to_string(),
+        function.complexity.to_string(),
+        function.calls.to_string(),
+        function.array_bytes.to_string(),
+        function.ir_instructions.to_string(),
+    ]
+}
+
+// 终端中汉字占两列
+fn display_width(text: &str) -> usize {
+    text.chars().map(|c| if c.is_ascii() { 1 } else { 2 }).sum()
+}
+
+/// 对齐的文本表格：每个函数一行，最后是合计与全局定义的数量。
+pub fn stats_table(stats: &ProgramStats) -> String {
+    let sum = |field: fn(&FunctionStats) -> usize| stats.functions.iter().map(field).sum::<usize>().to_string();
+    let total = [
+        "合计".to_string(),
+        sum(|f| f.parameters),
+        sum(|f| f.nodes),
+        sum(|f| f.statements),
+        stats.functions.iter().map(|f| f.max_depth).max().unwrap_or(0).to_string(),
+        sum(|f| f.complexity),
+        sum(|f| f.calls),
+        sum(|f| f.array_bytes),
+        sum(|f| f.ir_instructions),
+    ];
+    let mut rows = vec![HEADERS.map(String::from)];
+    rows.extend(stats.functions.iter().map(row));
+    rows.push(total);
+    let widths: Vec<usize> = (0..HEADERS.len())
+        .map(|i| rows.iter().map(|row| display_width(&row[i])).max().unwrap())
+        .collect();
+    let mut out = String::new();
+    for row in &rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, (cell, &width))| {
+                let pad = " ".repeat(width - display_width(cell));
+                // 函数名左对齐，数字右对齐
+                if i == 0 {
+                    format!("{}{}", cell, pad)
+                } else {
+                    format!("{}{}", pad, cell)
+                }
+            })
+            .collect();
+        out += cells.join("  ").trim_end();
+        out.push('\n');
+    }
+    out += &format!(
+        "全局定义: 常量 {}，变量 {}，常量数组 {}，数组 {}，函数 {}\n",
+        stats.const_variables,
+        stats.variables,
+        stats.const_arrays,
+        stats.arrays,
+        stats.functions.len()
+    );
+    out
+}
+
+pub fn stats_json(stats: &ProgramStats) -> String {
+    let number = |n: usize| Json::Number(n as i64);
+    let functions = stats
+        .functions
+        .iter()
+        .map(|f| {
+            Json::Object(vec![
+                ("name", Json::String(f.name.clone())),
+                ("parameters", number(f.parameters)),
+                ("nodes", number(f.nodes)),
+                ("statements", number(f.statements)),
+                ("max_depth", number(f.max_depth)),
+                ("complexity", number(f.complexity)),
+                ("calls", number(f.calls)),
+                ("array_bytes", number(f.array_bytes)),
+                ("ir_instructions", number(f.ir_instructions)),
+            ])
+        })
+        .collect();
+    json_text(&Json::Object(vec![
+        ("functions", Json::Array(functions)),
+        (
+            "globals",
+            Json::Object(vec![
+                ("const_variables", number(stats.const_variables)),
+                ("variables", number(stats.variables)),
+                ("const_arrays", number(stats.const_arrays)),
+                ("arrays", number(stats.arrays)),
+                ("functions", number(stats.functions.len())),
+            ]),
+        ),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{checker::check, dump::dump_ir, parser::build_ast};
+    use super::*;
+
+    const PROGRAM: &str = "const int N = 2;
+int g[N][3];
+int count;
+int sum(int a[], int n) {
+  int i = 0, s = 0;
+  while (i < n) {
+    if (a[i] > 0) s = s + a[i];
+    i = i + 1;
+  }
+  return s;
+}
+int main() {
+  int b[N][4] = {};
+  {
+    count = sum(b[0], 4);
+  }
+  putint(count);
+  return 0;
+}
+";
+
+    fn program_stats() -> ProgramStats {
+        let (ast, _) = check(vec![build_ast(PROGRAM).unwrap()], &["test.sy"]).unwrap();
+        collect(&ast, &dump_ir(&ast))
+    }
+
+    fn without_ir(f: &FunctionStats) -> (&str, usize, usize, usize, usize, usize, usize, usize) {
+        (
+            f.name.as_str(),
+            f.parameters,
+            f.nodes,
+            f.statements,
+            f.max_depth,
+            f.complexity,
+            f.calls,
+            f.array_bytes,
+        )
+    }
+
+    #[test]
+    fn known_program() {
+        let stats = program_stats();
+        let [sum, main] = &stats.functions[..] else { panic!() };
+        assert_eq!(without_ir(sum), ("sum", 2, 28, 5, 3, 3, 0, 0));
+        assert_eq!(without_ir(main), ("main", 0, 14, 3, 2, 1, 2, 32));
+        assert!(sum.ir_instructions > 0 && main.ir_instructions > 0);
+        assert_eq!(
+            (stats.const_variables, stats.variables, stats.const_arrays, stats.arrays),
+            (1, 1, 0, 1)
+        );
+    }
+
+    #[test]
+    fn table_is_aligned() {
+        let mut stats = program_stats();
+        for function in stats.functions.iter_mut() {
+            function.ir_instructions = 10;
+        }
+        assert_eq!(
+            stats_table(&stats),
+            "\
+函数  参数  结点  语句  嵌套  圈复杂度  调用  局部数组字节  IR 指令
+sum      2    28     5     3         3     0             0       10
+main     0    14     3     2         1     2            32       10
+合计     2    42     8     3         4     2            32       20
+全局定义: 常量 1，变量 1，常量数组 0，数组 1，函数 2
+"
+        );
+    }
+}
@@ -0,0 +1,59 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::ast::*;
+use super::expr::types::lengths_str;
+
+fn parameter_str(parameter: &Parameter) -> String {
+    match parameter {
+        Parameter::Int(id) => format!("int {}", id),
+        Parameter::Pointer(id, lengths) => format!("int {}[]{}", id, lengths_str(lengths)),
+        Parameter::PointerTmp(_, _) => unreachable!(),
+    }
+}
+
+pub fn global_item_str(item: &GlobalItem) -> (&str, String) {
+    match item {
+        GlobalItem::Def(Definition::ConstVariableDef(id, value)) => (id, format!("const int {} = {}", id, value)),
+        GlobalItem::Def(Definition::ConstArrayDef { id, lengths, values: _ }) => {
+            (id, format!("const int {}{}", id, lengths_str(lengths)))
+        }
+        GlobalItem::Def(Definition::VariableDef(id, _)) => (id, format!("int {}", id)),
+        GlobalItem::Def(Definition::ArrayDef {
+            id,
+            lengths,
+            init_list: _,
+        }) => (id, format!("int {}{}", id, lengths_str(lengths))),
+        GlobalItem::FuncDef {
+            return_void,
+            id,
+            parameter_list,
+            block: _,
+        } => {
+            let return_type = if *return_void { "void" } else { "int" };
+            let parameters: Vec<String> = parameter_list.iter().map(parameter_str).collect();
+            (id, format!("{} {}({})", return_type, id, parameters.join(", ")))
+        }
+        _ => unreachable!(),
+    }
+}
+
+pub fn dump_symbols(ast: &TranslationUnit) -> String {
+    let mut symbols: Vec<(&str, String)> = ast.iter().map(|item| global_item_str(item)).collect();
+    symbols.sort();
+    symbols.into_iter().map(|(_, symbol)| format!("{}\n", symbol)).collect()
+}
@@ -0,0 +1,93 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! 运行时检查（bounds、overflow）共用的遍历: 找出函数体中的每个表达式及其所在的行。
+
+use super::ast::*;
+
+struct Walker<'l, F> {
+    lines: std::slice::Iter<'l, usize>,
+    f: F,
+}
+
+impl<F: FnMut(&mut Expr, usize)> Walker<'_, F> {
+    fn init_list(&mut self, init_list: &mut InitList, line: usize) {
+        for item in init_list {
+            match item {
+                InitListItem::InitList(init_list) => self.init_list(init_list, line),
+                InitListItem::Expr(expr) => (self.f)(expr, line),
+            }
+        }
+    }
+
+    fn block(&mut self, block: &mut Block, line: usize) {
+        for item in block {
+            match item {
+                BlockItem::Def(definition) => {
+                    // 与 coverage 相同，每条语句与每个定义按先序对应一个行号
+                    let line = self.lines.next().copied().unwrap_or(line);
+                    match definition.as_mut() {
+                        Definition::VariableDef(_, Some(init)) => (self.f)(init, line),
+                        Definition::ArrayDefTmp {
+                            init_list: Some(init_list),
+                            ..
+                        } => self.init_list(init_list, line),
+                        _ => (),
+                    }
+                }
+                BlockItem::Block(block) => self.block(block, line),
+                BlockItem::Statement(statement) => {
+                    let line = self.lines.next().copied().unwrap_or(line);
+                    match statement.as_mut() {
+                        Statement::Expr(expr) | Statement::Return(Some(expr)) => (self.f)(expr, line),
+                        Statement::If {
+                            condition,
+                            then_block,
+                            else_block,
+                        } => {
+                            (self.f)(condition, line);
+                            self.block(then_block, line);
+                            self.block(else_block, line);
+                        }
+                        Statement::While { condition, block } => {
+                            (self.f)(condition, line);
+                            self.block(block, line);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 按先序把检查之前的语法树中函数体里的表达式（表达式语句、返回值、条件与变量定义的初始值）连同所在语句
+/// 或定义的行号交给 `f`。`lines[i]` 是第 i 个文件中各语句与定义的行号，见 `parser::statement_lines`。
+/// 常量定义与数组长度不在其中，它们在编译期求值。
+pub fn function_exprs(units: &mut [TranslationUnit], lines: &[Vec<usize>], mut f: impl FnMut(&mut Expr, usize)) {
+    for (unit, lines) in units.iter_mut().zip(lines) {
+        let mut walker = Walker {
+            lines: lines.iter(),
+            f: &mut f,
+        };
+        for item in unit.iter_mut() {
+            if let GlobalItem::FuncDef { block, .. } = item.as_mut() {
+                walker.block(block, 0);
+            }
+        }
+    }
+}
@@ -0,0 +1,281 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! 评测用例的测试框架。用例目录中的每个 `名字.sy` 对应一个期望输出 `名字.out`，以及可选的输入 `名字.in`。
+//!
+//! 与评测机的约定一致：实际输出为程序的标准输出，若不以换行结尾则补一个换行，再加上一行退出码。
+
+use crate::arg_parse::Options;
+use crate::process::{self, Outcome};
+use crate::{Source, RUN_STACK_SIZE};
+use std::fs::{read, read_dir, read_to_string, remove_file, write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// 执行编译结果的方式。
+pub enum Runner {
+    /// 用 AST 解释器执行。
+    Interpreter,
+    /// 编译为 Koopa IR 后调用外部命令（例如 IR 的解释器）执行，IR 文件的路径作为最后一个参数。
+    Command(Vec<String>),
+}
+
+#[derive(Clone)]
+pub struct Case {
+    pub name: String,
+    pub source: String,
+    pub input: Vec<u8>,
+    pub expected: String,
+}
+
+/// 按名字顺序读取目录中的全部用例。
+pub fn discover(dir: &Path) -> Result<Vec<Case>, String> {
+    let entries = read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let mut sources = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| format!("{}: {}", dir.display(), e))?.path();
+        if path.extension().is_some_and(|extension| extension == "sy") {
+            sources.push(path);
+        }
+    }
+    sources.sort();
+    sources
+        .into_iter()
+        .map(|path| {
+            let read_text = |path: &Path| read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e));
+            let input = path.with_extension("in");
+            Ok(Case {
+                name: path.file_stem().unwrap().to_string_lossy().into_owned(),
+                source: read_text(&path)?,
+                input: match input.exists() {
+                    true => read(&input).map_err(|e| format!("{}: {}", input.display(), e))?,
+                    false => Vec::new(),
+                },
+                expected: read_text(&path.with_extension("out"))?,
+            })
+        })
+        .collect()
+}
+
+/// 按评测机的约定拼接标准输出和退出码。
+pub fn judge_output(stdout: &[u8], exit_code: i32) -> String {
+    let mut text = String::from_utf8_lossy(stdout).into_owned();
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    format!("{}{}\n", text, exit_code)
+}
+
+/// 忽略行尾空白和文件末尾的空行，比较失败时给出第一处不同。
+pub fn compare(expected: &str, actual: &str) -> Result<(), String> {
+    let lines = |text: &str| -> Vec<String> {
+        let mut lines: Vec<_> = text.lines().map(|line| line.trim_end().to_string()).collect();
+        while lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+        lines
+    };
+    let (expected, actual) = (lines(expected), lines(actual));
+    let line = match expected.iter().zip(&actual).position(|(e, a)| e != a) {
+        Some(line) => line,
+        None if expected.len() == actual.len() => return Ok(()),
+        None => expected.len().min(actual.len()),
+    };
+    let show = |lines: &[String]| lines.get(line).map_or("<文件结束>".to_string(), |line| format!("{:?}", line));
+    Err(format!(
+        "第 {} 行不同\n  期望: {}\n  实际: {}",
+        line + 1,
+        show(&expected),
+        show(&actual)
+    ))
+}
+
+fn sources(case: &Case) -> Vec<Source> {
+    vec![Source {
+        name: format!("{}.sy", case.name),
+        code: case.source.clone(),
+    }]
+}
+
+fn errors(diagnostics: Vec<crate::Diagnostic>) -> String {
+    let errors: Vec<_> = diagnostics.into_iter().map(|(_, e)| e).collect();
+    format!("编译失败: {}", errors.join("\n"))
+}
+
+/// 在单独的线程中解释执行。超时后通知该线程取消执行，它在下一次函数调用或循环迭代时结束。
+fn interpret(case: &Case, timeout: Duration) -> Result<String, String> {
+    let (sender, receiver) = mpsc::channel();
+    let (sources, input) = (sources(case), case.input.clone());
+    let cancel = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&cancel);
+    thread::Builder::new()
+        .stack_size(RUN_STACK_SIZE)
+        .spawn(move || {
+            let mut output = Vec::new();
+            let result = crate::run_with_cancel(
+                &sources,
+                &Options::default(),
+                &mut input.as_slice(),
+                &mut output,
+                &mut std::io::sink(),
+                &flag,
+            );
+            let _ = sender.send(result.map(|execution| judge_output(&output, execution.return_value & 0xff)));
+        })
+        .map_err(|e| e.to_string())?;
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result.map_err(errors),
+        Err(_) => {
+            cancel.store(true, Ordering::Relaxed);
+            Err(format!("运行超时（{} 毫秒）", timeout.as_millis()))
+        }
+    }
+}
+
+fn execute(case: &Case, command: &[String], timeout: Duration) -> Result<String, String> {
+    let artifacts = crate::compile(&sources(case), &Options::default()).map_err(errors)?;
+    let path = std::env::temp_dir().join(format!("xenon-test-{}-{}.koopa", std::process::id(), case.name));
+    write(&path, artifacts.output).map_err(|e| e.to_string())?;
+    let result = spawn(command, &path, &case.input, timeout);
+    let _ = remove_file(&path);
+    result
+}
+
+fn spawn(command: &[String], path: &Path, input: &[u8], timeout: Duration) -> Result<String, String> {
+    let mut command = command.to_vec();
+    command.push(path.display().to_string());
+    match process::execute(&command, input, timeout)? {
+        (Outcome::Exited(code), output) => Ok(judge_output(&output, code)),
+        (Outcome::Signaled, _) => Err(format!("{} 被信号终止", command[0])),
+        (Outcome::TimedOut, _) => Err(format!("运行超时（{} 毫秒）", timeout.as_millis())),
+    }
+}
+
+/// 运行一个用例，失败时返回可读的报告。
+pub fn run_case(case: &Case, runner: &Runner, timeout: Duration) -> Result<(), String> {
+    let actual = match runner {
+        Runner::Interpreter => interpret(case, timeout)?,
+        Runner::Command(command) => execute(case, command, timeout)?,
+    };
+    compare(&case.expected, &actual)
+}
+
+/// 用 `jobs` 个线程并行运行全部用例，结果与 `cases` 一一对应。
+pub fn run_all(cases: &[Case], runner: &Runner, jobs: usize, timeout: Duration) -> Vec<Result<(), String>> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![Ok(()); cases.len()]);
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, cases.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(case) = cases.get(index) else { break };
+                let result = run_case(case, runner, timeout);
+                results.lock().unwrap()[index] = result;
+            });
+        }
+    });
+    results.into_inner().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(name: &str, source: &str, input: &str, expected: &str) -> Case {
+        Case {
+            name: name.to_string(),
+            source: source.to_string(),
+            input: input.as_bytes().to_vec(),
+            expected: expected.to_string(),
+        }
+    }
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn exit_code_on_its_own_line() {
+        assert_eq!(judge_output(b"", 0), "0\n");
+        assert_eq!(judge_output(b"12", 3), "12\n3\n");
+        assert_eq!(judge_output(b"12\n", 3), "12\n3\n");
+    }
+
+    #[test]
+    fn first_divergence() {
+        assert!(compare("1 2\n3\n", "1 2  \n3\n\n").is_ok());
+        assert_eq!(
+            compare("1\n2\n0\n", "1\n4\n0\n").unwrap_err(),
+            "第 2 行不同\n  期望: \"2\"\n  实际: \"4\""
+        );
+        assert_eq!(
+            compare("1\n0\n", "1\n").unwrap_err(),
+            "第 2 行不同\n  期望: \"0\"\n  实际: <文件结束>"
+        );
+    }
+
+    #[test]
+    fn embedded_cases() {
+        let dir = std::env::temp_dir().join(format!("xenon-harness-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        write(dir.join("00_return.sy"), "int main() { return 259; }").unwrap();
+        write(dir.join("00_return.out"), "3\n").unwrap();
+        write(
+            dir.join("01_echo.sy"),
+            "int main() { int n = getint(); putint(n * 2); return n; }",
+        )
+        .unwrap();
+        write(dir.join("01_echo.in"), "21\n").unwrap();
+        write(dir.join("01_echo.out"), "42\n21\n").unwrap();
+        let cases = discover(&dir).unwrap();
+        assert_eq!(
+            cases.iter().map(|case| case.name.as_str()).collect::<Vec<_>>(),
+            ["00_return", "01_echo"]
+        );
+        for result in run_all(&cases, &Runner::Interpreter, 2, TIMEOUT) {
+            assert_eq!(result, Ok(()));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failures_are_reported() {
+        let wrong = case("wrong", "int main() { putch(65); return 0; }", "", "B\n0\n");
+        assert_eq!(
+            run_case(&wrong, &Runner::Interpreter, TIMEOUT).unwrap_err(),
+            "第 1 行不同\n  期望: \"B\"\n  实际: \"A\""
+        );
+        let invalid = case("invalid", "int main() { return x; }", "", "0\n");
+        assert!(run_case(&invalid, &Runner::Interpreter, TIMEOUT)
+            .unwrap_err()
+            .starts_with("编译失败"));
+        let endless = case("endless", "int main() { while (1) {} return 0; }", "", "0\n");
+        let result = run_case(&endless, &Runner::Interpreter, Duration::from_millis(100));
+        assert_eq!(result.unwrap_err(), "运行超时（100 毫秒）");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn external_command() {
+        // 脚本忽略 IR 文件，只检查输入能否传给外部命令
+        let script = "read n; echo $((n + 1)); exit 2";
+        let runner = Runner::Command(vec!["sh".to_string(), "-c".to_string(), script.to_string()]);
+        let echo = case("external", "int main() { return 0; }", "41\n", "42\n2\n");
+        assert_eq!(run_case(&echo, &runner, TIMEOUT), Ok(()));
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Xenon 的库入口。编译过程不做任何输入输出，也不会因用户输入而 panic 或退出进程。
+
+use arg_parse::{Emit, Instrument, Options, Trace};
+use std::io::{BufRead, Write};
+use std::sync::atomic::AtomicBool;
+
+pub mod arg_parse;
+pub mod diagnostics;
+mod frontend;
+pub mod harness;
+mod preprocessor;
+pub mod process;
+pub mod runtime;
+
+pub use frontend::incremental::{Document, Severity, SpannedDiagnostic};
+pub use frontend::{ChineseFormatter, Diagnostic, ErrorFormatter, ItemKind, Source};
+
+/// 解释器的每层函数调用都占用宿主的栈，解释执行应在栈至少这么大的线程中进行。
+/// 语法分析与生成 IR 等递归的遍历也在这样的线程中进行，嵌套很深的源代码不会使进程因栈溢出而终止。
+pub const RUN_STACK_SIZE: usize = 1 << 30;
+
+/// 在栈大小为 [`RUN_STACK_SIZE`] 的线程中调用 `f`；无法创建线程时在当前线程中调用。
+fn with_large_stack<T: Send>(f: impl Fn() -> T + Sync) -> T {
+    std::thread::scope(|scope| {
+        match std::thread::Builder::new().stack_size(RUN_STACK_SIZE).spawn_scoped(scope, &f) {
+            // 线程中的 panic 原样传回调用者
+            Ok(handle) => handle.join().unwrap_or_else(|payload| std::panic::resume_unwind(payload)),
+            Err(_) => f(),
+        }
+    })
+}
+
+/// 编译的产物。`output` 的内容由 [`Options::emit`] 决定。
+pub struct Artifacts {
+    pub output: String,
+    pub warnings: Vec<Diagnostic>,
+    /// 插桩（见 [`Options::instrument`]）时映射文件的内容
+    pub instrument_map: Option<String>,
+    /// 产物完整、但源代码有错误时的错误，目前只有 [`Emit::Tokens`] 的词法错误。不为空时编译失败。
+    pub errors: Vec<Diagnostic>,
+}
+
+/// 解释执行的结果。
+pub struct Execution {
+    pub return_value: i32,
+    pub warnings: Vec<Diagnostic>,
+    /// 插桩执行时映射文件的内容
+    pub instrument_map: Option<String>,
+}
+
+fn prepare(sources: &[Source], emit: &Emit) -> Vec<Source> {
+    sources
+        .iter()
+        .map(|source| {
+            let code = source.code.replace("\r\n", "\n");
+            Source {
+                name: source.name.clone(),
+                // 格式化和文档需要保留注释，增量检查自行预处理，都不做预处理
+                code: match emit {
+                    Emit::Format | Emit::VerifyIncremental | Emit::Doc => code,
+                    _ => preprocessor::preprocess(&code),
+                },
+            }
+        })
+        .collect()
+}
+
+/// 编译一组源文件。`sources` 中是未经预处理的源代码；诊断信息中的下标指向 `sources`。
+pub fn compile(sources: &[Source], options: &Options) -> Result<Artifacts, Vec<Diagnostic>> {
+    with_large_stack(|| compile_sources(sources, options))
+}
+
+fn compile_sources(sources: &[Source], options: &Options) -> Result<Artifacts, Vec<Diagnostic>> {
+    let sources = prepare(sources, &options.emit);
+    let checks = options.check_bounds || options.sanitize_overflow;
+    if (options.instrument != Instrument::None || checks) && matches!(options.emit, Emit::Ir) {
+        return match frontend::generate_instrumented_ir(&sources, options) {
+            Ok((output, instrument_map, warnings)) => Ok(Artifacts {
+                output,
+                warnings,
+                instrument_map,
+                errors: Vec::new(),
+            }),
+            Err(error) => Err(vec![error]),
+        };
+    }
+    let result = match options.emit {
+        // 词法错误不影响输出，整个文件都会列出
+        Emit::Tokens => {
+            let (output, errors) = frontend::generate_tokens(&sources);
+            return Ok(Artifacts {
+                output,
+                warnings: Vec::new(),
+                instrument_map: None,
+                errors,
+            });
+        }
+        Emit::Ast => frontend::generate_ast_text(&sources),
+        Emit::Symbols => frontend::generate_symbols(&sources),
+        Emit::Doc => frontend::generate_doc(&sources),
+        Emit::Stats => frontend::generate_stats(&sources, false),
+        Emit::StatsJson => frontend::generate_stats(&sources, true),
+        Emit::ReportDead => frontend::generate_dead_code(&sources, false),
+        Emit::ReportDeadJson => frontend::generate_dead_code(&sources, true),
+        Emit::Format => frontend::generate_format(&sources),
+        Emit::VerifyIncremental => frontend::generate_incremental(&sources),
+        // 只做检查，执行需要调用 run
+        Emit::Run => frontend::generate_checked(&sources, &ChineseFormatter),
+        Emit::Ir => frontend::generate_ir(&sources),
+        Emit::Runtime(format) => Ok((runtime::source(format, options.target).to_string(), Vec::new())),
+    };
+    match result {
+        Ok((output, warnings)) => Ok(Artifacts {
+            output,
+            warnings,
+            instrument_map: None,
+            errors: Vec::new(),
+        }),
+        Err(error) => Err(vec![error]),
+    }
+}
+
+/// 检查一组源文件，返回警告。诊断信息由 `formatter` 生成，可以换成其他语言；[`compile`] 使用 [`ChineseFormatter`]。
+pub fn check(sources: &[Source], formatter: &(dyn ErrorFormatter + Sync)) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    with_large_stack(|| match frontend::generate_checked(&prepare(sources, &Emit::Run), formatter) {
+        Ok((_, warnings)) => Ok(warnings),
+        Err(error) => Err(vec![error]),
+    })
+}
+
+/// 解释执行一组源文件。运行时库函数从 `input` 读取输入，向 `output` 输出，插桩的计数写到 `errors`。
+/// `options` 中只有 [`Options::instrument`]、[`Options::check_bounds`] 与 [`Options::sanitize_overflow`] 起作用。
+pub fn run(
+    sources: &[Source],
+    options: &Options,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    errors: &mut dyn Write,
+) -> Result<Execution, Vec<Diagnostic>> {
+    run_with_cancel(sources, options, input, output, errors, &AtomicBool::new(false))
+}
+
+/// 同 [`run`]，其他线程置位 `cancel` 后，执行在下一次函数调用或循环迭代时以运行时错误结束。
+pub fn run_with_cancel(
+    sources: &[Source],
+    options: &Options,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    errors: &mut dyn Write,
+    cancel: &AtomicBool,
+) -> Result<Execution, Vec<Diagnostic>> {
+    match frontend::run(&prepare(sources, &Emit::Run), options, input, output, errors, cancel) {
+        Ok((return_value, instrument_map, warnings)) => Ok(Execution {
+            return_value,
+            warnings,
+            instrument_map,
+        }),
+        Err(error) => Err(vec![error]),
+    }
+}
+
+/// 把 --coverage 的映射文件与程序输出到标准错误的计数对应到源代码，生成逐行的执行次数与覆盖率。
+/// `counts` 中可以有多次运行的计数，它们被累加；`sources` 是编译时的源文件。
+pub fn coverage_report(map: &str, counts: &str, sources: &[Source]) -> Result<String, String> {
+    frontend::coverage::report(map, counts, sources)
+}
+
+/// 按 `trace`（即 --trace=<类别>）打开的类别检查一组源文件，返回跟踪记录，每条一行。源代码有错误时返回出错之前的记录。
+/// 记录中的诊断信息由 `formatter` 生成，同 [`check`]。
+pub fn trace(sources: &[Source], trace: Trace, formatter: &(dyn ErrorFormatter + Sync)) -> String {
+    with_large_stack(|| frontend::generate_trace(&prepare(sources, &Emit::Ir), formatter, trace.const_eval, trace.checker))
+}
+
+/// 以各种输出方式编译任意输入，供模糊测试使用。源代码中的错误只产生诊断信息，被忽略；
+/// 编译器 panic 时返回 panic 的信息。
+pub fn check_no_panic(code: &str) -> Result<(), String> {
+    let sources = [Source {
+        name: "fuzz.sy".to_string(),
+        code: code.to_string(),
+    }];
+    let emits = [
+        Emit::Tokens,
+        Emit::Ast,
+        Emit::Ir,
+        Emit::Symbols,
+        Emit::Format,
+        Emit::VerifyIncremental,
+        Emit::Doc,
+        Emit::Stats,
+        Emit::ReportDead,
+    ];
+    std::panic::catch_unwind(|| {
+        for emit in emits {
+            let options = Options {
+                emit,
+                ..Options::default()
+            };
+            let _ = compile(&sources, &options);
+        }
+    })
+    .map_err(|payload| match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .map_or("未知的 panic".to_string(), |message| message.to_string()),
+    })
+}
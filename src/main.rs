@@ -15,25 +15,200 @@
 // You should have received a copy of the GNU General Public License
 // along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::fs::{read_to_string, File};
-use std::io::Write;
-
-mod arg_parse;
-mod frontend;
-mod preprocessor;
-
-fn compile() -> Result<(), Box<dyn std::error::Error>> {
-    let (mode, input, output) = arg_parse::parse(std::env::args())?;
-    let code = preprocessor::preprocess(&read_to_string(input)?.replace("\r\n", "\n"));
-    let ir = frontend::generate_ir(&code)?;
-    let mut f = File::create(output)?;
-    f.write_fmt(format_args!("{}", ir))?;
+use std::fs::{read, read_to_string, File};
+use std::io::{stderr, stdin, stdout, BufWriter, Read, Write};
+use std::path::Path;
+use std::process::exit;
+use std::thread;
+use std::time::Duration;
+use xenon::arg_parse::{self, Command, Emit, Options, TestOptions};
+use xenon::diagnostics::codes;
+use xenon::harness::{self, Runner};
+use xenon::{ChineseFormatter, Diagnostic, Source, RUN_STACK_SIZE};
+
+// 退出码: 0 成功，1 源代码有错误，2 用法或输入输出错误，101 编译器内部错误
+const EXIT_SOURCE_ERROR: i32 = 1;
+const EXIT_USAGE_ERROR: i32 = 2;
+const EXIT_INTERNAL_ERROR: i32 = 101;
+
+enum Failure {
+    Source(String),
+    Usage(String),
+}
+
+impl Failure {
+    fn exit(self) -> ! {
+        let (message, code) = match self {
+            Failure::Source(message) => (message, EXIT_SOURCE_ERROR),
+            Failure::Usage(message) => (message, EXIT_USAGE_ERROR),
+        };
+        eprintln!("{}", message);
+        exit(code)
+    }
+}
+
+fn read_source(input: &str, name: &str) -> Result<String, Failure> {
+    let bytes = match input {
+        "-" => {
+            let mut bytes = Vec::new();
+            stdin().read_to_end(&mut bytes).map(|_| bytes)
+        }
+        _ => read(input),
+    };
+    let bytes = bytes.map_err(|e| Failure::Usage(format!("{}: 错误: {}", name, e)))?;
+    String::from_utf8(bytes).map_err(|e| {
+        let position = e.utf8_error().valid_up_to();
+        Failure::Source(format!(
+            "{}: 错误: 源代码不是合法的 UTF-8 文本，第 {} 个字节有误",
+            name, position
+        ))
+    })
+}
+
+fn read_sources(inputs: &[String]) -> Result<Vec<Source>, Failure> {
+    inputs
+        .iter()
+        .map(|input| {
+            let name = match input.as_str() {
+                "-" => "<stdin>".to_string(),
+                input => input.to_string(),
+            };
+            let code = read_source(input, &name)?;
+            Ok(Source { name, code })
+        })
+        .collect()
+}
+
+fn report(sources: &[Source], errors: Vec<Diagnostic>) -> String {
+    let errors: Vec<_> = errors
+        .into_iter()
+        .map(|(file, e)| format!("{}: 错误: {}", sources[file].name, e))
+        .collect();
+    errors.join("\n")
+}
+
+fn warn(sources: &[Source], warnings: Vec<Diagnostic>) {
+    for (file, warning) in warnings {
+        eprintln!("{}: 警告: {}", sources[file].name, warning);
+    }
+}
+
+fn trace(sources: &[Source], options: &Options) {
+    if options.trace.is_enabled() {
+        eprint!("{}", xenon::trace(sources, options.trace, &ChineseFormatter));
+    }
+}
+
+/// 把插桩的映射文件写到第一个输入文件旁。
+fn write_instrument_map(options: &Options, map: Option<String>) -> Result<(), Failure> {
+    let (Some(map), Some(extension)) = (map, options.instrument.map_extension()) else {
+        return Ok(());
+    };
+    let path = arg_parse::map_path(&options.inputs[0], extension);
+    std::fs::write(&path, map).map_err(|e| Failure::Usage(format!("{}: 错误: 无法写入映射文件: {}", path.display(), e)))
+}
+
+fn compile(options: &Options) -> Result<(), Failure> {
+    let sources = read_sources(&options.inputs)?;
+    trace(&sources, options);
+    let artifacts = xenon::compile(&sources, options).map_err(|errors| Failure::Source(report(&sources, errors)))?;
+    warn(&sources, artifacts.warnings);
+    write_instrument_map(options, artifacts.instrument_map)?;
+    // 产物只写到 -o 指定的文件或标准输出，诊断信息只写到标准错误
+    let written = match &options.output {
+        Some(output) => File::create(output).and_then(|mut file| file.write_all(artifacts.output.as_bytes())),
+        None => stdout().lock().write_all(artifacts.output.as_bytes()),
+    };
+    let name = options.output.as_deref().unwrap_or("<stdout>");
+    written.map_err(|e| Failure::Usage(format!("{}: 错误: 无法写入输出: {}", name, e)))?;
+    match artifacts.errors.is_empty() {
+        true => Ok(()),
+        false => Err(Failure::Source(report(&sources, artifacts.errors))),
+    }
+}
+
+fn run(options: &Options) -> Result<i32, Failure> {
+    let sources = read_sources(&options.inputs)?;
+    trace(&sources, options);
+    let execution = thread::scope(|scope| {
+        let handle = thread::Builder::new().stack_size(RUN_STACK_SIZE).spawn_scoped(scope, || {
+            let mut output = BufWriter::new(stdout().lock());
+            xenon::run(&sources, options, &mut stdin().lock(), &mut output, &mut stderr())
+        });
+        handle.map(|handle| handle.join().unwrap())
+    });
+    let execution = execution.map_err(|e| Failure::Usage(format!("xenon: 错误: 无法创建解释器线程: {}", e)))?;
+    let execution = execution.map_err(|errors| Failure::Source(report(&sources, errors)))?;
+    warn(&sources, execution.warnings);
+    write_instrument_map(options, execution.instrument_map)?;
+    Ok(execution.return_value)
+}
+
+fn coverage_report(counts: &str, inputs: &[String]) -> Result<(), Failure> {
+    let sources = read_sources(inputs)?;
+    let counts = read_to_string(counts).map_err(|e| Failure::Usage(format!("{}: 错误: {}", counts, e)))?;
+    let path = arg_parse::map_path(&inputs[0], "covmap");
+    let map = read_to_string(&path).map_err(|e| Failure::Usage(format!("{}: 错误: {}", path.display(), e)))?;
+    let report = xenon::coverage_report(&map, &counts, &sources).map_err(|e| Failure::Source(format!("xenon: 错误: {}", e)))?;
+    print!("{}", report);
     Ok(())
 }
 
+fn test(options: &TestOptions) -> Result<bool, Failure> {
+    let cases = harness::discover(Path::new(&options.dir)).map_err(|e| Failure::Usage(format!("xenon: 错误: {}", e)))?;
+    let runner = match &options.runner {
+        Some(command) => Runner::Command(command.clone()),
+        None => Runner::Interpreter,
+    };
+    let results = harness::run_all(&cases, &runner, options.jobs, Duration::from_secs(options.timeout));
+    let mut passed = 0;
+    for (case, result) in cases.iter().zip(results) {
+        match result {
+            Ok(()) => {
+                passed += 1;
+                println!("{} ... 通过", case.name);
+            }
+            Err(report) => println!("{} ... 失败\n  {}", case.name, report.replace('\n', "\n  ")),
+        }
+    }
+    println!("{}/{} 个用例通过", passed, cases.len());
+    Ok(passed == cases.len())
+}
+
 fn main() {
-    if let Err(s) = compile() {
-        println!("{}", s);
-        std::process::exit(0);
+    // 任何线程中的 panic 都是编译器自身的缺陷，用单独的退出码与源代码错误区分
+    std::panic::set_hook(Box::new(|info| {
+        eprintln!("xenon: 内部错误: {}\n这是 Xenon 的缺陷，请附上源代码报告问题", info);
+        exit(EXIT_INTERNAL_ERROR);
+    }));
+    match arg_parse::parse(std::env::args()) {
+        // 与评测机一致，退出码为 main 的返回值的低 8 位
+        Ok(Command::Compile(options @ Options { emit: Emit::Run, .. })) => match run(&options) {
+            Ok(value) => exit(value & 0xff),
+            Err(failure) => failure.exit(),
+        },
+        Ok(Command::Compile(options)) => {
+            if let Err(failure) = compile(&options) {
+                failure.exit();
+            }
+        }
+        Ok(Command::Test(options)) => match test(&options) {
+            Ok(true) => (),
+            Ok(false) => exit(EXIT_SOURCE_ERROR),
+            Err(failure) => failure.exit(),
+        },
+        Ok(Command::CoverageReport { counts, inputs }) => {
+            if let Err(failure) = coverage_report(&counts, &inputs) {
+                failure.exit();
+            }
+        }
+        Ok(Command::Explain(code)) => match codes::lookup(&code) {
+            Some(entry) => print!("{}: {}\n\n{}", entry.code, entry.summary, entry.explanation),
+            None => Failure::Usage(format!("xenon: 错误: 没有编号为 {} 的错误或警告", code)).exit(),
+        },
+        Ok(Command::Help) => print!("{}", arg_parse::USAGE),
+        Ok(Command::Version) => println!("xenon {}", env!("CARGO_PKG_VERSION")),
+        Ok(Command::ForceInternalError) => panic!("由 --force-internal-error 触发"),
+        Err(s) => Failure::Usage(format!("xenon: 错误: {}\n使用 --help 查看用法", s)).exit(),
     }
 }
@@ -66,6 +66,7 @@ fn string_literal_with_escape_fun(c: char) -> (State, Option<char>, Option<char>
 fn comment_fun(c: char) -> (State, Option<char>, Option<char>) {
     match c {
         '*' => (CommentWithStar, None, None),
+        '\n' => (Comment, Some(c), None),
         _ => (Comment, None, None),
     }
 }
@@ -86,6 +87,7 @@ fn comment_with_star_fun(c: char) -> (State, Option<char>, Option<char>) {
     match c {
         '/' => (Code, Some(' '), None),
         '*' => (CommentWithStar, None, None),
+        '\n' => (Comment, Some(c), None),
         _ => (Comment, None, None),
     }
 }
@@ -0,0 +1,106 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! 运行外部程序并限制运行时间，供评测用例的外部执行方式使用。
+
+use std::io::{ErrorKind, Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// 程序结束的方式。
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Exited(i32),
+    /// 被信号终止，例如段错误
+    Signaled,
+    /// 超时后被终止
+    TimedOut,
+}
+
+/// 运行 `command`，其中第一项是程序，其余是参数。`input` 写入程序的标准输入，返回程序结束的方式与它的标准输出；
+/// 标准错误被丢弃。超过 `timeout` 的程序被终止。
+pub fn execute(command: &[String], input: &[u8], timeout: Duration) -> Result<(Outcome, Vec<u8>), String> {
+    let Some((program, args)) = command.split_first() else {
+        return Err("命令为空".to_string());
+    };
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => format!("找不到命令 {}", program),
+            _ => format!("无法启动 {}: {}", program, e),
+        })?;
+    // 输入和输出都在单独的线程中读写，避免管道写满后互相等待
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    thread::spawn(move || stdin.write_all(&input));
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok((Outcome::TimedOut, Vec::new()));
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    let output = reader.join().unwrap().map_err(|e| e.to_string())?;
+    match status.code() {
+        Some(code) => Ok((Outcome::Exited(code), output)),
+        None => Ok((Outcome::Signaled, output)),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Vec<String> {
+        vec!["sh".to_string(), "-c".to_string(), script.to_string()]
+    }
+
+    #[test]
+    fn exit_code_and_output() {
+        let (outcome, output) = execute(&sh("cat; exit 3"), b"abc", Duration::from_secs(10)).unwrap();
+        assert_eq!((outcome, output), (Outcome::Exited(3), b"abc".to_vec()));
+    }
+
+    #[test]
+    fn timeout_and_signal() {
+        let start = Instant::now();
+        let result = execute(&sh("sleep 10"), b"", Duration::from_millis(100));
+        assert_eq!(result.unwrap().0, Outcome::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        let result = execute(&sh("kill -9 $$"), b"", Duration::from_secs(10));
+        assert_eq!(result.unwrap().0, Outcome::Signaled);
+        assert!(execute(&["/nonexistent/program".to_string()], b"", Duration::from_secs(1))
+            .unwrap_err()
+            .starts_with("找不到命令"));
+        assert_eq!(execute(&[], b"", Duration::from_secs(1)).unwrap_err(), "命令为空");
+    }
+}
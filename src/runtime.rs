@@ -0,0 +1,85 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! SysY 运行时库（libsysy）的源代码模板，供 --emit-runtime 输出。
+
+use crate::arg_parse::{RuntimeFormat, Target};
+
+const C: &str = include_str!("runtime/sysy.c");
+const RISCV32: &str = include_str!("runtime/riscv32.S");
+
+/// 生成的代码所依赖的全部运行时函数。`starttime()` 与 `stoptime()` 是调用 `_sysy_` 版本的宏。
+pub const FUNCTIONS: [&str; 13] = [
+    "getint",
+    "getch",
+    "getarray",
+    "putint",
+    "putch",
+    "putarray",
+    "putf",
+    "_sysy_starttime",
+    "_sysy_stoptime",
+    "_sysy_write_counters",
+    "_sysy_write_profile",
+    "_sysy_check_bound",
+    "_sysy_check_overflow",
+];
+
+/// 运行时库的源代码。C 版本与目标平台无关，需要用目标平台的交叉编译器编译。
+pub fn source(format: RuntimeFormat, target: Target) -> &'static str {
+    match (format, target) {
+        (RuntimeFormat::C, _) => C,
+        (RuntimeFormat::Asm, Target::Riscv32) => RISCV32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_function_is_defined() {
+        for function in FUNCTIONS {
+            assert!(
+                C.lines().any(|line| line.contains(&format!(" {}(", function))),
+                "{}",
+                function
+            );
+            assert!(RISCV32.contains(&format!(".globl {}\n", function)), "{}", function);
+            assert!(RISCV32.contains(&format!("\n{}:\n", function)), "{}", function);
+        }
+    }
+
+    /// 汇编器取自 RISCV_AS，缺省时为 riscv64-unknown-elf-as。只检查能否汇编，行为与 C 版本的对照要等后端实现后
+    /// 在 qemu-riscv32 中运行。
+    #[test]
+    #[ignore = "需要 RISC-V 汇编器"]
+    fn riscv32_assembles() {
+        let dir = std::env::temp_dir().join(format!("xenon-runtime-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (runtime, object) = (dir.join("sysy.S"), dir.join("sysy.o"));
+        std::fs::write(&runtime, RISCV32).unwrap();
+        let assembler = std::env::var("RISCV_AS").unwrap_or_else(|_| "riscv64-unknown-elf-as".to_string());
+        let output = std::process::Command::new(&assembler)
+            .args(["-march=rv32im".as_ref(), "-mabi=ilp32".as_ref(), runtime.as_os_str()])
+            .args(["-o".as_ref(), object.as_os_str()])
+            .output();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let output = output.unwrap_or_else(|e| panic!("无法运行 {}: {}", assembler, e));
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+}
@@ -0,0 +1,143 @@
+/*
+ * SysY 运行时库，由 xenon --emit-runtime=c 生成。
+ * 可观察的行为与评测使用的官方 libsysy 一致：输入输出格式、计时器的汇总格式都相同。
+ */
+#include <limits.h>
+#include <stdarg.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/time.h>
+
+/* 输入 */
+
+int getint(void) {
+  int t;
+  scanf("%d", &t);
+  return t;
+}
+
+int getch(void) {
+  char c;
+  scanf("%c", &c);
+  return (int)c;
+}
+
+int getarray(int a[]) {
+  int n;
+  scanf("%d", &n);
+  for (int i = 0; i < n; i++) scanf("%d", &a[i]);
+  return n;
+}
+
+/* 输出 */
+
+void putint(int a) { printf("%d", a); }
+
+void putch(int a) { printf("%c", a); }
+
+void putarray(int n, int a[]) {
+  printf("%d:", n);
+  for (int i = 0; i < n; i++) printf(" %d", a[i]);
+  printf("\n");
+}
+
+void putf(char a[], ...) {
+  va_list args;
+  va_start(args, a);
+  vfprintf(stdout, a, args);
+  va_end(args);
+}
+
+/* 覆盖率：--coverage 插桩的程序在 main 返回之前调用，向标准错误输出一行计数 */
+
+void _sysy_write_counters(int n, int a[]) {
+  fprintf(stderr, "xenon-coverage: %d", n);
+  for (int i = 0; i < n; i++) fprintf(stderr, " %d", a[i]);
+  fprintf(stderr, "\n");
+}
+
+/* 性能剖析：--profile-generate 插桩的程序在 main 返回之前调用，向标准错误每个计数器输出一行 */
+
+void _sysy_write_profile(int n, int a[]) {
+  for (int i = 0; i < n; i++) fprintf(stderr, "xenon-profile: %d %d\n", i, a[i]);
+}
+
+/* 下标检查：--check-bounds 插桩的程序在每次取下标之前调用，越界时报告位置并退出 */
+
+int _sysy_check_bound(int index, int length, int line) {
+  if (index < 0 || index >= length) {
+    fprintf(stderr, "第 %d 行: 下标 %d 越界，长度为 %d\n", line, index, length);
+    exit(1);
+  }
+  return index;
+}
+
+/* 溢出检查：--sanitize=overflow 插桩的程序用它计算 + - * /，有符号整数溢出时报告位置并退出 */
+
+int _sysy_check_overflow(int op, int lhs, int rhs, int line) {
+  long long result;
+  switch (op) {
+  case 0:
+    result = (long long)lhs + rhs;
+    break;
+  case 1:
+    result = (long long)lhs - rhs;
+    break;
+  case 2:
+    result = (long long)lhs * rhs;
+    break;
+  default:
+    /* 除以 0 与不检查时相同 */
+    if (rhs == 0)
+      return lhs / rhs;
+    result = (long long)lhs / rhs;
+  }
+  if (result < INT_MIN || result > INT_MAX) {
+    fprintf(stderr, "第 %d 行: %d %c %d 溢出\n", line, lhs, "+-*/"[op], rhs);
+    exit(1);
+  }
+  return (int)result;
+}
+
+/* 计时：starttime() 与 stoptime() 是传入行号的宏，程序退出时向标准错误输出每段计时和总计 */
+
+#define _SYSY_N 1024
+static int _sysy_l1[_SYSY_N], _sysy_l2[_SYSY_N];
+static int _sysy_h[_SYSY_N], _sysy_m[_SYSY_N], _sysy_s[_SYSY_N], _sysy_us[_SYSY_N];
+static int _sysy_idx = 1;
+static struct timeval _sysy_start, _sysy_end;
+
+__attribute__((destructor)) static void _sysy_report(void) {
+  for (int i = 1; i < _sysy_idx; i++) {
+    fprintf(stderr, "Timer@%04d-%04d: %dH-%dM-%dS-%dus\n", _sysy_l1[i], _sysy_l2[i], _sysy_h[i], _sysy_m[i],
+            _sysy_s[i], _sysy_us[i]);
+    /* 与官方实现相同的累加顺序，总计的进位方式也因此相同 */
+    _sysy_us[0] += _sysy_us[i];
+    _sysy_s[0] += _sysy_s[i];
+    _sysy_us[0] %= 1000000;
+    _sysy_m[0] += _sysy_m[i];
+    _sysy_s[0] %= 60;
+    _sysy_h[0] += _sysy_h[i];
+    _sysy_m[0] %= 60;
+  }
+  fprintf(stderr, "TOTAL: %dH-%dM-%dS-%dus\n", _sysy_h[0], _sysy_m[0], _sysy_s[0], _sysy_us[0]);
+}
+
+void _sysy_starttime(int lineno) {
+  _sysy_l1[_sysy_idx] = lineno;
+  gettimeofday(&_sysy_start, NULL);
+}
+
+void _sysy_stoptime(int lineno) {
+  gettimeofday(&_sysy_end, NULL);
+  _sysy_l2[_sysy_idx] = lineno;
+  _sysy_us[_sysy_idx] +=
+      1000000 * (_sysy_end.tv_sec - _sysy_start.tv_sec) + _sysy_end.tv_usec - _sysy_start.tv_usec;
+  _sysy_s[_sysy_idx] += _sysy_us[_sysy_idx] / 1000000;
+  _sysy_us[_sysy_idx] %= 1000000;
+  _sysy_m[_sysy_idx] += _sysy_s[_sysy_idx] / 60;
+  _sysy_s[_sysy_idx] %= 60;
+  _sysy_h[_sysy_idx] += _sysy_m[_sysy_idx] / 60;
+  _sysy_m[_sysy_idx] %= 60;
+  _sysy_idx++;
+}
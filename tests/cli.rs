@@ -0,0 +1,448 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fs::write;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn source_file(name: &str, code: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("xenon-cli-{}-{}", std::process::id(), name));
+    write(&path, code).unwrap();
+    path
+}
+
+fn xenon(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_xenon")).args(args).output().unwrap()
+}
+
+#[test]
+fn compile_success() {
+    let input = source_file("ok.sy", "int main() { return 0; }");
+    let output = xenon(&[input.to_str().unwrap(), "--emit-ir"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("fun @main(): i32"));
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn semantic_error() {
+    let input = source_file("error.sy", "int main() { return x; }");
+    let output = xenon(&[input.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("错误: [E001] x 不存在"));
+}
+
+/// 词法错误之后的词法单元照常列出，错误写到标准错误，以源代码错误结束。
+#[test]
+fn dump_tokens_with_lexical_error() {
+    let input = source_file("lex.sy", "int a = 1 @;\n");
+    let output = xenon(&["--dump-tokens", input.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("1:11-1:12 Error @\n1:12-1:13 Punctuator ;\n2:1-2:1 Eof\n"), "{}", stdout);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("错误: 1:11: 无法识别的词法单元 @"));
+}
+
+#[test]
+fn bad_flags() {
+    let input = source_file("flags.sy", "int main() { return 0; }");
+    let input = input.to_str().unwrap();
+    for args in [
+        vec![input, "--bogus"],
+        vec![input, "--emit-ir", "--emit-ast"],
+        vec![input, "--target=x86"],
+        vec![input, "--emit-asm"],
+        vec![input, "-O2"],
+        vec![input, "-g"],
+        vec!["--emit-runtime=asm"],
+        vec![input, "-o"],
+        vec![],
+    ] {
+        let output = xenon(&args);
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("--help"));
+    }
+}
+
+#[test]
+fn help_and_version() {
+    assert!(String::from_utf8_lossy(&xenon(&["--help"]).stdout).starts_with("用法"));
+    assert!(String::from_utf8_lossy(&xenon(&["--version"]).stdout).starts_with("xenon "));
+}
+
+#[test]
+fn legacy_invocation() {
+    let input = source_file("legacy.sy", "int main() { return 0; }");
+    let output = std::env::temp_dir().join(format!("xenon-cli-{}-legacy.koopa", std::process::id()));
+    let result = xenon(&["-koopa", input.to_str().unwrap(), "-o", output.to_str().unwrap()]);
+    assert_eq!(result.status.code(), Some(0));
+    assert!(std::fs::read_to_string(output).unwrap().contains("fun @main(): i32"));
+}
+
+fn xenon_stdin(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_xenon"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn source_from_stdin() {
+    let output = xenon_stdin(&["-", "--emit-ir"], b"int main() { return 3; }");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("ret 3"));
+
+    let output = xenon_stdin(&["-"], b"int main() { return x; }");
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("<stdin>: 错误: "));
+
+    let output = xenon_stdin(&["-"], b"int main() { \xff\xfe return 0; }");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("不是合法的 UTF-8 文本"));
+}
+
+#[test]
+fn multiple_translation_units() {
+    let util = source_file(
+        "util.sy",
+        "int twice(int x) { return helper(x) * 2; }\nint helper(int x) { return x + K; }\n",
+    );
+    let main = source_file("main.sy", "const int K = 1;\nint main() { return twice(3); }\n");
+    let output = xenon(&["--emit-ir", main.to_str().unwrap(), util.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let ir = String::from_utf8_lossy(&output.stdout);
+    assert!(ir.contains("call @twice(3)") && ir.contains("fun @helper(@x: i32): i32"));
+
+    let duplicate = source_file("duplicate.sy", "int twice(int y) { return y; }\n");
+    let output = xenon(&[main.to_str().unwrap(), util.to_str().unwrap(), duplicate.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with(duplicate.to_str().unwrap()));
+    assert!(stderr.contains(&format!(
+        "全局标识符 twice 在 {} 与 {} 中重复定义",
+        util.to_str().unwrap(),
+        duplicate.to_str().unwrap()
+    )));
+}
+
+#[test]
+fn format_source() {
+    let file = source_file("format.sy", "// 注释\nint main(){int a=1;return a+1;}\n");
+    let output = xenon(&["fmt", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "// 注释\n\nint main() {\n    int a = 1;\n    return a + 1;\n}\n"
+    );
+    let output = xenon_stdin(&["--format", "-"], b"int main(){return 0;}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "int main() {\n    return 0;\n}\n");
+}
+
+#[test]
+fn run_program() {
+    let file = source_file("run.sy", "int main() { int n = getint(); putint(n * 2); return n + 256; }\n");
+    let output = xenon_stdin(&["--run", file.to_str().unwrap()], b"21\n");
+    assert_eq!(output.status.code(), Some(21));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42");
+
+    let file = source_file("crash.sy", "int main() { int a[2]; return a[getint()]; }\n");
+    let output = xenon_stdin(&["--run", file.to_str().unwrap()], b"2");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("函数 main 运行时出错: 数组 a 的第 1 维下标 2 越界"));
+
+    let file = source_file("divide.sy", "int f(int x) { return 1 / x; }
+int main() {
+    return f(0);
+}
+");
+    let output = xenon(&["--run", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let expected = format!("函数 f（由 {} 第 3 行的 main 调用）运行时出错: 除以 0", file.to_str().unwrap());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&expected));
+}
+
+#[test]
+fn test_suite() {
+    let dir = std::env::temp_dir().join(format!("xenon-cli-{}-suite", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    write(dir.join("00_pass.sy"), "int main() { putint(getint() + 1); return 3; }").unwrap();
+    write(dir.join("00_pass.in"), "41").unwrap();
+    write(dir.join("00_pass.out"), "42\n3\n").unwrap();
+    write(dir.join("01_fail.sy"), "int main() { return 1; }").unwrap();
+    write(dir.join("01_fail.out"), "0\n").unwrap();
+    let output = xenon(&["test", dir.to_str().unwrap(), "-j", "2"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("00_pass ... 通过\n01_fail ... 失败\n  第 1 行不同"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.ends_with("1/2 个用例通过\n"));
+    assert_eq!(xenon(&["test", dir.to_str().unwrap(), "-j", "x"]).status.code(), Some(2));
+}
+
+#[test]
+fn explain_code() {
+    let output = xenon(&["--explain", "w001"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("W001: 局部变量定义后从未使用\n\n"));
+    let output = xenon(&["--explain", "E999"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("E999"));
+    assert_eq!(xenon(&["--explain"]).status.code(), Some(2));
+}
+
+#[test]
+fn trace_to_stderr() {
+    let input = source_file("trace.sy", "const int a = 1 + 2;\nint main() { return a; }\n");
+    let output = xenon(&["--trace=const-eval,checker", "--emit-ir", input.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("ret 3"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("const-eval: 1 + 2 => 3\n"), "{}", stderr);
+    assert!(stderr.contains("checker: a 解析为全局作用域中的 const int 3\n"), "{}", stderr);
+    assert_eq!(xenon(&["--trace=parser", input.to_str().unwrap()]).status.code(), Some(2));
+    // --explain 只接受错误或警告编号
+    assert_eq!(xenon(&["--explain=checker", input.to_str().unwrap()]).status.code(), Some(2));
+}
+
+#[test]
+fn missing_input_file() {
+    let output = xenon(&["/nonexistent/missing.sy", "--emit-ir"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("/nonexistent/missing.sy: 错误: "));
+    assert_eq!(xenon(&["test", "/nonexistent"]).status.code(), Some(2));
+}
+
+#[test]
+fn artifact_goes_only_to_output_file() {
+    let input = source_file("shadow.sy", "int main() { int x = 0; { int x = 1; } return x; }");
+    let path = std::env::temp_dir().join(format!("xenon-cli-{}-shadow.koopa", std::process::id()));
+    let output = xenon(&[input.to_str().unwrap(), "--emit-ir", "-o", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("警告: [W002] 标识符 x 遮蔽了外层作用域中的同名定义"));
+    assert!(std::fs::read_to_string(path).unwrap().contains("fun @main(): i32"));
+}
+
+#[test]
+fn internal_compiler_error() {
+    let output = xenon(&["--force-internal-error"]);
+    assert_eq!(output.status.code(), Some(101));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("xenon: 内部错误: "));
+}
+
+#[test]
+fn verify_incremental() {
+    let file = source_file(
+        "incremental.sy",
+        "const int N = 2;\nint f(int x) {\n    int N = x; /* 注释 */\n    return N;\n}\nint main() { return f(N); }\n",
+    );
+    let output = xenon(&["--verify-incremental", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.ends_with("17..75 警告: [W002] 标识符 N 遮蔽了外层作用域中的同名定义\n"),
+        "{}",
+        stdout
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn emitted_runtime_compiles() {
+    let dir = std::env::temp_dir().join(format!("xenon-cli-{}-runtime", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let runtime = dir.join("sysy.c");
+    let output = xenon(&["--emit-runtime=c", "-o", runtime.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let driver = dir.join("main.c");
+    write(
+        &driver,
+        "int getint(void), getch(void), getarray(int[]);\n\
+         void putint(int), putch(int), putarray(int, int[]), putf(char[], ...);\n\
+         void _sysy_starttime(int), _sysy_stoptime(int);\n\
+         int main(void) {\n\
+           int a[4], n = getarray(a);\n\
+           putarray(n, a); putint(getint()); putch(getch()); putf(\"%d-%d\\n\", 1, 2);\n\
+           _sysy_starttime(3); _sysy_stoptime(5); _sysy_starttime(12); _sysy_stoptime(1234);\n\
+           return 0;\n\
+         }\n",
+    )
+    .unwrap();
+    let program = dir.join("main");
+    let cc = std::env::var("CC").unwrap_or("cc".to_string());
+    let status = Command::new(&cc)
+        .args([runtime.as_os_str(), driver.as_os_str(), "-o".as_ref(), program.as_os_str()])
+        .status();
+    let Ok(status) = status else {
+        eprintln!("找不到 C 编译器 {}，跳过", cc);
+        return;
+    };
+    assert!(status.success());
+    let mut child = Command::new(&program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"3 1 2 3\n7x").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3: 1 2 3\n7x1-2\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", stderr);
+    assert!(lines[0].starts_with("Timer@0003-0005: 0H-0M-0S-") && lines[0].ends_with("us"));
+    assert!(lines[1].starts_with("Timer@0012-1234: 0H-0M-0S-"));
+    assert!(lines[2].starts_with("TOTAL: 0H-0M-0S-"));
+}
+
+#[test]
+fn doc_html() {
+    let input = source_file(
+        "doc.sy",
+        "const int N = 3;\n/// 把 a 清零。\n/// 长度为 n。\nvoid clear(int a[][N], int n) {}\nint main() { return 0; }\n",
+    );
+    let output = xenon(&["doc", input.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    let html = String::from_utf8_lossy(&output.stdout);
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<h2><code>void clear(int a[][3], int n)</code></h2>\n<p>把 a 清零。\n长度为 n。</p>"));
+    assert!(html.contains("<h2><code>int main()</code></h2>\n<p class=\"undocumented\">"));
+    assert_eq!(
+        xenon(&[
+            "doc",
+            &source_file("doc-error.sy", "int main() { return x; }").to_string_lossy()
+        ])
+        .status
+        .code(),
+        Some(1)
+    );
+}
+
+#[test]
+fn coverage_report() {
+    let input = source_file(
+        "coverage.sy",
+        "int main() {\n  int i = 0;\n  while (i < 2) {\n    i = i + 1;\n  }\n  if (i > 5) {\n    putint(i);\n  }\n  return i;\n}\n",
+    );
+    let input = input.to_str().unwrap();
+    let run = xenon(&["--coverage", "--run", input]);
+    assert_eq!(run.status.code(), Some(2));
+    assert!(run.stdout.is_empty());
+    let counts = source_file("coverage.counts", &String::from_utf8_lossy(&run.stderr));
+    let report = xenon(&["cov-report", counts.to_str().unwrap(), input]);
+    assert_eq!(report.status.code(), Some(0));
+    let report = String::from_utf8_lossy(&report.stdout);
+    assert!(report.contains("        2:    4:    i = i + 1;"), "{}", report);
+    assert!(report.contains("    #####:    7:    putint(i);"), "{}", report);
+    assert!(report.contains("语句 4/5 (80.0%)"), "{}", report);
+    assert_eq!(xenon(&["--coverage", "--emit-ast", input]).status.code(), Some(2));
+}
+
+#[test]
+fn profile_generate() {
+    let input = source_file(
+        "profile.sy",
+        "int main() {\n  int i = 0;\n  while (i < 4) {\n    i = i + 1;\n  }\n  putint(i);\n  return 0;\n}\n",
+    );
+    let input = input.to_str().unwrap();
+    let run = xenon(&["--profile-generate", "--run", input]);
+    assert_eq!(run.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "4");
+    assert_eq!(
+        String::from_utf8_lossy(&run.stderr),
+        "xenon-profile: 0 1\nxenon-profile: 1 4\n"
+    );
+    let map = std::fs::read_to_string(std::path::Path::new(input).with_extension("profmap")).unwrap();
+    assert!(map.ends_with("0 main\n1 main:while@3\n"), "{}", map);
+    assert_eq!(xenon(&["--coverage", "--profile-generate", input]).status.code(), Some(2));
+}
+
+#[test]
+fn check_bounds() {
+    let input = source_file(
+        "bounds.sy",
+        "int main() {\n  int a[3] = {1, 2, 3}, i = 0;\n  while (i <= 3) {\n    putint(a[i]);\n    i = i + 1;\n  }\n  return 0;\n}\n",
+    );
+    let input = input.to_str().unwrap();
+    let run = xenon(&["--check-bounds", "--run", input]);
+    assert_eq!(run.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "123");
+    assert!(String::from_utf8_lossy(&run.stderr).contains("第 4 行: 下标 3 越界，长度为 3"));
+    let ir = xenon(&["--check-bounds", "--emit-ir", input]);
+    assert!(String::from_utf8_lossy(&ir.stdout).contains("call @_sysy_check_bound"));
+    assert_eq!(xenon(&["--check-bounds", "--emit-ast", input]).status.code(), Some(2));
+}
+
+#[test]
+fn sanitize_overflow() {
+    let input = source_file(
+        "overflow.sy",
+        "int main() {\n  int x = 1, i = 0;\n  while (i < 32) {\n    x = x * 2;\n    i = i + 1;\n  }\n  return x;\n}\n",
+    );
+    let input = input.to_str().unwrap();
+    let run = xenon(&["--sanitize=overflow", "--run", input]);
+    assert_eq!(run.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&run.stderr).contains("第 4 行: 1073741824 * 2 溢出"));
+    assert_eq!(xenon(&["--run", input]).status.code(), Some(0));
+    let ir = xenon(&["--sanitize=overflow", "--check-bounds", "--emit-ir", input]);
+    assert!(String::from_utf8_lossy(&ir.stdout).contains("call @_sysy_check_overflow"));
+    assert_eq!(xenon(&["--sanitize=address", input]).status.code(), Some(2));
+    assert_eq!(xenon(&["--sanitize=overflow", "--emit-ast", input]).status.code(), Some(2));
+}
+
+#[test]
+fn deeply_nested_source() {
+    // 递归的遍历在大栈的线程中进行，深层嵌套的源代码不会使进程因栈溢出而终止
+    let terms = vec!["x"; 100000].join(" + ");
+    let chain = source_file("chain.sy", &format!("int main() {{ int x = 1; return {}; }}", terms));
+    let parens = source_file(
+        "parens.sy",
+        &format!("int main() {{ return {}1{}; }}", "(".repeat(10000), ")".repeat(10000)),
+    );
+    let output = std::env::temp_dir().join(format!("xenon-cli-{}-nested.out", std::process::id()));
+    for (input, args) in [
+        (&chain, vec!["--emit-ir"]),
+        (&chain, vec!["--emit-ast"]),
+        (&chain, vec!["--stats"]),
+        (&parens, vec!["symbols"]),
+        (&parens, vec!["--emit-ir"]),
+    ] {
+        let mut args = args.clone();
+        args.extend([input.to_str().unwrap(), "-o", output.to_str().unwrap()]);
+        let result = xenon(&args);
+        assert_eq!(
+            result.status.code(),
+            Some(0),
+            "{:?}: {}",
+            args,
+            String::from_utf8_lossy(&result.stderr)
+        );
+    }
+    let _ = std::fs::remove_file(output);
+    assert_eq!(xenon(&["--run", chain.to_str().unwrap()]).status.code(), Some(100000 & 0xff));
+}
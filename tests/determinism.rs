@@ -0,0 +1,130 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! 同一输入的每种输出在多次编译之间必须逐字节相同。
+//! 标准库的哈希表每次创建都使用不同的随机种子，进程内重复编译与在不同进程中编译都能暴露对遍历顺序的依赖。
+
+use std::process::Command;
+use xenon::arg_parse::{Emit, Options};
+use xenon::Source;
+
+const PROGRAM: &str = "\
+// 覆盖常量、数组、指针参数、控制流与运行时库
+const int N = 4, M = N * 2;
+const int table[2][N] = {{1, 2}, {3, 4, 5, 6}};
+int g[M], h = table[1][2], count;
+
+int sum(int a[], int n) {
+    int i = 0, s = 0;
+    while (i < n) {
+        s = s + a[i];
+        i = i + 1;
+    }
+    return s;
+}
+
+void fill(int a[][N], int v) {
+    int i = 0;
+    while (i < 2) {
+        int j = 0;
+        while (j < N) {
+            a[i][j] = v + i * N + j;
+            j = j + 1;
+            if (j == 3) continue;
+        }
+        i = i + 1;
+    }
+}
+
+int main() {
+    int grid[2][N];
+    int x = getint(), y, z = 0;
+    fill(grid, x);
+    y = sum(grid[1], N);
+    if (x > 0 && y != 0 || !z) {
+        z += -y % 7;
+    } else if (x == 0) {
+        goto done;
+    }
+    {
+        int x = z << 2;
+        putint(x);
+    }
+    count++;
+    putch(10);
+done:
+    putarray(M, g);
+    return (h, z);
+}
+";
+
+const EMITS: [Emit; 5] = [Emit::Tokens, Emit::Ast, Emit::Ir, Emit::Symbols, Emit::Format];
+
+fn options(emit: Emit) -> Options {
+    Options {
+        emit,
+        ..Options::default()
+    }
+}
+
+fn compile(code: &str, emit: Emit) -> Result<String, Vec<(usize, String)>> {
+    let sources = [Source {
+        name: "determinism.sy".to_string(),
+        code: code.to_string(),
+    }];
+    xenon::compile(&sources, &options(emit)).map(|artifacts| artifacts.output)
+}
+
+#[test]
+fn repeated_compilation_in_process() {
+    for emit in EMITS {
+        let first = compile(PROGRAM, emit).unwrap();
+        assert!(!first.is_empty());
+        for _ in 0..8 {
+            assert_eq!(compile(PROGRAM, emit).unwrap(), first);
+        }
+    }
+}
+
+#[test]
+fn repeated_errors_in_process() {
+    let code = "int main() { goto e; goto c; goto d; goto a; goto b; return 0; }";
+    let first = compile(code, Emit::Ir).unwrap_err();
+    for _ in 0..8 {
+        assert_eq!(compile(code, Emit::Ir).unwrap_err(), first);
+    }
+}
+
+#[test]
+fn repeated_compilation_across_processes() {
+    let input = std::env::temp_dir().join(format!("xenon-determinism-{}.sy", std::process::id()));
+    std::fs::write(&input, PROGRAM).unwrap();
+    for flag in ["--emit-tokens", "--emit-ast", "--emit-ir", "--format"] {
+        let outputs: Vec<_> = (0..3)
+            .map(|_| {
+                let output = Command::new(env!("CARGO_BIN_EXE_xenon"))
+                    .arg(&input)
+                    .arg(flag)
+                    .output()
+                    .unwrap();
+                assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+                output.stdout
+            })
+            .collect();
+        assert!(outputs.windows(2).all(|pair| pair[0] == pair[1]), "{}", flag);
+    }
+}
@@ -0,0 +1,200 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! 用固定种子生成随机输入，检查前端不会 panic。
+//! 设置环境变量 XENON_FUZZ_ROUNDS 可以成倍增加迭代次数，XENON_FUZZ_SEED 可以换一组种子，用于长时间运行。
+
+use xenon::check_no_panic;
+
+const TOKENS: [&str; 72] = [
+    "int",
+    "const",
+    "void",
+    "return",
+    "continue",
+    "break",
+    "goto",
+    "if",
+    "else",
+    "while",
+    "main",
+    "a",
+    "b",
+    "f",
+    "getint",
+    "putint",
+    "starttime",
+    "0",
+    "1",
+    "07",
+    "0x1F",
+    "0b1",
+    "2147483647",
+    "2147483648",
+    "99999999999999999999",
+    "(",
+    ")",
+    "[",
+    "]",
+    "{",
+    "}",
+    ",",
+    ";",
+    ":",
+    "=",
+    "+=",
+    "-=",
+    "*=",
+    "/=",
+    "%=",
+    "&=",
+    "|=",
+    "^=",
+    "<<=",
+    ">>=",
+    "&&",
+    "||",
+    "<<",
+    ">>",
+    "==",
+    "!=",
+    ">=",
+    "<=",
+    "++",
+    "--",
+    "*",
+    "/",
+    "%",
+    "+",
+    "-",
+    "^",
+    "&",
+    "|",
+    ">",
+    "<",
+    "!",
+    "~",
+    "`f`",
+    "/*",
+    "*/",
+    "//",
+    "\n",
+];
+
+// 固定种子的 xorshift，保证失败可以复现
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+fn rounds() -> usize {
+    std::env::var("XENON_FUZZ_ROUNDS").map_or(1, |rounds| rounds.parse().unwrap())
+}
+
+fn rng(seed: u64) -> Rng {
+    let offset: u64 = std::env::var("XENON_FUZZ_SEED").map_or(0, |seed| seed.parse().unwrap());
+    Rng(seed ^ offset.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+}
+
+fn compile_all(code: String) {
+    if let Err(message) = check_no_panic(&code) {
+        panic!("输入 {:?} 使编译器 panic: {}", code, message);
+    }
+}
+
+#[test]
+fn random_bytes() {
+    let mut rng = rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..500 * rounds() {
+        let bytes: Vec<u8> = (0..rng.below(64)).map(|_| rng.next() as u8).collect();
+        compile_all(String::from_utf8_lossy(&bytes).into_owned());
+    }
+}
+
+#[test]
+fn random_token_soups() {
+    let mut rng = rng(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..2000 * rounds() {
+        let tokens: Vec<_> = (0..rng.below(40)).map(|_| TOKENS[rng.below(TOKENS.len())]).collect();
+        compile_all(tokens.join(" "));
+    }
+}
+
+const CORPUS: [&str; 4] = [
+    "const int N = 2; int g[N][2] = {{1}, {2, 3}}; int f(int x, int p[][2]) { return x + p[0][1]; }
+        int main() { int i = getint(); while (i < 10) { if (i == 3) break; i = i + 1; } starttime(); putint(f(i, g));
+        goto end; end: return (i, 0); }",
+    "const int a[2][3] = {1, 2, 3, {4}}; int b[4] = {a[0][1], 5}; void g(int p[]) { p[0] = p[1] << 2; }
+        int main() { int c[2][2] = {{b[0]}, 3}; g(c[1]); if (!c[0][0] || -b[1] % 3 != ~2) { return a[1][0]; } else if (b[0]) {} return 0; }",
+    "int x; int main() { int y = 1, z[3]; x += y++ * --z[2]; y /= (x, 3); y %= 2; y <<= 1; y >>= 1; y &= 7; y |= 8; y ^= 1;
+        while (y) { y--; if (y > 3) continue; } return x >= y && y <= x; }",
+    "const int M = 0x10, K = 010 + 0b11; int h(int n) { if (n < 2) return n; return h(n - 1) + h(n - 2); }
+        int main() { int v[M][K]; getarray(v[0]); putarray(K, v[1]); putch(h(getch())); stoptime(); return v[M - 1][K - 1]; }",
+];
+
+#[test]
+fn mutated_programs() {
+    let mut rng = rng(0xdead_beef_cafe_f00d);
+    for _ in 0..2000 * rounds() {
+        let mut mutated: Vec<_> = CORPUS[rng.below(CORPUS.len())].split_whitespace().collect();
+        for _ in 0..=rng.below(3) {
+            if mutated.is_empty() {
+                break;
+            }
+            let position = rng.below(mutated.len());
+            match rng.below(3) {
+                0 => mutated[position] = TOKENS[rng.below(TOKENS.len())],
+                1 => mutated.insert(position, TOKENS[rng.below(TOKENS.len())]),
+                _ => {
+                    mutated.remove(position);
+                }
+            }
+        }
+        compile_all(mutated.join(" "));
+    }
+}
+
+// 曾经使编译器 panic 的输入
+#[test]
+fn previously_panicking_inputs() {
+    for code in [
+        "int main() { int x = 4; x %= 3; x /= 2; return x; }",
+        "int main() { return 1 | 2; }",
+        "int main() { return 99999999999999999999; }",
+        "int main() { int x = 1; -x; (!x, -x); return 0; }",
+        "int main() { int x; -(x = 1); +x; return 0; }",
+        "int n; int f(int a[][n]) { return 0; } int main() { return 0; }",
+        "int f(int a[][1 / 0]) { return 0; } int main() { return 0; }",
+        "int a[2][2] = {{{1}}}; int main() { return 0; }",
+        "int a[2] = {1, 2, 3}; int main() { return 0; }",
+        "const int a[2][2] = {1, {2}}; int main() { return 0; }",
+        "int main() { int a[0]; return 0; }",
+        "int main() { int a[-1][2] = {}; return 0; }",
+    ] {
+        compile_all(code.to_string());
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright (C) 2024 Elkeid-me
+//
+// This file is part of Xenon.
+//
+// Xenon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Xenon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Xenon.  If not, see <http://www.gnu.org/licenses/>.
+
+//! SysY 2021 一致性测试: 对目录中的每个程序做词法分析、语法分析和检查。
+//! 含有 `# ERROR: <信息>` 注释的程序必须报错，且错误信息包含该信息；其余程序必须没有错误。
+//! 设置环境变量 XENON_SYSY_2021 为官方测试用例的目录（如 functional）时，同时检查其中的全部程序。
+
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+use xenon::arg_parse::{Emit, Instrument, Options};
+use xenon::Source;
+
+fn programs(dir: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for entry in read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            found.extend(programs(&path));
+        } else if path.extension().is_some_and(|extension| extension == "sy") {
+            found.push(path);
+        }
+    }
+    found.sort();
+    found
+}
+
+fn expected_error(code: &str) -> Option<&str> {
+    code.lines()
+        .find_map(|line| line.split_once("# ERROR:"))
+        .map(|(_, message)| message.trim())
+}
+
+/// 检查一个程序，不符合预期时返回原因。
+fn conform(path: &Path) -> Result<(), String> {
+    let code = read_to_string(path).map_err(|e| e.to_string())?;
+    let sources = [Source {
+        name: path.display().to_string(),
+        code: code.clone(),
+    }];
+    // --run 只做检查，不执行
+    let options = Options {
+        emit: Emit::Run,
+        ..Options::default()
+    };
+    match (xenon::compile(&sources, &options), expected_error(&code)) {
+        (Ok(_), None) => Ok(()),
+        (Ok(_), Some(expected)) => Err(format!("应当报错 {}，但没有错误", expected)),
+        (Err(errors), None) => Err(format!("不应报错，但报告了 {}", errors[0].1)),
+        (Err(errors), Some(expected)) if errors.iter().any(|(_, e)| e.contains(expected)) => Ok(()),
+        (Err(errors), Some(expected)) => Err(format!("应当报错 {}，但报告了 {}", expected, errors[0].1)),
+    }
+}
+
+fn conform_all(dir: &Path) -> usize {
+    let programs = programs(dir);
+    let failures: Vec<String> = programs
+        .iter()
+        .filter_map(|path| conform(path).err().map(|e| format!("{}: {}", path.display(), e)))
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "{} 个程序不符合预期:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+    programs.len()
+}
+
+#[test]
+fn bundled_programs() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sysy_2021");
+    let programs = programs(&dir);
+    let invalid = programs
+        .iter()
+        .filter(|path| expected_error(&read_to_string(path).unwrap()).is_some())
+        .count();
+    assert!(invalid > 0 && invalid < programs.len());
+    assert_eq!(conform_all(&dir), programs.len());
+}
+
+/// 检查的结果（折叠常量后的语法树、IR、符号表与警告）只取决于源代码。每个程序按不同的顺序检查两遍，
+/// 比较两遍的输出，检查之间不能通过共享的状态相互影响。
+#[test]
+fn check_is_idempotent() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sysy_2021");
+    let programs = programs(&dir);
+    assert!(programs.len() > 10);
+    let outcome = |sources: &[Source], emit: Emit| {
+        let options = Options {
+            emit,
+            ..Options::default()
+        };
+        xenon::compile(sources, &options).map(|artifacts| (artifacts.output, artifacts.warnings))
+    };
+    let emits = [Emit::Ast, Emit::Ir, Emit::Symbols];
+    for path in programs {
+        let sources = [Source {
+            name: path.display().to_string(),
+            code: read_to_string(&path).unwrap(),
+        }];
+        let first: Vec<_> = emits.iter().map(|&emit| outcome(&sources, emit)).collect();
+        let mut second: Vec<_> = emits.iter().rev().map(|&emit| outcome(&sources, emit)).collect();
+        second.reverse();
+        assert_eq!(first, second, "{}", path.display());
+    }
+}
+
+/// 以空输入解释执行，返回标准输出与返回值；出错时返回第一条错误。
+fn execute(
+    sources: &[Source],
+    (instrument, check_bounds, sanitize_overflow): (Instrument, bool, bool),
+) -> Result<(String, i32), String> {
+    let (mut output, mut counts) = (Vec::new(), Vec::new());
+    let options = Options {
+        instrument,
+        check_bounds,
+        sanitize_overflow,
+        ..Options::default()
+    };
+    let execution = xenon::run(sources, &options, &mut "".as_bytes(), &mut output, &mut counts);
+    match execution {
+        Ok(execution) => Ok((String::from_utf8(output).unwrap(), execution.return_value)),
+        Err(errors) => Err(errors[0].1.clone()),
+    }
+}
+
+#[test]
+fn instrumentation_preserves_behavior() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sysy_2021");
+    let programs: Vec<_> = programs(&dir)
+        .into_iter()
+        .filter(|path| expected_error(&read_to_string(path).unwrap()).is_none())
+        .collect();
+    let failures: Vec<String> = std::thread::Builder::new()
+        .stack_size(xenon::RUN_STACK_SIZE)
+        .spawn(move || {
+            programs
+                .iter()
+                .flat_map(|path| {
+                    let sources = [Source {
+                        name: path.display().to_string(),
+                        code: read_to_string(path).unwrap(),
+                    }];
+                    let plain = execute(&sources, (Instrument::None, false, false));
+                    // 合法的程序不越界、不溢出，运行时检查也不改变行为
+                    [
+                        (Instrument::Coverage, false, false),
+                        (Instrument::Profile, false, false),
+                        (Instrument::ProfileBlocks, false, false),
+                        (Instrument::None, true, false),
+                        (Instrument::None, false, true),
+                        (Instrument::Coverage, true, true),
+                    ]
+                    .into_iter()
+                    .filter_map(|instrumentation| {
+                        let instrumented = execute(&sources, instrumentation);
+                        (plain != instrumented)
+                            .then(|| format!("{}: {:?} 与插桩后的 {:?} 不同", path.display(), plain, instrumented))
+                    })
+                    .collect::<Vec<_>>()
+                })
+                .collect()
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn checks_keep_compile_errors() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sysy_2021");
+    let mut failures = Vec::new();
+    for path in programs(&dir) {
+        let code = read_to_string(&path).unwrap();
+        let Some(expected) = expected_error(&code).map(str::to_string) else {
+            continue;
+        };
+        let sources = [Source {
+            name: path.display().to_string(),
+            code,
+        }];
+        // 运行时检查不能让默认模式下报错的程序通过编译
+        for instrumentation in [
+            (Instrument::None, true, false),
+            (Instrument::None, false, true),
+            (Instrument::Coverage, true, true),
+        ] {
+            match execute(&sources, instrumentation) {
+                Err(e) if e.contains(&expected) => (),
+                result => failures.push(format!(
+                    "{}: 检查下标 {}、检查溢出 {} 时应当报错 {}，实际为 {:?}",
+                    path.display(),
+                    instrumentation.1,
+                    instrumentation.2,
+                    expected,
+                    result
+                )),
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn official_suite() {
+    if let Ok(dir) = std::env::var("XENON_SYSY_2021") {
+        assert!(conform_all(Path::new(&dir)) > 0, "{} 中没有 .sy 文件", dir);
+    }
+}